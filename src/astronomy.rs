@@ -0,0 +1,222 @@
+//! Offline calculation of sun and moon positions, so that day/night and moon phase don't depend
+//! on what (if anything) a weather provider reports. The formulas are the usual low-precision
+//! approximations, good to within a minute or two for sun events and a few hours for the moon.
+
+use std::f64::consts::PI;
+
+/// Julian date of the J2000.0 epoch (2000-01-01 12:00 TT).
+const J2000: f64 = 2_451_545.;
+
+/// Julian date of the Unix epoch.
+const UNIX_EPOCH: f64 = 2_440_587.5;
+
+/// Julian date of a known new moon (2000-01-06 18:14 UTC).
+const KNOWN_NEW_MOON: f64 = 2_451_550.26;
+
+/// Mean length of a lunar cycle in days.
+const SYNODIC_MONTH: f64 = 29.530_588_853;
+
+/// Solar elevation at sunrise and sunset, accounting for refraction and the sun's radius.
+const SUNRISE_ELEVATION: f64 = -0.833;
+
+/// Solar elevation at the start of civil dawn and the end of civil dusk.
+const CIVIL_TWILIGHT_ELEVATION: f64 = -6.;
+
+pub struct SunEvents {
+    pub solar_noon: time::OffsetDateTime,
+    pub sunrise: Option<time::OffsetDateTime>,
+    pub sunset: Option<time::OffsetDateTime>,
+    pub civil_dawn: Option<time::OffsetDateTime>,
+    pub civil_dusk: Option<time::OffsetDateTime>,
+
+    /// Only meaningful if `sunrise` and `sunset` are `None`: true during the midnight sun, false
+    /// during the polar night.
+    sun_always_up: bool,
+}
+
+impl SunEvents {
+    /// Calculate the sun events for the given calendar date at a latitude and longitude (in
+    /// degrees, with north and east positive). All times are returned in UTC.
+    pub fn new(date: time::Date, latitude: f64, longitude: f64) -> Self {
+        let mean_solar_noon = (date.julian_day() as f64 - J2000) - longitude / 360.;
+        let solar_anomaly = (357.5291 + 0.985_600_28 * mean_solar_noon).rem_euclid(360.);
+        let center = 1.9148 * sin(solar_anomaly)
+            + 0.02 * sin(2. * solar_anomaly)
+            + 0.0003 * sin(3. * solar_anomaly);
+        let ecliptic_longitude = (solar_anomaly + center + 180. + 102.9372).rem_euclid(360.);
+        let transit = J2000 + mean_solar_noon + 0.0053 * sin(solar_anomaly)
+            - 0.0069 * sin(2. * ecliptic_longitude);
+        let declination = (sin(ecliptic_longitude) * sin(23.44)).asin().to_degrees();
+
+        // The cosine of the hour angle at which the sun crosses the given elevation, which is
+        // outside of -1..1 if it never does so on this day.
+        let cos_hour_angle = |elevation: f64| {
+            (sin(elevation) - sin(latitude) * sin(declination)) / (cos(latitude) * cos(declination))
+        };
+
+        let crossings = |elevation: f64| {
+            let cos_hour_angle = cos_hour_angle(elevation);
+
+            if (-1.0..=1.).contains(&cos_hour_angle) {
+                let hour_angle = cos_hour_angle.acos().to_degrees();
+                (
+                    Some(from_julian_date(transit - hour_angle / 360.)),
+                    Some(from_julian_date(transit + hour_angle / 360.)),
+                )
+            } else {
+                (None, None)
+            }
+        };
+
+        let (sunrise, sunset) = crossings(SUNRISE_ELEVATION);
+        let (civil_dawn, civil_dusk) = crossings(CIVIL_TWILIGHT_ELEVATION);
+
+        Self {
+            solar_noon: from_julian_date(transit),
+            sunrise,
+            sunset,
+            civil_dawn,
+            civil_dusk,
+            sun_always_up: cos_hour_angle(SUNRISE_ELEVATION) < -1.,
+        }
+    }
+
+    pub fn is_daytime(&self, time: time::OffsetDateTime) -> bool {
+        if let (Some(sunrise), Some(sunset)) = (self.sunrise, self.sunset) {
+            time > sunrise && time < sunset
+        } else {
+            self.sun_always_up
+        }
+    }
+}
+
+pub struct Moon {
+    /// Days since the last new moon.
+    pub age: f64,
+
+    /// Fraction of the moon's disc that is illuminated, from 0 to 1.
+    pub illumination: f64,
+}
+
+impl Moon {
+    pub fn at(time: time::OffsetDateTime) -> Self {
+        let age = (to_julian_date(time) - KNOWN_NEW_MOON).rem_euclid(SYNODIC_MONTH);
+
+        Self {
+            age,
+            illumination: (1. - (2. * PI * age / SYNODIC_MONTH).cos()) / 2.,
+        }
+    }
+
+    pub fn phase(&self) -> MoonPhase {
+        match (self.age / SYNODIC_MONTH * 8.).round() as u8 {
+            1 => MoonPhase::WaxingCrescent,
+            2 => MoonPhase::FirstQuarter,
+            3 => MoonPhase::WaxingGibbous,
+            4 => MoonPhase::Full,
+            5 => MoonPhase::WaningGibbous,
+            6 => MoonPhase::LastQuarter,
+            7 => MoonPhase::WaningCrescent,
+            _ => MoonPhase::New,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoonPhase {
+    New,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    Full,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+fn sin(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
+
+fn to_julian_date(time: time::OffsetDateTime) -> f64 {
+    time.unix_timestamp() as f64 / 86_400. + UNIX_EPOCH
+}
+
+fn from_julian_date(julian_date: f64) -> time::OffsetDateTime {
+    time::OffsetDateTime::from_unix_timestamp(((julian_date - UNIX_EPOCH) * 86_400.).round() as i64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utc(date: time::Date, time: time::Time) -> time::OffsetDateTime {
+        date.with_time(time).assume_utc()
+    }
+
+    fn assert_near(expected: time::OffsetDateTime, actual: Option<time::OffsetDateTime>) {
+        let actual = actual.expect("Missing event.");
+        assert!(
+            (expected - actual).whole_minutes().abs() <= 3,
+            "expected {}, got {}",
+            expected,
+            actual,
+        );
+    }
+
+    #[test]
+    fn sun_events_test() {
+        // Montreal on the summer solstice, per the NRC sunrise/sunset calculator.
+        let events = SunEvents::new(time::date!(2020 - 06 - 21), 45.5, -73.6);
+        assert_near(
+            utc(time::date!(2020 - 06 - 21), time::time!(9:05)),
+            events.sunrise,
+        );
+        assert_near(
+            utc(time::date!(2020 - 06 - 22), time::time!(0:47)),
+            events.sunset,
+        );
+        assert_near(
+            utc(time::date!(2020 - 06 - 21), time::time!(8:29)),
+            events.civil_dawn,
+        );
+        assert_near(
+            utc(time::date!(2020 - 06 - 22), time::time!(1:23)),
+            events.civil_dusk,
+        );
+
+        assert!(events.is_daytime(utc(time::date!(2020 - 06 - 21), time::time!(17:00))));
+        assert!(!events.is_daytime(utc(time::date!(2020 - 06 - 21), time::time!(6:00))));
+    }
+
+    #[test]
+    fn polar_test() {
+        let midnight_sun = SunEvents::new(time::date!(2020 - 06 - 21), 78.2, 15.6);
+        assert!(midnight_sun.sunrise.is_none());
+        assert!(midnight_sun.is_daytime(utc(time::date!(2020 - 06 - 21), time::time!(0:00))));
+
+        let polar_night = SunEvents::new(time::date!(2020 - 12 - 21), 78.2, 15.6);
+        assert!(polar_night.sunset.is_none());
+        assert!(!polar_night.is_daytime(utc(time::date!(2020 - 12 - 21), time::time!(12:00))));
+    }
+
+    #[test]
+    fn moon_test() {
+        let full = Moon::at(utc(time::date!(2020 - 12 - 30), time::time!(3:28)));
+        assert!(full.illumination > 0.99);
+        assert_eq!(MoonPhase::Full, full.phase());
+
+        let new = Moon::at(utc(time::date!(2020 - 12 - 14), time::time!(16:17)));
+        assert!(new.illumination < 0.01);
+        assert_eq!(MoonPhase::New, new.phase());
+
+        assert_eq!(
+            MoonPhase::FirstQuarter,
+            Moon::at(utc(time::date!(2020 - 12 - 21), time::time!(23:41))).phase()
+        );
+    }
+}
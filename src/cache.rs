@@ -0,0 +1,115 @@
+//! Persistence of the last successfully fetched data, so the display can fall back to it when the
+//! network is unavailable.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Use the directory given by the `STATE_DIRECTORY` environment variable (as set by systemd's
+    /// `StateDirectory=`), or `state` in the working directory.
+    pub fn from_env() -> Self {
+        Self::new(env::var("STATE_DIRECTORY").unwrap_or_else(|_| "state".to_string()))
+    }
+
+    /// Store a value along with the time it was fetched, replacing any previous value. The time
+    /// goes on the first line of the same file, so that the two are always replaced together.
+    pub fn store(
+        &self,
+        name: &str,
+        data: &[u8],
+        fetched_at: time::OffsetDateTime,
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let mut contents = format!("{}\n", fetched_at.unix_timestamp()).into_bytes();
+        contents.extend_from_slice(data);
+
+        // Write to a temporary file first so a power cut can't leave a half-written cache.
+        let temp_path = self.dir.join(format!("{}.tmp", name));
+        fs::write(&temp_path, contents)?;
+        fs::rename(&temp_path, self.dir.join(name))
+    }
+
+    /// Load the last stored value. The result is marked as stale.
+    pub fn load(&self, name: &str) -> io::Result<Fetched<Vec<u8>>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid fetch timestamp.");
+
+        let mut contents = fs::read(self.dir.join(name))?;
+        let newline = contents
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(invalid)?;
+        let fetched_at = std::str::from_utf8(&contents[..newline])
+            .ok()
+            .and_then(|timestamp| timestamp.parse().ok())
+            .ok_or_else(invalid)?;
+
+        Ok(Fetched {
+            value: contents.split_off(newline + 1),
+            fetched_at: time::OffsetDateTime::from_unix_timestamp(fetched_at),
+            stale: true,
+        })
+    }
+}
+
+/// A value along with when it was fetched, and whether it was loaded from the cache because a
+/// fresh copy couldn't be fetched.
+pub struct Fetched<T> {
+    pub value: T,
+    pub fetched_at: time::OffsetDateTime,
+    pub stale: bool,
+}
+
+impl<T> Fetched<T> {
+    pub fn fresh(value: T) -> Self {
+        Self {
+            value,
+            fetched_at: time::OffsetDateTime::now_utc(),
+            stale: false,
+        }
+    }
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Fetched<U> {
+        Fetched {
+            value: f(self.value),
+            fetched_at: self.fetched_at,
+            stale: self.stale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn store_load_test() {
+        let dir = env::temp_dir().join(format!("weathervane-cache-test-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let fetched_at = time::OffsetDateTime::from_unix_timestamp(1_608_134_400);
+
+        assert!(cache.load("radar.gif").is_err());
+
+        cache.store("radar.gif", b"GIF89a", fetched_at).unwrap();
+        let cached = cache.load("radar.gif").unwrap();
+        assert_eq!(b"GIF89a".to_vec(), cached.value);
+        assert_eq!(fetched_at, cached.fetched_at);
+        assert!(cached.stale);
+
+        // A file from before the timestamp was stored with the data isn't trusted.
+        fs::write(dir.join("radar.gif"), b"GIF89a").unwrap();
+        assert!(cache.load("radar.gif").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
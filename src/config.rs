@@ -0,0 +1,9 @@
+//! Helpers for reading configuration from environment variables.
+
+use std::env;
+use std::str::FromStr;
+
+/// Parse an environment variable, treating a missing or malformed value as unset.
+pub fn parse_env<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|s| s.parse().ok())
+}
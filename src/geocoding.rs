@@ -0,0 +1,126 @@
+//! Resolve place names and postal codes to coordinates using the Open-Meteo geocoding API.
+
+use serde::{Deserialize, Serialize};
+
+use crate::http;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Place {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+
+    /// IANA time zone name, eg. `America/Toronto`.
+    pub timezone: Option<String>,
+}
+
+/// Look up the best match for a place name, eg. "Mont-Tremblant", or a Canadian or US postal code.
+pub async fn search(client: &http::Client, query: &str) -> Result<Place, String> {
+    let (name, country_code) = match postal_code(query) {
+        Some((code, country_code)) => (code, Some(country_code)),
+        None => (query.trim().to_string(), None),
+    };
+
+    let mut url = reqwest::Url::parse("https://geocoding-api.open-meteo.com/v1/search").unwrap();
+    url.query_pairs_mut()
+        .append_pair("name", &name)
+        .append_pair("count", "1")
+        .append_pair("format", "json");
+    if let Some(country_code) = country_code {
+        url.query_pairs_mut()
+            .append_pair("countryCode", country_code);
+    }
+
+    decode(&client.get_text(url.as_str()).await?)?
+        .ok_or_else(|| format!("No match for \"{}\".", query))
+}
+
+/// ```json
+/// {
+///     "results": [{
+///         "id": 6077243,
+///         "name": "Montréal",
+///         "latitude": 45.50884,
+///         "longitude": -73.58781,
+///         "country_code": "CA",
+///         "timezone": "America/Toronto",
+///         "admin1": "Quebec"
+///     }],
+///     "generationtime_ms": 0.7
+/// }
+/// ```
+pub fn decode(body: &str) -> Result<Option<Place>, String> {
+    #[derive(Deserialize)]
+    struct SearchResponse {
+        // Omitted entirely when there are no matches.
+        #[serde(default)]
+        results: Vec<Place>,
+    }
+
+    let response: SearchResponse = serde_json::from_str(body).map_err(|e| e.to_string())?;
+
+    Ok(response.results.into_iter().next())
+}
+
+/// Recognize a Canadian postal code or US ZIP code, returning the code in the form the geocoder
+/// knows it along with its country code. Canadian postal codes are reduced to their forward
+/// sortation area (the first three characters), and ZIP+4 codes to the five-digit ZIP code.
+fn postal_code(query: &str) -> Option<(String, &'static str)> {
+    let compact: String = query
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_uppercase();
+    let chars: Vec<char> = compact.chars().collect();
+
+    let is_canadian = (chars.len() == 3 || chars.len() == 6)
+        && chars.iter().enumerate().all(|(i, c)| {
+            if i % 2 == 0 {
+                c.is_ascii_alphabetic()
+            } else {
+                c.is_ascii_digit()
+            }
+        });
+
+    if is_canadian {
+        Some((compact[..3].to_string(), "CA"))
+    } else if (chars.len() == 5 || chars.len() == 9) && chars.iter().all(char::is_ascii_digit) {
+        Some((compact[..5].to_string(), "US"))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn postal_code_test() {
+        assert_eq!(Some(("H2X".to_string(), "CA")), postal_code("H2X 1Y4"));
+        assert_eq!(Some(("H2X".to_string(), "CA")), postal_code("h2x1y4"));
+        assert_eq!(Some(("K1A".to_string(), "CA")), postal_code("K1A"));
+        assert_eq!(Some(("05401".to_string(), "US")), postal_code("05401"));
+        assert_eq!(Some(("05401".to_string(), "US")), postal_code("05401-2345"));
+
+        assert_eq!(None, postal_code("Montréal"));
+        assert_eq!(None, postal_code("Mont-Tremblant"));
+        assert_eq!(None, postal_code("1234"));
+    }
+
+    #[test]
+    fn decode_test() {
+        assert_eq!(
+            Some(Place {
+                name: "Montréal".to_string(),
+                latitude: 45.50884,
+                longitude: -73.58781,
+                timezone: Some("America/Toronto".to_string()),
+            }),
+            decode(include_str!("../tests/fixtures/open_meteo/geocoding.json")).unwrap(),
+        );
+
+        assert_eq!(None, decode(r#"{"generationtime_ms": 0.5}"#).unwrap());
+        assert!(decode("{").is_err());
+    }
+}
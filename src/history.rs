@@ -0,0 +1,497 @@
+//! A local SQLite log of every observation and forecast fetched, for comparisons with the past and
+//! for working out after the fact what the display showed.
+
+use std::env;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension, Row, NO_PARAMS};
+
+use crate::cache::Fetched;
+use crate::config::parse_env;
+use crate::location::Location;
+use crate::weather::{
+    Precipitation, Pressure, Temperature, Weather, WeatherCondition, WeatherReport, WeatherState,
+    Wind,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS weather_states (
+        id INTEGER PRIMARY KEY,
+        location TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        fetched_at INTEGER NOT NULL,
+        time INTEGER NOT NULL,
+        utc_offset INTEGER NOT NULL,
+        sunrise INTEGER,
+        sunset INTEGER,
+        temp REAL,
+        temp_min REAL,
+        temp_max REAL,
+        humidity INTEGER,
+        wind_speed REAL,
+        wind_direction INTEGER,
+        wind_gust REAL,
+        pressure REAL,
+        precipitation REAL,
+        clouds INTEGER,
+        condition INTEGER,
+        provider TEXT NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS weather_states_by_time
+        ON weather_states (location, kind, time);
+
+    CREATE INDEX IF NOT EXISTS weather_states_by_fetched_at
+        ON weather_states (location, kind, fetched_at);
+
+    -- The same observation is returned until the provider updates it, so only keep it once.
+    CREATE UNIQUE INDEX IF NOT EXISTS weather_states_observation
+        ON weather_states (location, provider, time) WHERE kind = 'current';
+";
+
+const COLUMNS: &str = "time, utc_offset, sunrise, sunset, temp, temp_min, temp_max, humidity, \
+    wind_speed, wind_direction, wind_gust, pressure, precipitation, clouds, condition, provider";
+
+/// Which part of a report a state was recorded from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Current,
+    Hourly,
+    Daily,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Current => "current",
+            Self::Hourly => "hourly",
+            Self::Daily => "daily",
+        }
+    }
+}
+
+/// How long to keep history for.
+pub struct Retention {
+    pub observations: time::Duration,
+
+    /// Forecasts are only useful for debugging, so they're kept for much less time.
+    pub forecasts: time::Duration,
+}
+
+impl Retention {
+    /// Read retention periods in days from `HISTORY_RETENTION_DAYS` (default 365) and
+    /// `HISTORY_FORECAST_RETENTION_DAYS` (default 14).
+    pub fn from_env() -> Self {
+        Self {
+            observations: time::Duration::days(parse_env("HISTORY_RETENTION_DAYS").unwrap_or(365)),
+            forecasts: time::Duration::days(
+                parse_env("HISTORY_FORECAST_RETENTION_DAYS").unwrap_or(14),
+            ),
+        }
+    }
+}
+
+pub struct History {
+    connection: Connection,
+}
+
+impl History {
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    /// Use the database given by `HISTORY_DATABASE`, or `history.sqlite3` in `STATE_DIRECTORY`.
+    pub fn from_env() -> rusqlite::Result<Self> {
+        match env::var("HISTORY_DATABASE") {
+            Ok(path) => Self::open(path),
+            Err(_) => {
+                let dir = env::var("STATE_DIRECTORY").unwrap_or_else(|_| "state".to_string());
+                if let Err(e) = std::fs::create_dir_all(&dir) {
+                    eprintln!("Unable to create {}: {}", dir, e);
+                }
+                Self::open(Path::new(&dir).join("history.sqlite3"))
+            }
+        }
+    }
+
+    fn init(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// Record the freshly fetched reports for every location. Reports loaded from the cache were
+    /// recorded when they were first fetched, so they're skipped.
+    pub fn record_weather(&mut self, weather: &Weather) -> rusqlite::Result<()> {
+        for location in &weather.locations {
+            if let Some(report) = location.report.as_ref().filter(|report| !report.stale) {
+                self.record(&location.location, report)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record a report's current conditions and its hourly and daily forecasts.
+    pub fn record(
+        &mut self,
+        location: &Location,
+        report: &Fetched<WeatherReport>,
+    ) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+
+        {
+            let mut insert = transaction.prepare(&format!(
+                "INSERT OR IGNORE INTO weather_states (location, kind, fetched_at, {})
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                COLUMNS,
+            ))?;
+
+            let entries = std::iter::once((Kind::Current, &report.value.current))
+                .chain(
+                    report
+                        .value
+                        .hourly
+                        .iter()
+                        .map(|state| (Kind::Hourly, state)),
+                )
+                .chain(report.value.daily.iter().map(|state| (Kind::Daily, state)));
+
+            for (kind, state) in entries {
+                insert.execute(params![
+                    location.slug(),
+                    kind.as_str(),
+                    report.fetched_at.unix_timestamp(),
+                    state.time.unix_timestamp(),
+                    state.time.offset().as_seconds(),
+                    state.sunrise.map(|time| time.unix_timestamp()),
+                    state.sunset.map(|time| time.unix_timestamp()),
+                    state.temp.as_ref().map(|temp| f64::from(temp.kelvin())),
+                    state.temp_min.as_ref().map(|temp| f64::from(temp.kelvin())),
+                    state.temp_max.as_ref().map(|temp| f64::from(temp.kelvin())),
+                    state.humidity,
+                    state.wind.as_ref().map(|wind| f64::from(wind.speed)),
+                    state.wind.as_ref().and_then(|wind| wind.direction),
+                    state
+                        .wind
+                        .as_ref()
+                        .and_then(|wind| wind.gust)
+                        .map(f64::from),
+                    state.pressure.as_ref().map(|p| f64::from(p.hectopascals())),
+                    state
+                        .precipitation
+                        .as_ref()
+                        .map(|p| f64::from(p.millimetres())),
+                    state.clouds,
+                    state.condition.as_ref().map(WeatherCondition::code),
+                    state.provider.id(),
+                ])?;
+            }
+        }
+
+        transaction.commit()
+    }
+
+    /// Delete anything older than the retention periods.
+    pub fn prune(&self, retention: &Retention, now: time::OffsetDateTime) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "DELETE FROM weather_states WHERE kind = 'current' AND time < ?",
+            params![(now - retention.observations).unix_timestamp()],
+        )?;
+        self.connection.execute(
+            "DELETE FROM weather_states WHERE kind != 'current' AND fetched_at < ?",
+            params![(now - retention.forecasts).unix_timestamp()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Observed conditions between two times, oldest first.
+    pub fn observations(
+        &self,
+        location: &Location,
+        from: time::OffsetDateTime,
+        to: time::OffsetDateTime,
+    ) -> rusqlite::Result<Vec<WeatherState>> {
+        let mut query = self.connection.prepare(&format!(
+            "SELECT {} FROM weather_states
+                WHERE location = ? AND kind = 'current' AND time BETWEEN ? AND ?
+                ORDER BY time",
+            COLUMNS,
+        ))?;
+
+        let states = query
+            .query_map(
+                params![location.slug(), from.unix_timestamp(), to.unix_timestamp()],
+                weather_state,
+            )?
+            .collect();
+        states
+    }
+
+    /// The observation closest to a time, if there is one within `tolerance` of it. For example,
+    /// the observation a day before the current one to compare with yesterday.
+    pub fn observation_near(
+        &self,
+        location: &Location,
+        time: time::OffsetDateTime,
+        tolerance: time::Duration,
+    ) -> rusqlite::Result<Option<WeatherState>> {
+        self.connection
+            .query_row(
+                &format!(
+                    "SELECT {} FROM weather_states
+                        WHERE location = ? AND kind = 'current' AND time BETWEEN ? AND ?
+                        ORDER BY abs(time - ?)
+                        LIMIT 1",
+                    COLUMNS,
+                ),
+                params![
+                    location.slug(),
+                    (time - tolerance).unix_timestamp(),
+                    (time + tolerance).unix_timestamp(),
+                    time.unix_timestamp(),
+                ],
+                weather_state,
+            )
+            .optional()
+    }
+
+    /// The most recent forecast fetched at or before a time, along with when it was fetched.
+    pub fn forecast_at(
+        &self,
+        location: &Location,
+        kind: Kind,
+        at: time::OffsetDateTime,
+    ) -> rusqlite::Result<Option<(time::OffsetDateTime, Vec<WeatherState>)>> {
+        let fetched_at: Option<i64> = self.connection.query_row(
+            "SELECT max(fetched_at) FROM weather_states
+                WHERE location = ? AND kind = ? AND fetched_at <= ?",
+            params![location.slug(), kind.as_str(), at.unix_timestamp()],
+            |row| row.get(0),
+        )?;
+
+        let fetched_at = match fetched_at {
+            Some(fetched_at) => fetched_at,
+            None => return Ok(None),
+        };
+
+        let mut query = self.connection.prepare(&format!(
+            "SELECT {} FROM weather_states
+                WHERE location = ? AND kind = ? AND fetched_at = ?
+                ORDER BY time",
+            COLUMNS,
+        ))?;
+
+        let states = query
+            .query_map(
+                params![location.slug(), kind.as_str(), fetched_at],
+                weather_state,
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(Some((
+            time::OffsetDateTime::from_unix_timestamp(fetched_at),
+            states,
+        )))
+    }
+
+    /// The number of states recorded, for all locations.
+    pub fn count(&self) -> rusqlite::Result<usize> {
+        self.connection
+            .query_row("SELECT count(*) FROM weather_states", NO_PARAMS, |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as usize)
+    }
+}
+
+/// Read a row selected with `COLUMNS`.
+fn weather_state(row: &Row) -> rusqlite::Result<WeatherState> {
+    let offset = time::UtcOffset::seconds(row.get(1)?);
+    let time =
+        |timestamp: i64| time::OffsetDateTime::from_unix_timestamp(timestamp).to_offset(offset);
+    let provider: String = row.get(15)?;
+
+    Ok(WeatherState {
+        time: time(row.get(0)?),
+        sunrise: row.get::<_, Option<i64>>(2)?.map(time),
+        sunset: row.get::<_, Option<i64>>(3)?.map(time),
+        temp: row
+            .get::<_, Option<f64>>(4)?
+            .map(|k| Temperature::from_kelvin(k as f32)),
+        temp_min: row
+            .get::<_, Option<f64>>(5)?
+            .map(|k| Temperature::from_kelvin(k as f32)),
+        temp_max: row
+            .get::<_, Option<f64>>(6)?
+            .map(|k| Temperature::from_kelvin(k as f32)),
+        humidity: row.get(7)?,
+        wind: match row.get::<_, Option<f64>>(8)? {
+            Some(speed) => Some(Wind {
+                speed: speed as f32,
+                direction: row.get(9)?,
+                gust: row.get::<_, Option<f64>>(10)?.map(|gust| gust as f32),
+            }),
+            None => None,
+        },
+        pressure: row
+            .get::<_, Option<f64>>(11)?
+            .map(|p| Pressure::from_hectopascals(p as f32)),
+        precipitation: row
+            .get::<_, Option<f64>>(12)?
+            .map(|p| Precipitation::from_millimetres(p as f32)),
+        clouds: row.get(13)?,
+        visibility: None,
+        condition: row.get::<_, Option<u16>>(14)?.map(WeatherCondition::from),
+        provider: provider.parse().map_err(|_| {
+            rusqlite::Error::InvalidColumnType(
+                15,
+                "provider".to_string(),
+                rusqlite::types::Type::Text,
+            )
+        })?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::weather::{open_weather, DecodeMode};
+
+    fn home() -> Location {
+        Location {
+            name: "Home".to_string(),
+            latitude: 45.5,
+            longitude: -73.6,
+            timezone: None,
+        }
+    }
+
+    fn report(fetched_at: time::OffsetDateTime) -> Fetched<WeatherReport> {
+        Fetched {
+            value: open_weather::decode(
+                include_str!("../tests/fixtures/open_weather/onecall.json"),
+                DecodeMode::Strict,
+            )
+            .unwrap()
+            .value,
+            fetched_at,
+            stale: false,
+        }
+    }
+
+    #[test]
+    fn record_test() {
+        let mut history = History::open_in_memory().unwrap();
+        let fetched_at = time::OffsetDateTime::from_unix_timestamp(1_608_134_500);
+        let report = report(fetched_at);
+        let observed_at = report.value.current.time;
+
+        history.record(&home(), &report).unwrap();
+        assert_eq!(1 + 3 + 1, history.count().unwrap());
+
+        // Recording the same observation again only adds the new forecasts.
+        history
+            .record(
+                &home(),
+                &self::report(fetched_at + time::Duration::minutes(10)),
+            )
+            .unwrap();
+        assert_eq!(1 + (3 + 1) * 2, history.count().unwrap());
+
+        let observations = history
+            .observations(&home(), observed_at - time::Duration::hour(), observed_at)
+            .unwrap();
+        assert_eq!(1, observations.len());
+
+        let observation = &observations[0];
+        assert_eq!(observed_at, observation.time);
+        assert_eq!(observed_at.offset(), observation.time.offset());
+        assert_eq!(
+            report.value.current.temp.as_ref().unwrap().kelvin(),
+            observation.temp.as_ref().unwrap().kelvin()
+        );
+        assert_eq!(Some(96), observation.humidity);
+        assert_eq!(Some(320), observation.wind.as_ref().unwrap().direction);
+        assert_eq!(
+            Some(701),
+            observation.condition.as_ref().map(WeatherCondition::code)
+        );
+
+        assert!(history
+            .observation_near(
+                &home(),
+                observed_at + time::Duration::minutes(20),
+                time::Duration::hour()
+            )
+            .unwrap()
+            .is_some());
+        assert!(history
+            .observation_near(
+                &home(),
+                observed_at + time::Duration::day(),
+                time::Duration::hour()
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn forecast_at_test() {
+        let mut history = History::open_in_memory().unwrap();
+        let fetched_at = time::OffsetDateTime::from_unix_timestamp(1_608_134_500);
+
+        history.record(&home(), &report(fetched_at)).unwrap();
+        history
+            .record(&home(), &report(fetched_at + time::Duration::hour()))
+            .unwrap();
+
+        let (snapshot_fetched_at, hourly) = history
+            .forecast_at(
+                &home(),
+                Kind::Hourly,
+                fetched_at + time::Duration::minutes(30),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched_at, snapshot_fetched_at);
+        assert_eq!(3, hourly.len());
+
+        assert!(history
+            .forecast_at(&home(), Kind::Daily, fetched_at - time::Duration::minute())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn prune_test() {
+        let mut history = History::open_in_memory().unwrap();
+        let fetched_at = time::OffsetDateTime::from_unix_timestamp(1_608_134_500);
+        history.record(&home(), &report(fetched_at)).unwrap();
+
+        let retention = Retention {
+            observations: time::Duration::days(365),
+            forecasts: time::Duration::days(14),
+        };
+
+        history
+            .prune(&retention, fetched_at + time::Duration::days(7))
+            .unwrap();
+        assert_eq!(5, history.count().unwrap());
+
+        // Forecasts expire first.
+        history
+            .prune(&retention, fetched_at + time::Duration::days(30))
+            .unwrap();
+        assert_eq!(1, history.count().unwrap());
+
+        history
+            .prune(&retention, fetched_at + time::Duration::days(400))
+            .unwrap();
+        assert_eq!(0, history.count().unwrap());
+    }
+}
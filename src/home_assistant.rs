@@ -0,0 +1,406 @@
+//! Entities read from Home Assistant's REST API, so that sensors it already knows about don't
+//! need to be wired up twice.
+
+use std::env;
+use std::str::FromStr;
+
+use futures::future;
+use serde::Deserialize;
+
+use crate::http;
+use crate::locale::{Label, Locale};
+use crate::sensors::{EntityValue, Reading, Readings};
+use crate::timestamp::parse_iso8601;
+use crate::weather::Temperature;
+
+pub struct HomeAssistant {
+    /// The base URL, eg. `http://homeassistant.local:8123`.
+    pub url: String,
+
+    /// A long-lived access token, created from the user's profile page.
+    pub token: String,
+
+    pub entities: Vec<EntityConfig>,
+}
+
+impl HomeAssistant {
+    /// Read the configuration from `HOME_ASSISTANT_URL`, `HOME_ASSISTANT_TOKEN` and
+    /// `HOME_ASSISTANT_ENTITIES`. Returns `None` unless all three are set.
+    ///
+    /// `HOME_ASSISTANT_ENTITIES` is a `;`-separated list of `widget=entity_id` entries, eg.
+    /// `outdoor=sensor.porch_temperature;Front door=binary_sensor.front_door`.
+    pub fn from_env() -> Option<Self> {
+        let entities: Vec<EntityConfig> = env::var("HOME_ASSISTANT_ENTITIES")
+            .ok()?
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .filter_map(|entry| match entry.parse() {
+                Ok(entity) => Some(entity),
+                Err(e) => {
+                    eprintln!("Ignoring Home Assistant entity \"{}\": {}", entry, e);
+                    None
+                }
+            })
+            .collect();
+
+        if entities.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            url: env::var("HOME_ASSISTANT_URL")
+                .ok()?
+                .trim_end_matches('/')
+                .to_string(),
+            token: env::var("HOME_ASSISTANT_TOKEN").ok()?,
+            entities,
+        })
+    }
+
+    /// Fetch every entity, skipping any that can't be fetched or are unavailable.
+    pub async fn query(&self, client: &http::Client, locale: Locale) -> Readings {
+        let states = future::join_all(
+            self.entities
+                .iter()
+                .map(|entity| self.get_state(client, &entity.entity_id)),
+        )
+        .await;
+
+        let mut readings = Readings::default();
+
+        for (entity, state) in self.entities.iter().zip(states) {
+            let state = match state {
+                Ok(state) if state.is_available() => state,
+                Ok(_) => continue,
+                Err(e) => {
+                    eprintln!("Unable to fetch {}: {}", entity.entity_id, e);
+                    continue;
+                }
+            };
+
+            match &entity.widget {
+                Widget::Outdoor | Widget::Indoor => {
+                    let reading = match state.reading() {
+                        Some(reading) => reading,
+                        None => {
+                            eprintln!("{} isn't a temperature sensor.", entity.entity_id);
+                            continue;
+                        }
+                    };
+
+                    if entity.widget == Widget::Outdoor {
+                        readings.outdoor = Some(reading);
+                    } else {
+                        readings.indoor = Some(reading);
+                    }
+                }
+                Widget::Label(label) => readings.entities.push(EntityValue {
+                    label: label.clone(),
+                    value: state.display_value(locale),
+                }),
+            }
+        }
+
+        readings
+    }
+
+    async fn get_state(
+        &self,
+        client: &http::Client,
+        entity_id: &str,
+    ) -> Result<EntityState, String> {
+        let body = client
+            .get_text_with_headers(
+                &format!("{}/api/states/{}", self.url, entity_id),
+                &[("Authorization", &format!("Bearer {}", self.token))],
+            )
+            .await?;
+
+        serde_json::from_str(&body).map_err(|e| format!("{}", e))
+    }
+}
+
+/// Where an entity's value is shown.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Widget {
+    /// The outdoor temperature, used if there's no local outdoor sensor.
+    Outdoor,
+
+    /// The "Inside" panel, used if there's no local indoor sensor.
+    Indoor,
+
+    /// A row with this label under the radar.
+    Label(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityConfig {
+    pub widget: Widget,
+    pub entity_id: String,
+}
+
+impl FromStr for EntityConfig {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut parts = raw.splitn(2, '=');
+        let (widget, entity_id) = match (parts.next().map(str::trim), parts.next().map(str::trim)) {
+            (Some(widget), Some(entity_id)) if !widget.is_empty() && entity_id.contains('.') => {
+                (widget, entity_id)
+            }
+            _ => return Err("Expected widget=entity_id.".to_string()),
+        };
+
+        Ok(Self {
+            widget: match widget {
+                "outdoor" => Widget::Outdoor,
+                "indoor" => Widget::Indoor,
+                label => Widget::Label(label.to_string()),
+            },
+            entity_id: entity_id.to_string(),
+        })
+    }
+}
+
+/// The parts of `/api/states/<entity_id>` that we use.
+#[derive(Debug, Deserialize)]
+struct EntityState {
+    entity_id: String,
+    state: String,
+
+    #[serde(default)]
+    attributes: Attributes,
+
+    /// When the state or attributes last changed, eg. `2020-12-17T14:00:00.042371+00:00`.
+    last_updated: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Attributes {
+    unit_of_measurement: Option<String>,
+    device_class: Option<String>,
+
+    /// The title of a calendar's next or current event.
+    message: Option<String>,
+
+    /// When a calendar event starts, eg. `2020-12-17 18:30:00`.
+    start_time: Option<String>,
+
+    #[serde(default)]
+    all_day: bool,
+}
+
+impl EntityState {
+    fn is_available(&self) -> bool {
+        self.state != "unavailable" && self.state != "unknown"
+    }
+
+    fn domain(&self) -> &str {
+        self.entity_id.split('.').next().unwrap_or("")
+    }
+
+    /// A temperature reading, if this is a temperature sensor, timed by when Home Assistant last
+    /// updated it.
+    fn reading(&self) -> Option<Reading> {
+        let value: f32 = self.state.parse().ok()?;
+
+        let temperature = match self.attributes.unit_of_measurement.as_deref()? {
+            "°C" => Temperature::from_celsius(value),
+            "°F" => Temperature::from_fahrenheit(value),
+            "K" => Temperature::from_kelvin(value),
+            _ => return None,
+        };
+
+        Some(Reading {
+            sensor: self.entity_id.clone(),
+            temperature,
+            humidity: None,
+            pressure: None,
+            time: parse_iso8601(self.last_updated.as_deref()?)?,
+        })
+    }
+
+    /// The state as it would be shown in Home Assistant, eg. "Open" for a door or "Dentist 14:30"
+    /// for a calendar.
+    fn display_value(&self, locale: Locale) -> String {
+        match self.domain() {
+            "binary_sensor" => {
+                let (on, off) = match self.attributes.device_class.as_deref() {
+                    Some("door") | Some("garage_door") | Some("opening") | Some("window") => {
+                        (Label::Open, Label::Closed)
+                    }
+                    Some("lock") => (Label::Unlocked, Label::Locked),
+                    Some("motion") | Some("occupancy") | Some("presence") => {
+                        (Label::Detected, Label::Clear)
+                    }
+                    _ => (Label::On, Label::Off),
+                };
+                locale
+                    .label(if self.state == "on" { on } else { off })
+                    .to_string()
+            }
+            "calendar" => match &self.attributes.message {
+                Some(message) => match &self.attributes.start_time {
+                    // Show the time of day, unless the event lasts all day.
+                    Some(start_time) if !self.attributes.all_day && start_time.len() >= 16 => {
+                        format!("{} {}", message, &start_time[11..16])
+                    }
+                    _ => message.clone(),
+                },
+                None => locale.label(Label::NothingScheduled).to_string(),
+            },
+            _ => match &self.attributes.unit_of_measurement {
+                Some(unit) => format!("{} {}", self.state, unit),
+                None => self.state.clone(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::stub::StubServer;
+
+    fn state(json: &str) -> EntityState {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn from_str_test() {
+        assert_eq!(
+            Ok(EntityConfig {
+                widget: Widget::Outdoor,
+                entity_id: "sensor.porch_temperature".to_string(),
+            }),
+            "outdoor = sensor.porch_temperature".parse(),
+        );
+        assert_eq!(
+            Ok(EntityConfig {
+                widget: Widget::Label("Front door".to_string()),
+                entity_id: "binary_sensor.front_door".to_string(),
+            }),
+            "Front door=binary_sensor.front_door".parse(),
+        );
+        assert!("Front door=".parse::<EntityConfig>().is_err());
+        assert!("=sensor.porch".parse::<EntityConfig>().is_err());
+        assert!("sensor.porch".parse::<EntityConfig>().is_err());
+    }
+
+    #[test]
+    fn display_value_test() {
+        assert_eq!(
+            "Open",
+            state(r#"{"entity_id":"binary_sensor.front_door","state":"on","attributes":{"device_class":"door"}}"#)
+                .display_value(Locale::EnCa)
+        );
+        assert_eq!(
+            "Off",
+            state(r#"{"entity_id":"binary_sensor.kettle","state":"off"}"#)
+                .display_value(Locale::EnCa)
+        );
+        assert_eq!(
+            "Fermé",
+            state(r#"{"entity_id":"binary_sensor.front_door","state":"off","attributes":{"device_class":"door"}}"#)
+                .display_value(Locale::FrCa)
+        );
+        assert_eq!(
+            "Dentist 14:30",
+            state(r#"{"entity_id":"calendar.family","state":"off","attributes":{"message":"Dentist","all_day":false,"start_time":"2020-12-17 14:30:00"}}"#)
+                .display_value(Locale::EnCa)
+        );
+        assert_eq!(
+            "Recycling",
+            state(r#"{"entity_id":"calendar.family","state":"on","attributes":{"message":"Recycling","all_day":true,"start_time":"2020-12-17 00:00:00"}}"#)
+                .display_value(Locale::EnCa)
+        );
+        assert_eq!(
+            "412 ppm",
+            state(r#"{"entity_id":"sensor.co2","state":"412","attributes":{"unit_of_measurement":"ppm"}}"#)
+                .display_value(Locale::EnCa)
+        );
+    }
+
+    #[test]
+    fn reading_test() {
+        let reading = state(
+            r#"{"entity_id":"sensor.porch","state":"23.0","attributes":{"unit_of_measurement":"°F"},"last_updated":"2020-12-17T14:05:30.042371+00:00"}"#,
+        )
+        .reading()
+        .unwrap();
+        assert!((-5. - reading.temperature.celsius()).abs() < 0.001);
+        assert_eq!(
+            time::date!(2020 - 12 - 17)
+                .with_time(time::time!(14:05:30))
+                .assume_utc(),
+            reading.time
+        );
+
+        assert!(state(
+            r#"{"entity_id":"sensor.porch","state":"23.0","attributes":{"unit_of_measurement":"°F"}}"#
+        )
+        .reading()
+        .is_none());
+
+        assert!(state(
+            r#"{"entity_id":"sensor.co2","state":"412","attributes":{"unit_of_measurement":"ppm"}}"#
+        )
+        .reading()
+        .is_none());
+    }
+
+    #[tokio::test]
+    async fn query_test() {
+        let server = StubServer::start(vec![(
+            200,
+            r#"{"entity_id":"sensor.porch_temperature","state":"-3.5","attributes":{"unit_of_measurement":"°C","device_class":"temperature"},"last_changed":"2020-12-17T14:00:00+00:00","last_updated":"2020-12-17T14:00:00+00:00"}"#
+                .as_bytes(),
+        )])
+        .await;
+
+        let home_assistant = HomeAssistant {
+            url: server.url.clone(),
+            token: "secret".to_string(),
+            entities: vec![EntityConfig {
+                widget: Widget::Outdoor,
+                entity_id: "sensor.porch_temperature".to_string(),
+            }],
+        };
+
+        let readings = home_assistant
+            .query(&http::Client::new(Default::default()), Locale::EnCa)
+            .await;
+
+        let outdoor = readings.outdoor.unwrap();
+        assert_eq!("sensor.porch_temperature", outdoor.sensor);
+        assert!((-3.5 - outdoor.temperature.celsius()).abs() < 0.001);
+
+        let request = server.requests().remove(0);
+        assert!(request.starts_with("GET /api/states/sensor.porch_temperature "));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer secret"));
+    }
+
+    #[tokio::test]
+    async fn unavailable_test() {
+        let server = StubServer::start(vec![(
+            200,
+            br#"{"entity_id":"binary_sensor.front_door","state":"unavailable","attributes":{}}"#,
+        )])
+        .await;
+
+        let readings = HomeAssistant {
+            url: server.url.clone(),
+            token: "secret".to_string(),
+            entities: vec![EntityConfig {
+                widget: Widget::Label("Front door".to_string()),
+                entity_id: "binary_sensor.front_door".to_string(),
+            }],
+        }
+        .query(&http::Client::new(Default::default()), Locale::EnCa)
+        .await;
+
+        assert!(readings.entities.is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+//! A shared HTTP client that applies timeouts, a User-Agent, and retries with exponential backoff
+//! to every fetch.
+
+use std::env;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::config::parse_env;
+
+/// Identifies us to the services we fetch from, as requested by the MSC Datamart.
+const DEFAULT_USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/MikkelPaulson/weathervane)",
+);
+
+pub struct ClientConfig {
+    /// Timeout for a single request, from connecting until the body is read.
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+
+    /// Maximum number of attempts for a single request, including the first.
+    pub max_attempts: u32,
+
+    /// Backoff before the first retry, doubled for each retry after that up to `max_delay`. The
+    /// actual delay is chosen at random between zero and this value.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+
+    /// Maximum number of retries across all requests made by the client, so that an outage
+    /// doesn't stall a refresh for the full backoff of every request.
+    pub retry_budget: u32,
+
+    pub user_agent: String,
+}
+
+impl ClientConfig {
+    /// Override the defaults from the `HTTP_TIMEOUT`, `HTTP_CONNECT_TIMEOUT`, `HTTP_BASE_DELAY`,
+    /// `HTTP_MAX_DELAY` (all in seconds), `HTTP_MAX_ATTEMPTS`, `HTTP_RETRY_BUDGET` and
+    /// `HTTP_USER_AGENT` environment variables.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            timeout: parse_env("HTTP_TIMEOUT")
+                .map(Duration::from_secs)
+                .unwrap_or(default.timeout),
+            connect_timeout: parse_env("HTTP_CONNECT_TIMEOUT")
+                .map(Duration::from_secs)
+                .unwrap_or(default.connect_timeout),
+            base_delay: parse_env("HTTP_BASE_DELAY")
+                .map(Duration::from_secs)
+                .unwrap_or(default.base_delay),
+            max_delay: parse_env("HTTP_MAX_DELAY")
+                .map(Duration::from_secs)
+                .unwrap_or(default.max_delay),
+            max_attempts: parse_env("HTTP_MAX_ATTEMPTS").unwrap_or(default.max_attempts),
+            retry_budget: parse_env("HTTP_RETRY_BUDGET").unwrap_or(default.retry_budget),
+            user_agent: env::var("HTTP_USER_AGENT").unwrap_or(default.user_agent),
+        }
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            retry_budget: 8,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    client: reqwest::Client,
+    config: Arc<ClientConfig>,
+    retries_remaining: Arc<AtomicU32>,
+}
+
+impl Client {
+    pub fn new(config: ClientConfig) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(config.timeout)
+                .connect_timeout(config.connect_timeout)
+                .user_agent(&config.user_agent)
+                .build()
+                .expect("Unable to initialize HTTP client."),
+            retries_remaining: Arc::new(AtomicU32::new(config.retry_budget)),
+            config: Arc::new(config),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(ClientConfig::from_env())
+    }
+
+    pub async fn get_text(&self, url: &str) -> Result<String, String> {
+        self.get(url).await?.text().await.map_err(describe)
+    }
+
+    pub async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, String> {
+        self.get(url)
+            .await?
+            .bytes()
+            .await
+            .map(|bytes| bytes.to_vec())
+            .map_err(describe)
+    }
+
+    /// Like `get_text`, with extra headers such as `Authorization`.
+    pub async fn get_text_with_headers(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<String, String> {
+        self.get_with_headers(url, headers)
+            .await?
+            .text()
+            .await
+            .map_err(describe)
+    }
+
+    /// Like `get_text`, but a resource that doesn't exist is `None` rather than an error.
+    pub async fn get_text_if_exists(&self, url: &str) -> Result<Option<String>, String> {
+        let response = self.send(url, &[]).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        response
+            .error_for_status()
+            .map_err(describe)?
+            .text()
+            .await
+            .map(Some)
+            .map_err(describe)
+    }
+
+    /// Make a GET request, retrying on connection errors, timeouts, server errors and rate
+    /// limiting until the request succeeds or runs out of attempts or retry budget.
+    pub async fn get(&self, url: &str) -> Result<reqwest::Response, String> {
+        self.get_with_headers(url, &[]).await
+    }
+
+    pub async fn get_with_headers(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<reqwest::Response, String> {
+        self.send(url, headers)
+            .await?
+            .error_for_status()
+            .map_err(describe)
+    }
+
+    /// Make a GET request with retries, returning any response that isn't worth retrying, even if
+    /// it's an error.
+    async fn send(&self, url: &str, headers: &[(&str, &str)]) -> Result<reqwest::Response, String> {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let request = headers
+                .iter()
+                .fold(self.client.get(url), |request, (name, value)| {
+                    request.header(*name, *value)
+                });
+
+            let error = match request.send().await {
+                Ok(response) if is_retryable_status(response.status()) => {
+                    format!("{} returned {}", redact(url), response.status())
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_builder() => return Err(describe(e)),
+                Err(e) => describe(e),
+            };
+
+            if attempt >= self.config.max_attempts || !self.take_retry() {
+                return Err(error);
+            }
+
+            tokio::time::delay_for(self.backoff(attempt)).await;
+        }
+    }
+
+    fn take_retry(&self) -> bool {
+        self.retries_remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| {
+                remaining.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Exponential backoff with full jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .config
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempt - 1))
+            .unwrap_or(self.config.max_delay)
+            .min(self.config.max_delay);
+
+        ceiling.mul_f64(rand::thread_rng().gen())
+    }
+}
+
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Drop the query string from a URL before it goes into an error message, since it may hold an
+/// API key (eg. OpenWeather's `appid`).
+fn redact(url: &str) -> &str {
+    url.split('?').next().unwrap_or(url)
+}
+
+/// Describe a request error without the query string of the URL it names.
+fn describe(e: reqwest::Error) -> String {
+    let message = e.to_string();
+    match e.url() {
+        Some(url) => message.replace(url.as_str(), redact(url.as_str())),
+        None => message,
+    }
+}
+
+/// A minimal HTTP server for tests, which answers each incoming connection with the next of a
+/// fixed list of responses.
+#[cfg(test)]
+pub(crate) mod stub {
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    pub struct StubServer {
+        pub url: String,
+        requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StubServer {
+        pub async fn start(responses: Vec<(u16, &'static [u8])>) -> Self {
+            let mut listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+                .await
+                .unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let recorded = requests.clone();
+
+            tokio::spawn(async move {
+                for (status, body) in responses {
+                    let (mut socket, _) = listener.accept().await.unwrap();
+
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let len = socket.read(&mut buffer).await.unwrap();
+                        if len == 0 {
+                            break;
+                        }
+                        request.extend_from_slice(&buffer[..len]);
+                    }
+                    recorded
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8_lossy(&request).into_owned());
+
+                    socket
+                        .write_all(
+                            format!(
+                                "HTTP/1.1 {} Stub\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                                status,
+                                body.len(),
+                            )
+                            .as_bytes(),
+                        )
+                        .await
+                        .unwrap();
+                    socket.write_all(body).await.unwrap();
+                }
+            });
+
+            Self { url, requests }
+        }
+
+        /// The raw request lines and headers received so far.
+        pub fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::stub::StubServer;
+    use super::*;
+
+    fn test_client(max_attempts: u32, retry_budget: u32) -> Client {
+        Client::new(ClientConfig {
+            max_attempts,
+            retry_budget,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            user_agent: "weathervane-test".to_string(),
+            ..ClientConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn retry_test() {
+        let server = StubServer::start(vec![(503, b""), (429, b""), (200, b"ok")]).await;
+
+        assert_eq!(
+            Ok("ok".to_string()),
+            test_client(4, 8).get_text(&server.url).await
+        );
+
+        let requests = server.requests();
+        assert_eq!(3, requests.len());
+        assert!(requests[0]
+            .to_lowercase()
+            .contains("user-agent: weathervane-test"));
+    }
+
+    #[tokio::test]
+    async fn no_retry_on_client_error_test() {
+        let server = StubServer::start(vec![(404, b""), (200, b"ok")]).await;
+
+        assert!(test_client(4, 8).get_text(&server.url).await.is_err());
+        assert_eq!(1, server.requests().len());
+    }
+
+    #[tokio::test]
+    async fn redact_test() {
+        let server = StubServer::start(vec![(404, b""), (503, b"")]).await;
+        let client = test_client(1, 0);
+
+        let error = client
+            .get_text(&format!("{}/onecall?appid=secret", server.url))
+            .await
+            .unwrap_err();
+        assert!(error.contains("/onecall"), "{}", error);
+        assert!(!error.contains("secret"), "{}", error);
+
+        let error = client
+            .get_text(&format!("{}/onecall?appid=secret", server.url))
+            .await
+            .unwrap_err();
+        assert!(!error.contains("secret"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn max_attempts_test() {
+        let server = StubServer::start(vec![(500, b""), (500, b""), (200, b"ok")]).await;
+
+        assert!(test_client(2, 8).get_bytes(&server.url).await.is_err());
+        assert_eq!(2, server.requests().len());
+    }
+
+    #[tokio::test]
+    async fn retry_budget_test() {
+        let server =
+            StubServer::start(vec![(500, b""), (500, b""), (500, b""), (200, b"ok")]).await;
+        let client = test_client(4, 1);
+
+        // The first request uses up the only retry, so the second gives up immediately.
+        assert!(client.get_text(&server.url).await.is_err());
+        assert!(client.get_text(&server.url).await.is_err());
+        assert_eq!(3, server.requests().len());
+    }
+
+    #[test]
+    fn backoff_test() {
+        let client = Client::new(ClientConfig::default());
+
+        for attempt in 1..10 {
+            let delay = client.backoff(attempt);
+            assert!(delay <= Duration::from_secs(30));
+            assert!(delay <= Duration::from_secs(1) * 2u32.pow(attempt - 1));
+        }
+    }
+}
@@ -1,43 +1,77 @@
 use std::collections::HashMap;
 
-use piet::kurbo::{Affine, Circle, Line, Point, Rect};
+use piet::kurbo::{Affine, BezPath, Circle, Line, Point, Rect, Size};
 use piet::{RenderContext, Text, TextLayout, TextLayoutBuilder};
-use piet_cairo::{CairoRenderContext, CairoText};
+use piet_cairo::{CairoRenderContext, CairoText, CairoTextLayout};
 use resvg;
 use usvg;
 
+use crate::astronomy::Moon;
+use crate::locale::{Label, Locale};
+use crate::sensors::{EntityValue, Reading, Readings};
+use crate::summary;
+use crate::units::Units;
+use crate::weather::air_quality::{AirQuality, AirQualityCategory, AirQualityReport};
+use crate::weather::alerts::Alert;
 use crate::weather::{
-    AtmosphereType, OpenWeatherResponse, RainType, SnowType, ThunderstormType, WeatherCondition,
-    WeatherState,
+    radar, AtmosphereType, Forecast, LocationReport, RainType, SnowType, ThunderstormType, Weather,
+    WeatherCondition, WeatherState, Wind,
 };
 
+/// Maximum number of secondary locations to list over the radar map.
+const MAX_SECONDARY_LOCATIONS: usize = 3;
+
+/// Maximum number of Home Assistant entities to list over the radar map.
+const MAX_ENTITIES: usize = 3;
+
+/// Maximum number of alerts to list over the radar map.
+const MAX_ALERTS: usize = 2;
+
 pub fn render(
-    weather_report: Option<OpenWeatherResponse>,
-    radar_map: Option<Vec<u8>>,
+    weather: Weather,
+    readings: &Readings,
+    units: &Units,
+    locale: Locale,
     ctx: &mut CairoRenderContext,
 ) {
     // Render the image upside down (since the device is mounted upside down).
     ctx.transform(Affine::translate((280., 480.)));
     ctx.transform(Affine::rotate(std::f64::consts::PI));
 
+    let now =
+        time::OffsetDateTime::try_now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+
     // Flip the layout daily to mitigate burn-in. (Is burn-in a thing with e-paper?)
-    let radar_on_top = time::OffsetDateTime::try_now_local()
-        .unwrap_or_else(|_| time::OffsetDateTime::now_utc())
-        .day()
-        % 2
-        == 0;
+    let radar_on_top = now.day() % 2 == 0;
+
+    let mut locations = weather.locations.into_iter();
+    let mut forecast_summary = None;
+
+    if let Some(weather_report) = locations.next().and_then(|primary| primary.report) {
+        let position =
+            Rect::from_origin_size((0., if radar_on_top { 265. } else { 95. }), (280., 120.));
 
-    if let Some(weather_report) = weather_report {
+        let current = &weather_report.value.current;
+
+        // An outdoor sensor set to override the reported temperature has already replaced it, so
+        // any reading left is shown beside it.
         draw_current_conditions(
             ctx,
-            &weather_report.current,
-            Rect::from_origin_size((0., if radar_on_top { 265. } else { 95. }), (280., 120.)),
+            current,
+            readings.outdoor.as_ref(),
+            units,
+            locale,
+            position,
         );
 
-        for (i, forecast) in weather_report
-            .hourly
+        let hourly = Forecast::new(&weather_report.value.hourly);
+
+        forecast_summary = summary::summarize(hourly, current.time.max(now), units, locale);
+
+        // Don't show forecasts that are already in the past if the report is stale.
+        for (i, forecast) in hourly
+            .after(current.time.max(now))
             .iter()
-            .filter(|e| e.time > weather_report.current.time)
             .step_by(2)
             .take(5)
             .enumerate()
@@ -45,45 +79,573 @@ pub fn render(
             draw_forecast(
                 ctx,
                 forecast,
+                units,
+                locale,
                 Rect::from_origin_size(
                     (15. + 50. * i as f64, if radar_on_top { 390. } else { 10. }),
                     (50., 80.),
                 ),
             );
         }
+
+        if weather_report.stale {
+            draw_stale_label(
+                ctx,
+                weather_report.fetched_at.to_offset(now.offset()),
+                locale,
+                position,
+            );
+        }
     }
 
-    if let Some(radar_map) = radar_map {
+    let radar_position = Rect::from_origin_size(
+        if radar_on_top {
+            Point::ORIGIN
+        } else {
+            (0., 220.).into()
+        },
+        (280., 260.),
+    );
+
+    if let Some(radar_map) = weather.radar_map {
         draw_weather_radar(
             ctx,
-            radar_map,
+            radar_map.value,
+            radar::overlays(&weather.radar_station),
+            radar_position,
+        );
+
+        if radar_map.stale {
+            draw_hatching(ctx, radar_position);
+            draw_stale_label(
+                ctx,
+                radar_map.fetched_at.to_offset(now.offset()),
+                locale,
+                radar_position,
+            );
+        }
+    }
+
+    // Rows are stacked up from the bottom of the radar map: the forecast summary, alerts,
+    // entities, then other locations.
+    let mut rows_top = radar_position.y1;
+
+    if let Some(forecast_summary) = forecast_summary {
+        let layout = summary_layout(forecast_summary, radar_position.width() - 12.);
+        let bottom = rows_top;
+        rows_top -= layout.size().height + 8.;
+        draw_summary(
+            ctx,
+            &layout,
+            Rect::new(radar_position.x0, rows_top, radar_position.x1, bottom),
+        );
+    }
+
+    let alerts = &weather.alerts[..weather.alerts.len().min(MAX_ALERTS)];
+    if !alerts.is_empty() {
+        let bottom = rows_top;
+        rows_top -= 24. * alerts.len() as f64;
+        draw_alerts(
+            ctx,
+            alerts,
+            locale,
+            Rect::new(radar_position.x0, rows_top, radar_position.x1, bottom),
+        );
+    }
+
+    let entities = &readings.entities[..readings.entities.len().min(MAX_ENTITIES)];
+    if !entities.is_empty() {
+        let bottom = rows_top;
+        rows_top -= 20. * entities.len() as f64;
+        draw_entities(
+            ctx,
+            entities,
+            Rect::new(radar_position.x0, rows_top, radar_position.x1, bottom),
+        );
+    }
+
+    let secondary: Vec<LocationReport> = locations.take(MAX_SECONDARY_LOCATIONS).collect();
+    if !secondary.is_empty() {
+        let bottom = rows_top;
+        rows_top -= 24. * secondary.len() as f64;
+        draw_secondary_locations(
+            ctx,
+            &secondary,
+            now,
+            units,
+            locale,
+            Rect::new(radar_position.x0, rows_top, radar_position.x1, bottom),
+        );
+    }
+
+    // Keep the indoor conditions on the edge of the radar next to the current conditions.
+    if let Some(indoor) = &readings.indoor {
+        let size = Size::new(100., 56.);
+        draw_indoor_conditions(
+            ctx,
+            indoor,
+            units,
+            locale,
             Rect::from_origin_size(
                 if radar_on_top {
-                    Point::ORIGIN
+                    (radar_position.x0 + 4., rows_top - size.height - 4.)
                 } else {
-                    (0., 220.).into()
+                    (radar_position.x0 + 4., radar_position.y0 + 4.)
                 },
-                (280., 260.),
+                size,
             ),
         );
     }
+
+    // Fall back to the nearest forecast if there's no current observation.
+    if let Some(air_quality) =
+        weather
+            .air_quality
+            .and_then(|AirQualityReport { current, forecast }| {
+                current.or_else(|| forecast.into_iter().next())
+            })
+    {
+        draw_air_quality_badge(
+            ctx,
+            &air_quality,
+            locale,
+            Rect::from_origin_size(
+                (radar_position.x1 - 54., radar_position.y0 + 4.),
+                (50., 50.),
+            ),
+        );
+    }
+}
+
+fn summary_layout(text: String, width: f64) -> CairoTextLayout {
+    CairoText::new()
+        .new_text_layout(text)
+        .max_width(width)
+        .default_attribute(piet::TextAttribute::FontSize(13.))
+        .build()
+        .unwrap()
+}
+
+/// The forecast summary, wrapped over as many lines as it needs.
+fn draw_summary(ctx: &mut CairoRenderContext, layout: &CairoTextLayout, position: Rect) {
+    ctx.with_save(|ctx| {
+        ctx.clip(position);
+        ctx.fill(position, &piet::Color::WHITE);
+        ctx.stroke(
+            Line::new((position.x0, position.y0), (position.x1, position.y0)),
+            &piet::Color::BLACK,
+            1.,
+        );
+        ctx.draw_text(layout, (position.x0 + 6., position.y0 + 4.));
+
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// One row per alert with its headline, in white on black to stand out from the other rows.
+fn draw_alerts(ctx: &mut CairoRenderContext, alerts: &[Alert], locale: Locale, position: Rect) {
+    ctx.with_save(|ctx| {
+        ctx.clip(position);
+        ctx.fill(position, &piet::Color::BLACK);
+
+        let row_height = position.height() / alerts.len() as f64;
+
+        for (i, alert) in alerts.iter().enumerate() {
+            let y0 = position.y0 + row_height * i as f64;
+
+            ctx.stroke(
+                Line::new((position.x0, y0), (position.x1, y0)),
+                &piet::Color::WHITE,
+                1.,
+            );
+
+            // Environment Canada's headlines are in lower case, eg. "freezing rain warning in
+            // effect".
+            let headline = match alert.text(locale.language()) {
+                Some(text) => text.headline.clone().unwrap_or_else(|| text.event.clone()),
+                None => continue,
+            };
+            let mut chars = headline.chars();
+            let headline = match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => headline,
+            };
+
+            let layout = CairoText::new()
+                .new_text_layout(headline)
+                .default_attribute(piet::TextAttribute::FontSize(row_height / 2.))
+                .default_attribute(piet::TextAttribute::TextColor(piet::Color::WHITE))
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &layout,
+                (
+                    position.x0 + 6.,
+                    y0 + (row_height - layout.size().height) / 2.,
+                ),
+            );
+        }
+
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// One row per entity with its label on the left and value on the right.
+fn draw_entities(ctx: &mut CairoRenderContext, entities: &[EntityValue], position: Rect) {
+    ctx.with_save(|ctx| {
+        ctx.clip(position);
+        ctx.fill(position, &piet::Color::WHITE);
+
+        let row_height = position.height() / entities.len() as f64;
+
+        for (i, entity) in entities.iter().enumerate() {
+            let y0 = position.y0 + row_height * i as f64;
+
+            ctx.stroke(
+                Line::new((position.x0, y0), (position.x1, y0)),
+                &piet::Color::BLACK,
+                1.,
+            );
+
+            let label = CairoText::new()
+                .new_text_layout(entity.label.clone())
+                .default_attribute(piet::TextAttribute::FontSize(row_height / 2.))
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &label,
+                (
+                    position.x0 + 6.,
+                    y0 + (row_height - label.size().height) / 2.,
+                ),
+            );
+
+            let value = CairoText::new()
+                .new_text_layout(entity.value.clone())
+                .default_attribute(piet::TextAttribute::FontSize(row_height / 2.))
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &value,
+                (
+                    position.x1 - value.size().width - 6.,
+                    y0 + (row_height - value.size().height) / 2.,
+                ),
+            );
+        }
+
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// One row per location with its name, current conditions and today's low and high.
+fn draw_secondary_locations(
+    ctx: &mut CairoRenderContext,
+    locations: &[LocationReport],
+    now: time::OffsetDateTime,
+    units: &Units,
+    locale: Locale,
+    position: Rect,
+) {
+    ctx.with_save(|ctx| {
+        ctx.clip(position);
+        ctx.fill(position, &piet::Color::WHITE);
+
+        let row_height = position.height() / locations.len() as f64;
+
+        for (i, location) in locations.iter().enumerate() {
+            let row = Rect::from_origin_size(
+                (position.x0, position.y0 + row_height * i as f64),
+                (position.width(), row_height),
+            );
+
+            ctx.stroke(
+                Line::new((row.x0, row.y0), (row.x1, row.y0)),
+                &piet::Color::BLACK,
+                1.,
+            );
+
+            let name = CairoText::new()
+                .new_text_layout(location.location.name.clone())
+                .default_attribute(piet::TextAttribute::FontSize(row_height / 2.))
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &name,
+                (row.x0 + 6., row.y0 + (row_height - name.size().height) / 2.),
+            );
+
+            let (report, stale) = match &location.report {
+                Some(report) => (&report.value, report.stale),
+                None => continue,
+            };
+
+            // A stale report's current conditions are out of date, so estimate them from its
+            // hourly forecast instead, if it reaches that far.
+            let estimate = if stale {
+                Forecast::new(&report.hourly).at(now)
+            } else {
+                None
+            };
+            let current = estimate.as_ref().unwrap_or(&report.current);
+
+            draw_weather_icon(
+                ctx,
+                current,
+                Rect::from_origin_size(
+                    (row.x0 + 110., row.y0 + 2.),
+                    (row_height - 4., row_height - 4.),
+                ),
+            );
+
+            // Today at the location, which may not be the same day here.
+            let offset = report.current.time.offset();
+            let date = now.to_offset(offset).date();
+            let today = Forecast::new(&report.daily).day(date, offset).iter().next();
+            let day = today.map_or_else(String::new, |_| locale.weekday_short(date.weekday()));
+            let text = CairoText::new()
+                .new_text_layout(format!(
+                    "{}  {} {} / {}",
+                    current
+                        .temp
+                        .as_ref()
+                        .map_or_else(|| "-".to_string(), |temp| temp.format(units.temperature)),
+                    day,
+                    today
+                        .and_then(|today| today.temp_min.as_ref())
+                        .map_or_else(|| "-".to_string(), |temp| temp.format(units.temperature)),
+                    today
+                        .and_then(|today| today.temp_max.as_ref())
+                        .map_or_else(|| "-".to_string(), |temp| temp.format(units.temperature)),
+                ))
+                .default_attribute(piet::TextAttribute::FontSize(row_height / 2.))
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text,
+                (
+                    row.x1 - text.size().width - 6.,
+                    row.y0 + (row_height - text.size().height) / 2.,
+                ),
+            );
+        }
+
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// A compact badge showing the air quality index, inverted when the health risk is high.
+fn draw_air_quality_badge(
+    ctx: &mut CairoRenderContext,
+    air_quality: &AirQuality,
+    locale: Locale,
+    position: Rect,
+) {
+    let (background, foreground) = if air_quality.index.category() >= AirQualityCategory::High {
+        (piet::Color::BLACK, piet::Color::WHITE)
+    } else {
+        (piet::Color::WHITE, piet::Color::BLACK)
+    };
+
+    let badge = position.to_rounded_rect(6.);
+    ctx.fill(badge, &background);
+    ctx.stroke(badge, &piet::Color::BLACK, 2.);
+
+    let name = CairoText::new()
+        .new_text_layout(locale.air_quality_index(&air_quality.index))
+        .default_attribute(piet::TextAttribute::FontSize(position.height() / 4.))
+        .default_attribute(piet::TextAttribute::TextColor(foreground.clone()))
+        .build()
+        .unwrap();
+
+    ctx.draw_text(
+        &name,
+        (
+            position.x0 + (position.width() - name.size().width) / 2.,
+            position.y0 + 2.,
+        ),
+    );
+
+    let value = CairoText::new()
+        .new_text_layout(air_quality.index.to_string())
+        .default_attribute(piet::TextAttribute::FontSize(position.height() / 2.))
+        .default_attribute(piet::TextAttribute::TextColor(foreground))
+        .build()
+        .unwrap();
+
+    ctx.draw_text(
+        &value,
+        (
+            position.x0 + (position.width() - value.size().width) / 2.,
+            position.y1 - value.size().height - 2.,
+        ),
+    );
+}
+
+/// A small "Inside" panel with the temperature and whatever else the indoor sensor measures.
+fn draw_indoor_conditions(
+    ctx: &mut CairoRenderContext,
+    reading: &Reading,
+    units: &Units,
+    locale: Locale,
+    position: Rect,
+) {
+    let panel = position.to_rounded_rect(6.);
+    ctx.fill(panel, &piet::Color::WHITE);
+    ctx.stroke(panel, &piet::Color::BLACK, 2.);
+
+    let details = reading
+        .humidity
+        .map(|humidity| locale.percent(humidity))
+        .into_iter()
+        .chain(reading.pressure.as_ref().map(|pressure| {
+            format!(
+                "{} {}",
+                locale.decimal(pressure.in_unit(units.pressure), units.pressure.precision()),
+                units.pressure.symbol(),
+            )
+        }))
+        .collect::<Vec<_>>()
+        .join("  ");
+
+    let mut y = position.y0 + 2.;
+    for (text, font_size) in &[
+        (
+            locale.label(Label::Inside).to_string(),
+            position.height() / 5.,
+        ),
+        (
+            reading.temperature.format(units.temperature),
+            position.height() / 3.,
+        ),
+        (details, position.height() / 5.),
+    ] {
+        let text = CairoText::new()
+            .new_text_layout(text.clone())
+            .default_attribute(piet::TextAttribute::FontSize(*font_size))
+            .build()
+            .unwrap();
+        ctx.draw_text(
+            &text,
+            (position.x0 + (position.width() - text.size().width) / 2., y),
+        );
+        y += text.size().height;
+    }
+}
+
+/// Label data loaded from the cache with the time it was fetched, eg. "as of 7:40am".
+fn draw_stale_label(
+    ctx: &mut CairoRenderContext,
+    fetched_at: time::OffsetDateTime,
+    locale: Locale,
+    position: Rect,
+) {
+    ctx.with_save(|ctx| {
+        ctx.clip(position);
+
+        let text = CairoText::new()
+            .new_text_layout(format!(
+                "{} {}",
+                locale.label(Label::AsOf),
+                locale.time(fetched_at.hour(), fetched_at.minute()),
+            ))
+            .default_attribute(piet::TextAttribute::FontSize(14.))
+            .build()
+            .unwrap();
+        let origin = Point::new(position.x0 + 4., position.y0 + 2.);
+
+        ctx.fill(
+            Rect::from_origin_size(origin, text.size()).inflate(2., 0.),
+            &piet::Color::WHITE,
+        );
+        ctx.draw_text(&text, origin);
+
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// Cover an area with diagonal lines to show that its contents are out of date.
+fn draw_hatching(ctx: &mut CairoRenderContext, position: Rect) {
+    ctx.with_save(|ctx| {
+        ctx.clip(position);
+
+        for offset in (0..(position.width() + position.height()) as usize)
+            .step_by(16)
+            .map(|i| i as f64)
+        {
+            ctx.stroke(
+                Line::new(
+                    (position.x0 + offset, position.y0),
+                    (position.x0 + offset - position.height(), position.y1),
+                ),
+                &piet::Color::rgb8(0xAA, 0xAA, 0xAA),
+                1.,
+            );
+        }
+
+        Ok(())
+    })
+    .unwrap();
 }
 
-fn draw_current_conditions(ctx: &mut CairoRenderContext, state: &WeatherState, position: Rect) {
+fn draw_current_conditions(
+    ctx: &mut CairoRenderContext,
+    state: &WeatherState,
+    outdoor_sensor: Option<&Reading>,
+    units: &Units,
+    locale: Locale,
+    position: Rect,
+) {
     ctx.with_save(|ctx| {
         ctx.clip(position);
 
         let icon_size = position.height() - 20.;
         let text_area_width = position.width() - icon_size;
 
+        // Smaller lines under the temperature for the comfort index and local sensor, if any.
+        let details: Vec<String> = state
+            .feels_like(units.temperature)
+            .map(|feels_like| {
+                format!(
+                    "{} {}",
+                    locale.feels_like(&feels_like),
+                    feels_like.temperature().format(units.temperature),
+                )
+            })
+            .into_iter()
+            .chain(outdoor_sensor.map(|reading| {
+                format!(
+                    "{} {}",
+                    locale.label(Label::Sensor),
+                    reading.temperature.format(units.temperature),
+                )
+            }))
+            .collect();
+
+        // Shrink the temperature to make room for the details.
+        let temp_font_size = match details.len() {
+            0 => position.height() / 3. * 2.,
+            1 => position.height() / 2.,
+            _ => position.height() / 3.,
+        };
+
         if let Some(temp) = &state.temp {
             let text = CairoText::new()
                 .new_text_layout(format!(
                     "{}{}",
-                    if temp.celsius() > -10. { " " } else { "" },
-                    temp,
+                    if temp.in_unit(units.temperature) > -10. {
+                        " "
+                    } else {
+                        ""
+                    },
+                    temp.format(units.temperature),
                 ))
-                .default_attribute(piet::TextAttribute::FontSize(position.height() / 3. * 2.))
+                .default_attribute(piet::TextAttribute::FontSize(temp_font_size))
                 .build()
                 .unwrap();
             ctx.draw_text(
@@ -92,80 +654,146 @@ fn draw_current_conditions(ctx: &mut CairoRenderContext, state: &WeatherState, p
             );
         }
 
+        let mut y = position.y0 + temp_font_size;
+        for detail in details {
+            let text = CairoText::new()
+                .new_text_layout(detail)
+                .default_attribute(piet::TextAttribute::FontSize(position.height() / 8.))
+                .build()
+                .unwrap();
+            ctx.draw_text(&text, ((text_area_width - text.size().width) / 2., y));
+            y += text.size().height;
+        }
+
         if let Some(wind) = &state.wind {
             let wind_speed = CairoText::new()
-                .new_text_layout(
-                    if wind.gust.is_some() && wind.gust.unwrap() > wind.speed + 3. {
-                        format!(
-                            "{}-{} km/h",
-                            wind.speed_km_h().round(),
-                            wind.gust_km_h().unwrap().round()
-                        )
-                    } else {
-                        format!("{} km/h", wind.speed_km_h().round())
-                    },
-                )
+                .new_text_layout(wind.format_speed(units.speed))
                 .default_attribute(piet::TextAttribute::FontSize(position.height() / 6.))
                 .build()
                 .unwrap();
-            let wind_direction = CairoText::new()
-                .new_text_layout(wind.arrow())
-                .default_attribute(piet::TextAttribute::FontSize(position.height() / 4.))
-                .build()
-                .unwrap();
+            let dial_size = position.height() / 4.;
 
-            ctx.draw_text(
-                &wind_direction,
-                (
-                    (text_area_width - wind_direction.size().width - wind_speed.size().width) / 2.,
-                    position.y1
-                        - wind_speed.size().height * 1.5
-                        - (wind_direction.size().height - wind_speed.size().height) / 2.,
+            draw_wind_dial(
+                ctx,
+                wind,
+                Rect::from_origin_size(
+                    (
+                        (text_area_width - dial_size - wind_speed.size().width) / 2. - 5.,
+                        position.y1
+                            - wind_speed.size().height * 1.5
+                            - (dial_size - wind_speed.size().height) / 2.,
+                    ),
+                    (dial_size, dial_size),
                 ),
             );
             ctx.draw_text(
                 &wind_speed,
                 (
-                    (text_area_width + wind_direction.size().width - wind_speed.size().width) / 2.,
+                    (text_area_width + dial_size - wind_speed.size().width) / 2.,
                     position.y1 - wind_speed.size().height * 1.5,
                 ),
             );
         }
 
-        {
-            let icon = ctx
-                .make_image(
-                    icon_size as usize,
-                    icon_size as usize,
-                    &resvg::render(
-                        &get_weather_icon(state),
-                        usvg::FitTo::Height(icon_size as u32),
-                        None,
-                    )
-                    .unwrap()
-                    .data()[..],
-                    piet::ImageFormat::RgbaPremul,
-                )
-                .unwrap();
-            ctx.draw_image(
-                &icon,
-                Rect::from_origin_size(
-                    (
-                        position.x1 - icon_size - 10.,
-                        position.y0 + (position.height() - icon_size) / 2.,
-                    ),
-                    (icon_size, icon_size),
+        draw_weather_icon(
+            ctx,
+            state,
+            Rect::from_origin_size(
+                (
+                    position.x1 - icon_size - 10.,
+                    position.y0 + (position.height() - icon_size) / 2.,
                 ),
-                piet::InterpolationMode::NearestNeighbor,
+                (icon_size, icon_size),
+            ),
+        );
+
+        Ok(())
+    })
+    .unwrap()
+}
+
+/// Draw a compass dial with an arrow pointing downwind. The black arrow is scaled to the
+/// sustained wind speed and the grey arrow behind it to the gust speed, both relative to
+/// `WIND_DIAL_FULL_SCALE`.
+fn draw_wind_dial(ctx: &mut CairoRenderContext, wind: &Wind, position: Rect) {
+    /// Wind speed (in m/s) at which the arrow fills the dial, roughly 60 km/h.
+    const WIND_DIAL_FULL_SCALE: f64 = 16.7;
+
+    ctx.with_save(|ctx| {
+        let radius = position.width().min(position.height()) / 2.;
+
+        ctx.transform(Affine::translate(position.center().to_vec2()));
+
+        ctx.stroke(
+            Circle::new(Point::ORIGIN, radius - 1.),
+            &piet::Color::rgb8(0xAA, 0xAA, 0xAA),
+            1.,
+        );
+
+        for point in 0..16 {
+            let angle = point as f64 * std::f64::consts::PI / 8.;
+            let length = match point {
+                0 => radius / 2.,
+                _ if point % 4 == 0 => radius / 3.,
+                _ => radius / 6.,
+            };
+
+            ctx.stroke(
+                Affine::rotate(angle) * Line::new((0., -radius + 1.), (0., -radius + 1. + length)),
+                &piet::Color::rgb8(0xAA, 0xAA, 0xAA),
+                if point == 0 { 2. } else { 1. },
+            );
+        }
+
+        // A variable wind gets an empty dial, since there's no direction to point in.
+        let direction = match wind.direction {
+            Some(direction) => direction,
+            None => return Ok(()),
+        };
+
+        // The arrow points downwind, so a north wind (0°) points down the page.
+        ctx.transform(Affine::rotate(
+            f64::from(direction).to_radians() + std::f64::consts::PI,
+        ));
+
+        let scale = |speed: f32| (f64::from(speed) / WIND_DIAL_FULL_SCALE).clamp(0.4, 1.);
+
+        if let Some(gust) = wind.gust.filter(|&gust| gust > wind.speed) {
+            ctx.fill(
+                wind_arrow(radius * scale(gust)),
+                &piet::Color::rgb8(0xAA, 0xAA, 0xAA),
             );
         }
+        ctx.fill(wind_arrow(radius * scale(wind.speed)), &piet::Color::BLACK);
 
         Ok(())
     })
-    .unwrap()
+    .unwrap();
+}
+
+/// An arrow centred on the origin, pointing straight up, with the tip `length` from the origin.
+fn wind_arrow(length: f64) -> BezPath {
+    let (head_length, head_width, shaft_width) = (length * 0.8, length * 0.7, length * 0.25);
+
+    let mut arrow = BezPath::new();
+    arrow.move_to((0., -length));
+    arrow.line_to((head_width / 2., -length + head_length));
+    arrow.line_to((shaft_width / 2., -length + head_length));
+    arrow.line_to((shaft_width / 2., length));
+    arrow.line_to((-shaft_width / 2., length));
+    arrow.line_to((-shaft_width / 2., -length + head_length));
+    arrow.line_to((-head_width / 2., -length + head_length));
+    arrow.close_path();
+    arrow
 }
 
-fn draw_forecast(ctx: &mut CairoRenderContext, state: &WeatherState, position: Rect) {
+fn draw_forecast(
+    ctx: &mut CairoRenderContext,
+    state: &WeatherState,
+    units: &Units,
+    locale: Locale,
+    position: Rect,
+) {
     ctx.with_save(|ctx| {
         ctx.clip(position);
 
@@ -173,7 +801,7 @@ fn draw_forecast(ctx: &mut CairoRenderContext, state: &WeatherState, position: R
 
         if let Some(temp) = &state.temp {
             let text = CairoText::new()
-                .new_text_layout(format!(" {}", temp))
+                .new_text_layout(format!(" {}", temp.format(units.temperature)))
                 .default_attribute(piet::TextAttribute::FontSize(position.width() / 5. * 2.))
                 .build()
                 .unwrap();
@@ -186,37 +814,21 @@ fn draw_forecast(ctx: &mut CairoRenderContext, state: &WeatherState, position: R
             );
         }
 
-        {
-            let icon = ctx
-                .make_image(
-                    icon_size as usize,
-                    icon_size as usize,
-                    &resvg::render(
-                        &get_weather_icon(state),
-                        usvg::FitTo::Height(icon_size as u32),
-                        None,
-                    )
-                    .unwrap()
-                    .data()[..],
-                    piet::ImageFormat::RgbaPremul,
-                )
-                .unwrap();
-            ctx.draw_image(
-                &icon,
-                Rect::from_origin_size(
-                    (
-                        position.x0 + (position.width() - icon_size) / 2.,
-                        position.y0 + (position.height() - icon_size) / 2.,
-                    ),
-                    (icon_size, icon_size),
+        draw_weather_icon(
+            ctx,
+            state,
+            Rect::from_origin_size(
+                (
+                    position.x0 + (position.width() - icon_size) / 2.,
+                    position.y0 + (position.height() - icon_size) / 2.,
                 ),
-                piet::InterpolationMode::NearestNeighbor,
-            );
-        }
+                (icon_size, icon_size),
+            ),
+        );
 
         {
             let text = CairoText::new()
-                .new_text_layout(format!("{}h", state.time.hour()))
+                .new_text_layout(locale.hour(state.time.hour()))
                 .default_attribute(piet::TextAttribute::FontSize(position.width() / 3.))
                 .build()
                 .unwrap();
@@ -234,7 +846,31 @@ fn draw_forecast(ctx: &mut CairoRenderContext, state: &WeatherState, position: R
     .unwrap();
 }
 
-fn draw_weather_radar(ctx: &mut CairoRenderContext, radar_map: Vec<u8>, position: Rect) {
+fn draw_weather_icon(ctx: &mut CairoRenderContext, state: &WeatherState, position: Rect) {
+    let icon_size = position.height();
+    let icon = ctx
+        .make_image(
+            icon_size as usize,
+            icon_size as usize,
+            &resvg::render(
+                &get_weather_icon(state),
+                usvg::FitTo::Height(icon_size as u32),
+                None,
+            )
+            .unwrap()
+            .data()[..],
+            piet::ImageFormat::RgbaPremul,
+        )
+        .unwrap();
+    ctx.draw_image(&icon, position, piet::InterpolationMode::NearestNeighbor);
+}
+
+fn draw_weather_radar(
+    ctx: &mut CairoRenderContext,
+    radar_map: Vec<u8>,
+    overlays: Option<radar::Overlays>,
+    position: Rect,
+) {
     // Draw radar circles
     ctx.with_save(|ctx| {
         ctx.clip(position);
@@ -291,24 +927,26 @@ fn draw_weather_radar(ctx: &mut CairoRenderContext, radar_map: Vec<u8>, position
     .unwrap();
 
     // Draw rivers
-    draw_gif(
-        ctx,
-        &include_bytes!("../images/radar-rivers.gif")[..],
-        position,
-        |image_palette, _frame, bg_color| {
-            let mut palette = HashMap::new();
+    if let Some(overlays) = &overlays {
+        draw_gif(
+            ctx,
+            overlays.rivers,
+            position,
+            |image_palette, _frame, bg_color| {
+                let mut palette = HashMap::new();
 
-            for (index, color) in image_palette.iter().step_by(3).enumerate() {
-                palette.insert(index as u8, [0x80, 0x80, 0x80, 0xFF - *color]);
-            }
+                for (index, color) in image_palette.iter().step_by(3).enumerate() {
+                    palette.insert(index as u8, [0x80, 0x80, 0x80, 0xFF - *color]);
+                }
 
-            if let Some(index) = bg_color {
-                palette.insert(index as u8, [0x00; 4]);
-            }
+                if let Some(index) = bg_color {
+                    palette.insert(index as u8, [0x00; 4]);
+                }
 
-            palette
-        },
-    );
+                palette
+            },
+        );
+    }
 
     // Draw radar map
     draw_gif(
@@ -350,24 +988,26 @@ fn draw_weather_radar(ctx: &mut CairoRenderContext, radar_map: Vec<u8>, position
     );
 
     // Draw town names
-    draw_gif(
-        ctx,
-        &include_bytes!("../images/radar-towns.gif")[..],
-        position,
-        |image_palette, _frame, bg_color| {
-            let mut palette = HashMap::new();
+    if let Some(overlays) = &overlays {
+        draw_gif(
+            ctx,
+            overlays.towns,
+            position,
+            |image_palette, _frame, bg_color| {
+                let mut palette = HashMap::new();
 
-            for (index, color) in image_palette.chunks_exact(3).enumerate() {
-                palette.insert(index as u8, [color[0], color[1], color[2], 0xFF]);
-            }
+                for (index, color) in image_palette.chunks_exact(3).enumerate() {
+                    palette.insert(index as u8, [color[0], color[1], color[2], 0xFF]);
+                }
 
-            if let Some(index) = bg_color {
-                palette.insert(index as u8, [0x00; 4]);
-            }
+                if let Some(index) = bg_color {
+                    palette.insert(index as u8, [0x00; 4]);
+                }
 
-            palette
-        },
-    );
+                palette
+            },
+        );
+    }
 }
 
 fn draw_gif<F: Fn(&[u8], &gif::Frame, Option<u8>) -> HashMap<u8, [u8; 4]>>(
@@ -422,6 +1062,7 @@ fn get_weather_icon(state: &WeatherState) -> usvg::Tree {
     };
 
     let partly_cloudy = state.clouds.map_or(false, |clouds| clouds <= 50);
+    let full_moon = Moon::at(state.time).illumination >= 0.75;
 
     usvg::Tree::from_str(
         match &state.condition {
@@ -459,17 +1100,24 @@ fn get_weather_icon(state: &WeatherState) -> usvg::Tree {
                 AtmosphereType::Tornado => include_str!("../images/cute-weather/022-tornado.svg"),
                 AtmosphereType::Squalls => include_str!("../images/cute-weather/012-windy.svg"),
                 _ if daytime => include_str!("../images/cute-weather/019-fog.svg"),
+                _ if full_moon => include_str!("../images/cute-weather/029-full moon.svg"),
                 _ => include_str!("../images/cute-weather/028-fog.svg"),
             },
             Some(WeatherCondition::Clear) if daytime => {
                 include_str!("../images/cute-weather/001-sunny.svg")
             }
+            Some(WeatherCondition::Clear) if full_moon => {
+                include_str!("../images/cute-weather/008-full moon.svg")
+            }
             Some(WeatherCondition::Clear) => {
                 include_str!("../images/cute-weather/023-crescent moon.svg")
             }
             Some(WeatherCondition::Clouds(_)) if partly_cloudy && daytime => {
                 include_str!("../images/cute-weather/011-sunny.svg")
             }
+            Some(WeatherCondition::Clouds(_)) if partly_cloudy && full_moon => {
+                include_str!("../images/cute-weather/013-full moon.svg")
+            }
             Some(WeatherCondition::Clouds(_)) if partly_cloudy => {
                 include_str!("../images/cute-weather/025-crescent moon.svg")
             }
@@ -1,18 +1,195 @@
 use display::Display;
 
+pub mod astronomy;
+pub mod cache;
+pub mod config;
 pub mod display;
+pub mod geocoding;
+pub mod history;
+pub mod home_assistant;
+pub mod http;
 pub mod image;
+pub mod locale;
+pub mod location;
+pub mod mqtt;
+pub mod rtl433;
+pub mod sensors;
+pub mod station;
+pub mod summary;
+pub mod tempest;
+pub mod timestamp;
+pub mod units;
 pub mod weather;
 
 pub async fn refresh() -> Result<(), &'static str> {
-    let (weather_report, weather_radar) = weather::query().await;
-    let mut display = display::waveshare::EPaper3_7in::new();
+    let locale = locale::Locale::today(&locale::Locale::from_env());
+    // One client for every fetch, so that they share the retry budget.
+    let client = http::Client::from_env();
+    let mut mqtt = mqtt::MqttConfig::from_env().map(mqtt::Mqtt::connect);
 
-    display.on()?;
-    display.draw_context(|ctx| {
-        image::render(weather_report, weather_radar, ctx);
+    let sensor_values = async {
+        match &mut mqtt {
+            Some(mqtt) => mqtt.sensor_values().await,
+            None => Default::default(),
+        }
+    };
+    let home_assistant = async {
+        match home_assistant::HomeAssistant::from_env() {
+            Some(home_assistant) => home_assistant.query(&client, locale).await,
+            None => Default::default(),
+        }
+    };
+    let (weather, sensor_values, home_assistant) =
+        tokio::join!(weather::query(&client), sensor_values, home_assistant);
+    let mut weather = weather.map_err(|e| {
+        eprintln!("{}", e);
+        "Unable to query weather"
     })?;
-    display.sleep()?;
 
-    Ok(())
+    if let Err(e) = history::History::from_env().and_then(|mut history| {
+        history.record_weather(&weather)?;
+        history.prune(
+            &history::Retention::from_env(),
+            time::OffsetDateTime::now_utc(),
+        )
+    }) {
+        eprintln!("Unable to record history: {}", e);
+    }
+
+    let cache = cache::Cache::from_env();
+    let tempest_config = tempest::TempestConfig::from_env();
+    let tempest = tempest_config
+        .as_ref()
+        .and_then(|config| tempest::latest(&cache, config.max_age));
+
+    // Local readings take precedence over the provider's, but aren't recorded as its observations.
+    // Sensors configured individually take precedence over the weather stations.
+    if let Some(report) = weather
+        .locations
+        .first_mut()
+        .and_then(|primary| primary.report.as_mut())
+    {
+        if let Some(config) = station::StationConfig::from_env() {
+            if let Some(observation) = station::latest(&cache, config.max_age) {
+                observation.value.apply(&mut report.value.current);
+            }
+        }
+        if let Some(config) = rtl433::Rtl433Config::from_env() {
+            if let Some(observation) = rtl433::latest(&cache, config.max_age) {
+                observation.value.apply(&mut report.value.current);
+            }
+        }
+        if let (Some(tempest), Some(config)) = (&tempest, &tempest_config) {
+            tempest
+                .value
+                .apply(&mut report.value.current, config.elevation);
+        }
+        sensor_values.apply(&mut report.value.current);
+    }
+
+    if let Some(mqtt) = &mqtt {
+        if let Err(e) = mqtt.publish_weather(&weather).await {
+            eprintln!("Unable to publish weather: {}", e);
+        }
+    }
+
+    // Local sensors take precedence over the same readings from Home Assistant.
+    let mut readings = sensors::Readings::from_env().or(home_assistant);
+    readings.entities.extend(tempest.and_then(|tempest| {
+        tempest
+            .value
+            .lightning(time::OffsetDateTime::now_utc(), locale)
+    }));
+
+    // The outdoor sensor either replaces the reported temperature or is shown beside it.
+    if sensors::SensorDisplay::from_env() == sensors::SensorDisplay::Override {
+        if let Some(report) = weather
+            .locations
+            .first_mut()
+            .and_then(|primary| primary.report.as_mut())
+        {
+            if let Some(reading) = readings.outdoor.take() {
+                report.value.current.temp = Some(reading.temperature);
+            }
+        }
+    }
+
+    let units = units::Units::from_env();
+    let mut display = display::waveshare::EPaper3_7in::new();
+
+    // Take the status now, since rendering consumes the weather.
+    let mut status = mqtt.as_ref().map(|_| mqtt::Status::new(&weather));
+
+    let result = display
+        .on()
+        .and_then(|_| {
+            display.draw_context(|ctx| {
+                image::render(weather, &readings, &units, locale, ctx);
+            })
+        })
+        .and_then(|_| display.sleep());
+
+    if let (Some(mqtt), Some(status)) = (mqtt, status.as_mut()) {
+        if let Err(e) = result {
+            status.set_error(e);
+        }
+        if let Err(e) = mqtt.publish_status(status).await {
+            eprintln!("Unable to publish status: {}", e);
+        }
+        mqtt.disconnect().await;
+    }
+
+    result
+}
+
+/// Receive uploads from a personal weather station, Tempest broadcasts and rtl_433 events until
+/// interrupted, for whichever of them are configured. A listener that fails doesn't stop the
+/// others.
+pub async fn listen() -> Result<(), String> {
+    let station = station::StationConfig::from_env();
+    let tempest = tempest::TempestConfig::from_env();
+    let rtl433 = rtl433::Rtl433Config::from_env();
+
+    if station.is_none() && tempest.is_none() && rtl433.is_none() {
+        return Err(
+            "Nothing to listen for: set STATION_LISTEN, TEMPEST_LISTEN or RTL433_SENSORS."
+                .to_string(),
+        );
+    }
+
+    let (station, tempest, rtl433) = futures::future::join3(
+        async {
+            match station {
+                Some(config) => station::listen(config, cache::Cache::from_env())
+                    .await
+                    .map_err(|e| log_listener_error("station", e)),
+                None => Ok(()),
+            }
+        },
+        async {
+            match tempest {
+                Some(config) => tempest::listen(config, cache::Cache::from_env())
+                    .await
+                    .map_err(|e| log_listener_error("Tempest", e)),
+                None => Ok(()),
+            }
+        },
+        async {
+            match rtl433 {
+                Some(config) => rtl433::listen(config, cache::Cache::from_env())
+                    .await
+                    .map_err(|e| log_listener_error("rtl_433", e)),
+                None => Ok(()),
+            }
+        },
+    )
+    .await;
+
+    station.and(tempest).and(rtl433)
+}
+
+/// Report a listener's failure as soon as it happens, rather than once the others have stopped.
+fn log_listener_error(listener: &str, e: String) -> String {
+    eprintln!("The {} listener stopped: {}", listener, e);
+    e
 }
@@ -0,0 +1,402 @@
+use std::env;
+use std::str::FromStr;
+
+use crate::weather::air_quality::AirQualityIndex;
+use crate::weather::{
+    AtmosphereType, CloudsType, CompassPoint, DrizzleType, FeelsLike, RainType, SnowType,
+    ThunderstormType, WeatherCondition,
+};
+
+/// The language and conventions used for text on the display.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Locale {
+    EnCa,
+    FrCa,
+}
+
+impl Locale {
+    /// The locales to render in, from the `LOCALE` environment variable (eg. `fr-CA`, defaulting
+    /// to `en-CA`). Listing several (eg. `fr-CA,en-CA`, or `bilingual` for short) alternates
+    /// between them daily. Unrecognized values are ignored.
+    pub fn from_env() -> Vec<Self> {
+        let locales = env::var("LOCALE")
+            .map(|value| parse_list(&value))
+            .unwrap_or_default();
+
+        if locales.is_empty() {
+            vec![Self::EnCa]
+        } else {
+            locales
+        }
+    }
+
+    /// The locale for today, in local time.
+    pub fn today(locales: &[Self]) -> Self {
+        let now = time::OffsetDateTime::try_now_local()
+            .unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+        Self::for_date(locales, now.date())
+    }
+
+    /// Pick the locale for a day from those configured, so that the display switches language
+    /// at midnight.
+    pub fn for_date(locales: &[Self], date: time::Date) -> Self {
+        match locales.len() {
+            0 => Self::EnCa,
+            len => locales[date.julian_day().rem_euclid(len as i64) as usize],
+        }
+    }
+
+    /// The ISO 639-1 language code, eg. "fr".
+    pub fn language(&self) -> &'static str {
+        match self {
+            Self::EnCa => "en",
+            Self::FrCa => "fr",
+        }
+    }
+
+    pub fn label(&self, label: Label) -> &'static str {
+        match (self, label) {
+            (Self::EnCa, Label::Inside) => "Inside",
+            (Self::FrCa, Label::Inside) => "Intérieur",
+            (Self::EnCa, Label::Sensor) => "Sensor",
+            (Self::FrCa, Label::Sensor) => "Capteur",
+            (Self::EnCa, Label::AsOf) => "as of",
+            (Self::FrCa, Label::AsOf) => "en date de",
+            (Self::EnCa, Label::Lightning) => "Lightning",
+            (Self::FrCa, Label::Lightning) => "Foudre",
+            (Self::EnCa, Label::Open) => "Open",
+            (Self::FrCa, Label::Open) => "Ouvert",
+            (Self::EnCa, Label::Closed) => "Closed",
+            (Self::FrCa, Label::Closed) => "Fermé",
+            (Self::EnCa, Label::Unlocked) => "Unlocked",
+            (Self::FrCa, Label::Unlocked) => "Déverrouillé",
+            (Self::EnCa, Label::Locked) => "Locked",
+            (Self::FrCa, Label::Locked) => "Verrouillé",
+            (Self::EnCa, Label::Detected) => "Detected",
+            (Self::FrCa, Label::Detected) => "Détecté",
+            (Self::EnCa, Label::Clear) => "Clear",
+            (Self::FrCa, Label::Clear) => "Non détecté",
+            (Self::EnCa, Label::On) => "On",
+            (Self::FrCa, Label::On) => "Allumé",
+            (Self::EnCa, Label::Off) => "Off",
+            (Self::FrCa, Label::Off) => "Éteint",
+            (Self::EnCa, Label::NothingScheduled) => "Nothing scheduled",
+            (Self::FrCa, Label::NothingScheduled) => "Rien de prévu",
+        }
+    }
+
+    pub fn feels_like(&self, feels_like: &FeelsLike) -> &'static str {
+        match (self, feels_like) {
+            (Self::EnCa, FeelsLike::WindChill(_)) => "Wind chill",
+            // "Refroidissement éolien" doesn't fit under the temperature.
+            (Self::FrCa, FeelsLike::WindChill(_)) => "Refroid. éolien",
+            (_, FeelsLike::Humidex(_)) => "Humidex",
+            (Self::EnCa, FeelsLike::HeatIndex(_)) => "Heat index",
+            (Self::FrCa, FeelsLike::HeatIndex(_)) => "Indice de chaleur",
+        }
+    }
+
+    /// The short name of an air quality index, eg. "CAS" for the AQHI in French.
+    pub fn air_quality_index(&self, index: &AirQualityIndex) -> &'static str {
+        match (self, index) {
+            (Self::FrCa, AirQualityIndex::Aqhi(_)) => "CAS",
+            (Self::FrCa, AirQualityIndex::OpenWeather(_)) => "IQA",
+            (Self::EnCa, index) => index.name(),
+        }
+    }
+
+    pub fn weekday(&self, weekday: time::Weekday) -> &'static str {
+        use time::Weekday::*;
+
+        match (self, weekday) {
+            (Self::EnCa, Monday) => "Monday",
+            (Self::EnCa, Tuesday) => "Tuesday",
+            (Self::EnCa, Wednesday) => "Wednesday",
+            (Self::EnCa, Thursday) => "Thursday",
+            (Self::EnCa, Friday) => "Friday",
+            (Self::EnCa, Saturday) => "Saturday",
+            (Self::EnCa, Sunday) => "Sunday",
+            (Self::FrCa, Monday) => "lundi",
+            (Self::FrCa, Tuesday) => "mardi",
+            (Self::FrCa, Wednesday) => "mercredi",
+            (Self::FrCa, Thursday) => "jeudi",
+            (Self::FrCa, Friday) => "vendredi",
+            (Self::FrCa, Saturday) => "samedi",
+            (Self::FrCa, Sunday) => "dimanche",
+        }
+    }
+
+    /// The abbreviated weekday, eg. "Mon" or "lun.".
+    pub fn weekday_short(&self, weekday: time::Weekday) -> String {
+        let name = self.weekday(weekday);
+        match self {
+            Self::EnCa => name[..3].to_string(),
+            Self::FrCa => format!("{}.", &name[..3]),
+        }
+    }
+
+    /// Format a number with the given number of decimal places, eg. "101,3" in French.
+    pub fn decimal(&self, value: f32, precision: usize) -> String {
+        let formatted = format!("{:.*}", precision, value);
+        match self {
+            Self::EnCa => formatted,
+            Self::FrCa => formatted.replace('.', ","),
+        }
+    }
+
+    /// Format a percentage. French puts a space before the sign.
+    pub fn percent(&self, value: u8) -> String {
+        match self {
+            Self::EnCa => format!("{}%", value),
+            Self::FrCa => format!("{}\u{a0}%", value),
+        }
+    }
+
+    /// How long ago something happened, eg. "5 min ago" or "il y a 5 min".
+    pub fn minutes_ago(&self, minutes: i64) -> String {
+        match self {
+            Self::EnCa => format!("{} min ago", minutes),
+            Self::FrCa => format!("il y a {}\u{a0}min", minutes),
+        }
+    }
+
+    /// Format an hour of the day, eg. "3pm" or "15 h".
+    pub fn hour(&self, hour: u8) -> String {
+        match self {
+            Self::EnCa => format!("{}{}", twelve_hour(hour), meridiem(hour)),
+            Self::FrCa => format!("{}\u{a0}h", hour),
+        }
+    }
+
+    /// Format a time of day, eg. "7:40am" or "7 h 40".
+    pub fn time(&self, hour: u8, minute: u8) -> String {
+        match self {
+            Self::EnCa => format!("{}:{:02}{}", twelve_hour(hour), minute, meridiem(hour)),
+            Self::FrCa => format!("{}\u{a0}h\u{a0}{:02}", hour, minute),
+        }
+    }
+
+    /// The abbreviation of a compass point, eg. "ONO" for west-northwest in French.
+    pub fn compass_point(&self, point: CompassPoint) -> String {
+        match self {
+            Self::EnCa => point.abbreviation().to_string(),
+            Self::FrCa => point.abbreviation().replace('W', "O"),
+        }
+    }
+
+    /// A short description of the weather, eg. "Freezing rain" or "Pluie verglaçante".
+    pub fn condition(&self, condition: &WeatherCondition) -> &'static str {
+        match self {
+            Self::EnCa => condition.description(),
+            Self::FrCa => match condition {
+                WeatherCondition::Thunderstorm(subtype) => thunderstorm(subtype),
+                WeatherCondition::Drizzle(subtype) => drizzle(subtype),
+                WeatherCondition::Rain(subtype) => rain(subtype),
+                WeatherCondition::Snow(subtype) => snow(subtype),
+                WeatherCondition::Atmosphere(subtype) => atmosphere(subtype),
+                WeatherCondition::Clear => "Dégagé",
+                WeatherCondition::Clouds(subtype) => clouds(subtype),
+                WeatherCondition::Unknown(_) => "Inconnu",
+            },
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('_', "-").as_str() {
+            "en" | "en-ca" => Ok(Self::EnCa),
+            "fr" | "fr-ca" => Ok(Self::FrCa),
+            _ => Err("Unknown locale."),
+        }
+    }
+}
+
+/// Text on the display that isn't derived from the weather.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Label {
+    Inside,
+    Sensor,
+    /// Precedes the time stale data was fetched at.
+    AsOf,
+    Lightning,
+
+    // The states of Home Assistant's binary sensors, by device class.
+    Open,
+    Closed,
+    Unlocked,
+    Locked,
+    Detected,
+    Clear,
+    On,
+    Off,
+
+    /// A calendar without an upcoming event.
+    NothingScheduled,
+}
+
+fn parse_list(value: &str) -> Vec<Locale> {
+    if value.trim().eq_ignore_ascii_case("bilingual") {
+        return vec![Locale::FrCa, Locale::EnCa];
+    }
+
+    value
+        .split(',')
+        .filter_map(|locale| locale.parse().ok())
+        .collect()
+}
+
+fn twelve_hour(hour: u8) -> u8 {
+    match hour % 12 {
+        0 => 12,
+        hour => hour,
+    }
+}
+
+fn meridiem(hour: u8) -> &'static str {
+    if hour < 12 {
+        "am"
+    } else {
+        "pm"
+    }
+}
+
+fn thunderstorm(subtype: &ThunderstormType) -> &'static str {
+    match subtype {
+        ThunderstormType::ThunderstormWithLightRain => "Orage avec faible pluie",
+        ThunderstormType::ThunderstormWithRain => "Orage avec pluie",
+        ThunderstormType::ThunderstormWithHeavyRain => "Orage avec forte pluie",
+        ThunderstormType::LightThunderstorm => "Faible orage",
+        ThunderstormType::Thunderstorm | ThunderstormType::Unknown(_) => "Orage",
+        ThunderstormType::HeavyThunderstorm => "Violent orage",
+        ThunderstormType::RaggedThunderstorm => "Orages isolés",
+        ThunderstormType::ThunderstormWithLightDrizzle => "Orage avec faible bruine",
+        ThunderstormType::ThunderstormWithDrizzle => "Orage avec bruine",
+        ThunderstormType::ThunderstormWithHeavyDrizzle => "Orage avec forte bruine",
+    }
+}
+
+fn drizzle(subtype: &DrizzleType) -> &'static str {
+    match subtype {
+        DrizzleType::LightIntensityDrizzle => "Faible bruine",
+        DrizzleType::Drizzle | DrizzleType::Unknown(_) => "Bruine",
+        DrizzleType::HeavyIntensityDrizzle => "Forte bruine",
+        DrizzleType::LightIntensityDrizzleRain => "Faible pluie et bruine",
+        DrizzleType::DrizzleRain => "Pluie et bruine",
+        DrizzleType::HeavyIntensityDrizzleRain => "Forte pluie et bruine",
+        DrizzleType::ShowerRainAndDrizzle => "Averses et bruine",
+        DrizzleType::HeavyShowerRainAndDrizzle => "Fortes averses et bruine",
+        DrizzleType::ShowerDrizzle => "Averses de bruine",
+    }
+}
+
+fn rain(subtype: &RainType) -> &'static str {
+    match subtype {
+        RainType::LightRain => "Faible pluie",
+        RainType::ModerateRain | RainType::Unknown(_) => "Pluie",
+        RainType::HeavyIntensityRain => "Forte pluie",
+        RainType::VeryHeavyRain => "Très forte pluie",
+        RainType::ExtremeRain => "Pluie torrentielle",
+        RainType::FreezingRain => "Pluie verglaçante",
+        RainType::LightIntensityShowerRain => "Faibles averses",
+        RainType::ShowerRain => "Averses",
+        RainType::HeavyIntensityShowerRain => "Fortes averses",
+        RainType::RaggedShowerRain => "Averses isolées",
+    }
+}
+
+fn snow(subtype: &SnowType) -> &'static str {
+    match subtype {
+        SnowType::LightSnow => "Faible neige",
+        SnowType::Snow | SnowType::Unknown(_) => "Neige",
+        SnowType::HeavySnow => "Forte neige",
+        SnowType::Sleet => "Grésil",
+        SnowType::LightShowerSleet => "Faibles averses de grésil",
+        SnowType::ShowerSleet => "Averses de grésil",
+        SnowType::LightRainAndSnow => "Faible pluie et neige",
+        SnowType::RainAndSnow => "Pluie et neige",
+        SnowType::LightShowerSnow => "Faibles averses de neige",
+        SnowType::ShowerSnow => "Averses de neige",
+        SnowType::HeavyShowerSnow => "Fortes averses de neige",
+    }
+}
+
+fn atmosphere(subtype: &AtmosphereType) -> &'static str {
+    match subtype {
+        AtmosphereType::Mist => "Brume",
+        AtmosphereType::Smoke => "Fumée",
+        AtmosphereType::Haze => "Brume sèche",
+        AtmosphereType::SandDustWhirls => "Tourbillons de poussière",
+        AtmosphereType::Fog | AtmosphereType::Unknown(_) => "Brouillard",
+        AtmosphereType::Sand => "Chasse-sable",
+        AtmosphereType::Dust => "Chasse-poussière",
+        AtmosphereType::VolcanicAsh => "Cendres volcaniques",
+        AtmosphereType::Squalls => "Grains",
+        AtmosphereType::Tornado => "Tornade",
+    }
+}
+
+fn clouds(subtype: &CloudsType) -> &'static str {
+    match subtype {
+        CloudsType::FewClouds => "Généralement dégagé",
+        CloudsType::ScatteredClouds => "Partiellement nuageux",
+        CloudsType::BrokenClouds => "Généralement nuageux",
+        CloudsType::OvercastClouds | CloudsType::Unknown(_) => "Nuageux",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(Ok(Locale::FrCa), "fr_CA".parse());
+        assert_eq!(vec![Locale::FrCa, Locale::EnCa], parse_list("bilingual"));
+        assert_eq!(vec![Locale::EnCa, Locale::FrCa], parse_list("en-CA, fr-CA"));
+        assert!(parse_list("tlh").is_empty());
+    }
+
+    #[test]
+    fn for_date_test() {
+        let locales = [Locale::FrCa, Locale::EnCa];
+        let today = time::date!(2021 - 01 - 31);
+        let tomorrow = today.next_day();
+
+        assert_ne!(
+            Locale::for_date(&locales, today),
+            Locale::for_date(&locales, tomorrow)
+        );
+        assert_eq!(Locale::EnCa, Locale::for_date(&[Locale::EnCa], tomorrow));
+    }
+
+    #[test]
+    fn format_test() {
+        assert_eq!("101.3", Locale::EnCa.decimal(101.325, 1));
+        assert_eq!("101,3", Locale::FrCa.decimal(101.325, 1));
+        assert_eq!("12am", Locale::EnCa.hour(0));
+        assert_eq!("3pm", Locale::EnCa.hour(15));
+        assert_eq!("15\u{a0}h", Locale::FrCa.hour(15));
+        assert_eq!("7:05am", Locale::EnCa.time(7, 5));
+        assert_eq!("7\u{a0}h\u{a0}05", Locale::FrCa.time(7, 5));
+        assert_eq!("45\u{a0}%", Locale::FrCa.percent(45));
+    }
+
+    #[test]
+    fn weekday_test() {
+        assert_eq!("Wed", Locale::EnCa.weekday_short(time::Weekday::Wednesday));
+        assert_eq!("mer.", Locale::FrCa.weekday_short(time::Weekday::Wednesday));
+        assert_eq!("jeudi", Locale::FrCa.weekday(time::Weekday::Thursday));
+        assert_eq!("lun.", Locale::FrCa.weekday_short(time::Weekday::Monday));
+    }
+
+    #[test]
+    fn condition_test() {
+        let condition = WeatherCondition::from(511);
+        assert_eq!("Freezing rain", Locale::EnCa.condition(&condition));
+        assert_eq!("Pluie verglaçante", Locale::FrCa.condition(&condition));
+        assert_eq!("Partiellement nuageux", Locale::FrCa.condition(&802.into()));
+    }
+}
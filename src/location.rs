@@ -0,0 +1,276 @@
+//! The named locations to fetch the weather for.
+
+use std::env;
+use std::str::FromStr;
+
+use crate::cache::Cache;
+use crate::config::parse_env;
+use crate::geocoding;
+use crate::http;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+
+    /// IANA time zone name, if the location was geocoded.
+    pub timezone: Option<String>,
+}
+
+impl Location {
+    /// A lowercase identifier made up of the name's letters and digits, for use in file names.
+    pub fn slug(&self) -> String {
+        slugify(&self.name)
+    }
+}
+
+/// A location as configured, which may need to be geocoded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocationConfig {
+    /// Defaults to the geocoded place name.
+    pub name: Option<String>,
+    pub query: LocationQuery,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LocationQuery {
+    Coordinates {
+        latitude: f64,
+        longitude: f64,
+    },
+
+    /// A place name or postal code.
+    Search(String),
+}
+
+impl LocationConfig {
+    /// Read the locations from the `LOCATIONS` environment variable, a `;`-separated list of
+    /// `name=latitude,longitude` or `name=place` entries, eg.
+    /// `Home=H2X 1Y4;Cottage=46.2,-74.6;Office=Ottawa`. The name is optional for places. The first
+    /// location is the primary one.
+    ///
+    /// Without `LOCATIONS`, there is a single location named by `LOCATION_NAME`, at the place given
+    /// by `LOCATION` or else at `OPEN_WEATHER_LAT` and `OPEN_WEATHER_LON`.
+    pub fn all_from_env() -> Vec<Self> {
+        let locations = env::var("LOCATIONS")
+            .ok()
+            .map(|locations| {
+                locations
+                    .split(';')
+                    .filter(|entry| !entry.trim().is_empty())
+                    .filter_map(|entry| match entry.parse() {
+                        Ok(location) => Some(location),
+                        Err(e) => {
+                            eprintln!("Ignoring location \"{}\": {}", entry, e);
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        if !locations.is_empty() {
+            return locations;
+        }
+
+        let query = env::var("LOCATION")
+            .ok()
+            .and_then(|location| location.parse().ok())
+            .unwrap_or_else(|| LocationQuery::Coordinates {
+                latitude: parse_env("OPEN_WEATHER_LAT").unwrap_or(45.5),
+                longitude: parse_env("OPEN_WEATHER_LON").unwrap_or(-73.6),
+            });
+
+        vec![Self {
+            name: Some(env::var("LOCATION_NAME").unwrap_or_else(|_| "Home".to_string())),
+            query,
+        }]
+    }
+
+    /// Look up the coordinates of a place, using the cached result if it's been looked up before.
+    pub async fn resolve(self, client: &http::Client, cache: &Cache) -> Result<Location, String> {
+        let (latitude, longitude, timezone, place_name) = match self.query {
+            LocationQuery::Coordinates {
+                latitude,
+                longitude,
+            } => (latitude, longitude, None, None),
+            LocationQuery::Search(query) => {
+                let cache_name = format!("geocoding_{}.json", slugify(&query));
+
+                let place = match cache
+                    .load(&cache_name)
+                    .ok()
+                    .and_then(|cached| serde_json::from_slice(&cached.value).ok())
+                {
+                    Some(place) => place,
+                    None => {
+                        let place: geocoding::Place = geocoding::search(client, &query).await?;
+                        match serde_json::to_vec(&place) {
+                            Ok(data) => {
+                                if let Err(e) =
+                                    cache.store(&cache_name, &data, time::OffsetDateTime::now_utc())
+                                {
+                                    eprintln!("Unable to cache geocoding result: {}", e);
+                                }
+                            }
+                            Err(e) => eprintln!("Unable to cache geocoding result: {}", e),
+                        }
+                        place
+                    }
+                };
+
+                (
+                    place.latitude,
+                    place.longitude,
+                    place.timezone,
+                    Some(place.name),
+                )
+            }
+        };
+
+        Ok(Location {
+            name: self
+                .name
+                .or(place_name)
+                .unwrap_or_else(|| format!("{:.2}, {:.2}", latitude, longitude)),
+            latitude,
+            longitude,
+            timezone,
+        })
+    }
+}
+
+impl FromStr for LocationConfig {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut parts = raw.splitn(2, '=');
+        let (name, query) = match (parts.next(), parts.next()) {
+            (Some(name), Some(query)) if name.trim().is_empty() => {
+                return Err(format!("Missing name before \"={}\".", query))
+            }
+            (Some(name), Some(query)) => (Some(name.trim().to_string()), query),
+            (Some(query), None) => (None, query),
+            (None, _) => unreachable!(),
+        };
+
+        Ok(Self {
+            name,
+            query: query.parse()?,
+        })
+    }
+}
+
+impl FromStr for LocationQuery {
+    type Err = String;
+
+    /// Parse `latitude,longitude`, or else take the whole string as a place to search for.
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
+
+        if raw.is_empty() {
+            return Err("Missing place.".to_string());
+        }
+
+        let mut parts = raw.splitn(2, ',');
+        if let (Some(Ok(latitude)), Some(Ok(longitude))) = (
+            parts.next().map(|part| part.trim().parse::<f64>()),
+            parts.next().map(|part| part.trim().parse::<f64>()),
+        ) {
+            return if (-90. ..=90.).contains(&latitude) && (-180. ..=180.).contains(&longitude) {
+                Ok(Self::Coordinates {
+                    latitude,
+                    longitude,
+                })
+            } else {
+                Err("Coordinates out of range.".to_string())
+            };
+        }
+
+        Ok(Self::Search(raw.to_string()))
+    }
+}
+
+fn slugify(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_str_test() {
+        assert_eq!(
+            Ok(LocationConfig {
+                name: Some("Cottage".to_string()),
+                query: LocationQuery::Coordinates {
+                    latitude: 46.2,
+                    longitude: -74.6,
+                },
+            }),
+            " Cottage = 46.2, -74.6".parse(),
+        );
+
+        assert_eq!(
+            Ok(LocationConfig {
+                name: Some("Home".to_string()),
+                query: LocationQuery::Search("H2X 1Y4".to_string()),
+            }),
+            "Home=H2X 1Y4".parse(),
+        );
+
+        assert_eq!(
+            Ok(LocationConfig {
+                name: None,
+                query: LocationQuery::Search("Mont-Tremblant, QC".to_string()),
+            }),
+            "Mont-Tremblant, QC".parse(),
+        );
+
+        assert!("=46.2,-74.6".parse::<LocationConfig>().is_err());
+        assert!("Cottage=".parse::<LocationConfig>().is_err());
+        assert!("Cottage=91,-74.6".parse::<LocationConfig>().is_err());
+    }
+
+    #[test]
+    fn slug_test() {
+        assert_eq!("home", slugify("Home"));
+        assert_eq!("lac-des-îles", slugify("Lac des Îles"));
+        assert_eq!("mom-s-place", slugify("Mom's place"));
+        assert_eq!("h2x-1y4", slugify("H2X 1Y4"));
+    }
+
+    #[tokio::test]
+    async fn resolve_cached_test() {
+        let dir = env::temp_dir().join(format!("weathervane-location-test-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        cache
+            .store(
+                "geocoding_mont-tremblant.json",
+                br#"{"name":"Mont-Tremblant","latitude":46.12,"longitude":-74.6,"timezone":"America/Toronto"}"#,
+                time::OffsetDateTime::now_utc(),
+            )
+            .unwrap();
+
+        // The cached result is used without making a request.
+        let location = LocationConfig {
+            name: None,
+            query: LocationQuery::Search("Mont-Tremblant".to_string()),
+        }
+        .resolve(&http::Client::new(Default::default()), &cache)
+        .await
+        .unwrap();
+
+        assert_eq!("Mont-Tremblant", location.name);
+        assert_eq!(46.12, location.latitude);
+        assert_eq!(Some("America/Toronto".to_string()), location.timezone);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
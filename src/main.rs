@@ -3,5 +3,9 @@ use dotenv::dotenv;
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    weathervane::refresh().await.unwrap();
+
+    match std::env::args().nth(1).as_deref() {
+        Some("listen") => weathervane::listen().await.unwrap(),
+        _ => weathervane::refresh().await.unwrap(),
+    }
 }
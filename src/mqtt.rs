@@ -0,0 +1,613 @@
+//! An MQTT client for sharing with other home devices: local sensor readings are taken from
+//! subscribed topics, and the forecast and refresh status are published as JSON.
+
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::parse_env;
+use crate::weather::{Pressure, Temperature, Weather, WeatherState};
+
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub credentials: Option<(String, String)>,
+
+    /// Prepended to every topic we publish, eg. `weathervane/status`.
+    pub topic_prefix: String,
+
+    pub sensor_topics: Vec<SensorTopic>,
+
+    /// How long to wait for sensor readings. Retained messages normally arrive right after
+    /// subscribing.
+    pub sensor_wait: Duration,
+
+    pub keep_alive: Duration,
+
+    /// Backoff before reconnecting, doubled for each failed attempt up to `max_reconnect_delay`.
+    pub reconnect_delay: Duration,
+    pub max_reconnect_delay: Duration,
+}
+
+impl MqttConfig {
+    /// Read the configuration from `MQTT_HOST` (MQTT is disabled if it isn't set), `MQTT_PORT`,
+    /// `MQTT_CLIENT_ID`, `MQTT_USERNAME`, `MQTT_PASSWORD`, `MQTT_TOPIC_PREFIX`,
+    /// `MQTT_SENSOR_TOPICS`, `MQTT_SENSOR_WAIT` and `MQTT_KEEP_ALIVE` (both in seconds).
+    ///
+    /// `MQTT_SENSOR_TOPICS` is a `;`-separated list of `field=topic` entries, eg.
+    /// `temperature=sensors/porch/temperature;humidity=zigbee2mqtt/porch`.
+    pub fn from_env() -> Option<Self> {
+        let host = env::var("MQTT_HOST").ok()?;
+
+        let sensor_topics = env::var("MQTT_SENSOR_TOPICS")
+            .map(|topics| {
+                topics
+                    .split(';')
+                    .filter(|entry| !entry.trim().is_empty())
+                    .filter_map(|entry| match entry.parse() {
+                        Ok(topic) => Some(topic),
+                        Err(e) => {
+                            eprintln!("Ignoring MQTT sensor topic \"{}\": {}", entry, e);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            host,
+            port: parse_env("MQTT_PORT").unwrap_or(1883),
+            client_id: env::var("MQTT_CLIENT_ID").unwrap_or_else(|_| "weathervane".to_string()),
+            credentials: env::var("MQTT_USERNAME")
+                .ok()
+                .map(|username| (username, env::var("MQTT_PASSWORD").unwrap_or_default())),
+            topic_prefix: env::var("MQTT_TOPIC_PREFIX")
+                .unwrap_or_else(|_| "weathervane".to_string()),
+            sensor_topics,
+            sensor_wait: Duration::from_secs(parse_env("MQTT_SENSOR_WAIT").unwrap_or(2)),
+            keep_alive: Duration::from_secs(parse_env("MQTT_KEEP_ALIVE").unwrap_or(30)),
+            reconnect_delay: Duration::from_millis(500),
+            max_reconnect_delay: Duration::from_secs(30),
+        })
+    }
+
+    fn options(&self, client_id: &str) -> MqttOptions {
+        let mut options = MqttOptions::new(client_id, &self.host, self.port);
+        options.set_keep_alive(self.keep_alive.as_secs().max(5) as u16);
+
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username, password);
+        }
+
+        options
+    }
+
+    fn status_topic(&self) -> String {
+        format!("{}/status", self.topic_prefix)
+    }
+
+    fn forecast_topic(&self, location_slug: &str) -> String {
+        format!("{}/forecast/{}", self.topic_prefix, location_slug)
+    }
+}
+
+/// A measurement that can be taken from a sensor topic.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SensorField {
+    /// In degrees Celsius.
+    Temperature,
+
+    /// In percent.
+    Humidity,
+
+    /// In hectopascals.
+    Pressure,
+}
+
+impl SensorField {
+    /// The key to look for when a topic's payload is a JSON object.
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Temperature => "temperature",
+            Self::Humidity => "humidity",
+            Self::Pressure => "pressure",
+        }
+    }
+}
+
+impl FromStr for SensorField {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_lowercase().as_str() {
+            "temperature" | "temp" => Ok(Self::Temperature),
+            "humidity" => Ok(Self::Humidity),
+            "pressure" => Ok(Self::Pressure),
+            _ => Err(format!("Unknown field \"{}\".", raw.trim())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SensorTopic {
+    pub field: SensorField,
+    pub topic: String,
+}
+
+impl FromStr for SensorTopic {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut parts = raw.splitn(2, '=');
+        match (parts.next(), parts.next().map(str::trim)) {
+            (Some(field), Some(topic)) if !topic.is_empty() => Ok(Self {
+                field: field.parse()?,
+                topic: topic.to_string(),
+            }),
+            _ => Err("Expected field=topic.".to_string()),
+        }
+    }
+}
+
+impl SensorTopic {
+    /// Parse a payload, which is either a bare number or a JSON object with the field's key, as
+    /// published by Zigbee2MQTT and similar bridges.
+    fn parse_payload(&self, payload: &[u8]) -> Option<f32> {
+        let payload = std::str::from_utf8(payload).ok()?.trim();
+
+        if let Ok(value) = payload.parse() {
+            return Some(value);
+        }
+
+        serde_json::from_str::<serde_json::Value>(payload)
+            .ok()?
+            .get(self.field.key())?
+            .as_f64()
+            .map(|value| value as f32)
+    }
+}
+
+/// Readings received from sensor topics.
+#[derive(Debug, Default, PartialEq)]
+pub struct SensorValues {
+    /// In degrees Celsius.
+    pub temperature: Option<f32>,
+    pub humidity: Option<f32>,
+
+    /// In hectopascals.
+    pub pressure: Option<f32>,
+}
+
+impl SensorValues {
+    fn set(&mut self, field: SensorField, value: f32) {
+        match field {
+            SensorField::Temperature => self.temperature = Some(value),
+            SensorField::Humidity => self.humidity = Some(value),
+            SensorField::Pressure => self.pressure = Some(value),
+        }
+    }
+
+    fn is_set(&self, field: SensorField) -> bool {
+        match field {
+            SensorField::Temperature => self.temperature.is_some(),
+            SensorField::Humidity => self.humidity.is_some(),
+            SensorField::Pressure => self.pressure.is_some(),
+        }
+    }
+
+    /// Replace the reported conditions with the local readings.
+    pub fn apply(&self, state: &mut WeatherState) {
+        if let Some(temperature) = self.temperature {
+            state.temp = Some(Temperature::from_celsius(temperature));
+        }
+        if let Some(humidity) = self.humidity {
+            state.humidity = Some(humidity.round().clamp(0., 100.) as u8);
+        }
+        if let Some(pressure) = self.pressure {
+            state.pressure = Some(Pressure::from_hectopascals(pressure));
+        }
+    }
+}
+
+/// The result of a refresh, published to `{prefix}/status`.
+#[derive(Debug, Serialize)]
+pub struct Status {
+    /// `ok` or `error`. The last will sets it to `offline` if we drop off without disconnecting.
+    pub state: &'static str,
+    pub refreshed_at: i64,
+    pub error: Option<String>,
+    pub locations: Vec<LocationStatus>,
+    pub radar: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocationStatus {
+    pub name: String,
+    pub fetched_at: Option<i64>,
+    pub stale: bool,
+}
+
+impl Status {
+    pub fn new(weather: &Weather) -> Self {
+        Self {
+            state: "ok",
+            refreshed_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+            error: None,
+            locations: weather
+                .locations
+                .iter()
+                .map(|location| LocationStatus {
+                    name: location.location.name.clone(),
+                    fetched_at: location
+                        .report
+                        .as_ref()
+                        .map(|report| report.fetched_at.unix_timestamp()),
+                    stale: match &location.report {
+                        Some(report) => report.stale,
+                        None => true,
+                    },
+                })
+                .collect(),
+            radar: weather.radar_map.is_some(),
+        }
+    }
+
+    pub fn set_error(&mut self, error: &str) {
+        self.state = "error";
+        self.error = Some(error.to_string());
+    }
+}
+
+/// A location's report in metric units, published to `{prefix}/forecast/{location}`.
+#[derive(Debug, Serialize)]
+struct ForecastPayload<'a> {
+    location: &'a str,
+    latitude: f64,
+    longitude: f64,
+    fetched_at: i64,
+    stale: bool,
+    current: StatePayload,
+    hourly: Vec<StatePayload>,
+    daily: Vec<StatePayload>,
+}
+
+#[derive(Debug, Serialize)]
+struct StatePayload {
+    time: i64,
+    temperature_c: Option<f32>,
+    temperature_min_c: Option<f32>,
+    temperature_max_c: Option<f32>,
+    humidity: Option<u8>,
+    pressure_hpa: Option<f32>,
+    wind_speed_ms: Option<f32>,
+    wind_gust_ms: Option<f32>,
+    wind_direction: Option<u16>,
+    precipitation_mm: Option<f32>,
+    clouds: Option<u8>,
+    visibility_m: Option<u32>,
+
+    /// OpenWeather condition code.
+    condition: Option<u16>,
+    provider: &'static str,
+}
+
+impl From<&WeatherState> for StatePayload {
+    fn from(state: &WeatherState) -> Self {
+        Self {
+            time: state.time.unix_timestamp(),
+            temperature_c: state.temp.as_ref().map(Temperature::celsius),
+            temperature_min_c: state.temp_min.as_ref().map(Temperature::celsius),
+            temperature_max_c: state.temp_max.as_ref().map(Temperature::celsius),
+            humidity: state.humidity,
+            pressure_hpa: state.pressure.as_ref().map(Pressure::hectopascals),
+            wind_speed_ms: state.wind.as_ref().map(|wind| wind.speed),
+            wind_gust_ms: state.wind.as_ref().and_then(|wind| wind.gust),
+            wind_direction: state.wind.as_ref().and_then(|wind| wind.direction),
+            precipitation_mm: state
+                .precipitation
+                .as_ref()
+                .map(|precipitation| precipitation.millimetres()),
+            clouds: state.clouds,
+            visibility_m: state.visibility,
+            condition: state.condition.as_ref().map(|condition| condition.code()),
+            provider: state.provider.id(),
+        }
+    }
+}
+
+/// A connection to the broker. The event loop runs in a background task, which reconnects with
+/// backoff and resubscribes after each reconnection.
+pub struct Mqtt {
+    config: MqttConfig,
+    client: AsyncClient,
+    messages: mpsc::UnboundedReceiver<(String, Vec<u8>)>,
+    event_loop: JoinHandle<()>,
+}
+
+impl Mqtt {
+    pub fn connect(config: MqttConfig) -> Self {
+        let mut options = config.options(&config.client_id);
+        let mut last_will = LastWill::new(
+            config.status_topic(),
+            QoS::AtLeastOnce,
+            r#"{"state":"offline"}"#,
+        );
+        last_will.retain = true;
+        options.set_last_will(last_will);
+
+        let (client, event_loop) = AsyncClient::new(options, 10);
+        let (sender, messages) = mpsc::unbounded_channel();
+
+        let event_loop = tokio::spawn(run_event_loop(
+            event_loop,
+            client.clone(),
+            config
+                .sensor_topics
+                .iter()
+                .map(|sensor| sensor.topic.clone())
+                .collect(),
+            sender,
+            (config.reconnect_delay, config.max_reconnect_delay),
+        ));
+
+        Self {
+            config,
+            client,
+            messages,
+            event_loop,
+        }
+    }
+
+    /// Collect readings from the sensor topics, waiting up to `sensor_wait` for all of them.
+    pub async fn sensor_values(&mut self) -> SensorValues {
+        let mut values = SensorValues::default();
+        let topics = &self.config.sensor_topics;
+        let messages = &mut self.messages;
+
+        if topics.is_empty() {
+            return values;
+        }
+
+        let _ = tokio::time::timeout(self.config.sensor_wait, async {
+            while let Some((topic, payload)) = messages.recv().await {
+                for sensor in topics.iter().filter(|sensor| sensor.topic == topic) {
+                    match sensor.parse_payload(&payload) {
+                        Some(value) => values.set(sensor.field, value),
+                        None => eprintln!("Ignoring unreadable MQTT payload on {}", topic),
+                    }
+                }
+
+                if topics.iter().all(|sensor| values.is_set(sensor.field)) {
+                    break;
+                }
+            }
+        })
+        .await;
+
+        values
+    }
+
+    /// Publish each location's report as a retained message.
+    pub async fn publish_weather(&self, weather: &Weather) -> Result<(), String> {
+        for location in &weather.locations {
+            let report = match &location.report {
+                Some(report) => report,
+                None => continue,
+            };
+
+            let payload = serde_json::to_vec(&ForecastPayload {
+                location: &location.location.name,
+                latitude: location.location.latitude,
+                longitude: location.location.longitude,
+                fetched_at: report.fetched_at.unix_timestamp(),
+                stale: report.stale,
+                current: (&report.value.current).into(),
+                hourly: report.value.hourly.iter().map(Into::into).collect(),
+                daily: report.value.daily.iter().map(Into::into).collect(),
+            })
+            .map_err(|e| e.to_string())?;
+
+            self.publish(
+                self.config.forecast_topic(&location.location.slug()),
+                payload,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn publish_status(&self, status: &Status) -> Result<(), String> {
+        let payload = serde_json::to_vec(status).map_err(|e| e.to_string())?;
+        self.publish(self.config.status_topic(), payload).await
+    }
+
+    async fn publish(&self, topic: String, payload: Vec<u8>) -> Result<(), String> {
+        self.client
+            .publish(topic, QoS::AtLeastOnce, true, payload)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Disconnect cleanly, so that the last will isn't published, after sending anything queued.
+    pub async fn disconnect(self) {
+        if let Err(e) = self.client.disconnect().await {
+            eprintln!("Unable to disconnect from MQTT broker: {}", e);
+        }
+
+        if tokio::time::timeout(Duration::from_secs(5), self.event_loop)
+            .await
+            .is_err()
+        {
+            eprintln!("Timed out disconnecting from MQTT broker.");
+        }
+    }
+}
+
+/// Subscribe to a topic for as long as the returned receiver is used, for the listeners. They
+/// connect with a client ID of their own, so that they don't disconnect the refresh's client.
+pub fn subscribe(
+    config: &MqttConfig,
+    client_id: &str,
+    topic: &str,
+) -> mpsc::UnboundedReceiver<(String, Vec<u8>)> {
+    let (client, event_loop) = AsyncClient::new(config.options(client_id), 10);
+    let (sender, messages) = mpsc::unbounded_channel();
+
+    tokio::spawn(run_event_loop(
+        event_loop,
+        client,
+        vec![topic.to_string()],
+        sender,
+        (config.reconnect_delay, config.max_reconnect_delay),
+    ));
+
+    messages
+}
+
+async fn run_event_loop(
+    mut event_loop: EventLoop,
+    client: AsyncClient,
+    topics: Vec<String>,
+    messages: mpsc::UnboundedSender<(String, Vec<u8>)>,
+    (base_delay, max_delay): (Duration, Duration),
+) {
+    let mut delay = base_delay;
+
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                delay = base_delay;
+                for topic in &topics {
+                    if let Err(e) = client.subscribe(topic.as_str(), QoS::AtMostOnce).await {
+                        eprintln!("Unable to subscribe to {}: {}", topic, e);
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let _ = messages.send((publish.topic, publish.payload.to_vec()));
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => return,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("MQTT connection error, reconnecting in {:?}: {}", delay, e);
+                tokio::time::delay_for(delay).await;
+                delay = (delay * 2).min(max_delay);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sensor_topic_test() {
+        assert_eq!(
+            Ok(SensorTopic {
+                field: SensorField::Temperature,
+                topic: "sensors/porch/temperature".to_string(),
+            }),
+            "temperature = sensors/porch/temperature".parse(),
+        );
+        assert!("temperature=".parse::<SensorTopic>().is_err());
+        assert!("wind=sensors/wind".parse::<SensorTopic>().is_err());
+    }
+
+    #[test]
+    fn parse_payload_test() {
+        let topic = SensorTopic {
+            field: SensorField::Humidity,
+            topic: "zigbee2mqtt/porch".to_string(),
+        };
+
+        assert_eq!(Some(45.5), topic.parse_payload(b" 45.5\n"));
+        assert_eq!(
+            Some(61.),
+            topic.parse_payload(br#"{"battery":97,"humidity":61,"temperature":4.2}"#)
+        );
+        assert_eq!(None, topic.parse_payload(br#"{"temperature":4.2}"#));
+        assert_eq!(None, topic.parse_payload(b"unavailable"));
+    }
+
+    /// Runs against a real broker given by `MQTT_TEST_HOST` (default `localhost`), eg. a local
+    /// Mosquitto: `cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn broker_test() {
+        let host = env::var("MQTT_TEST_HOST").unwrap_or_else(|_| "localhost".to_string());
+        let prefix = format!("weathervane-test-{}", std::process::id());
+        let config = |client_id: &str, sensor_topics| MqttConfig {
+            host: host.clone(),
+            port: 1883,
+            client_id: format!("{}-{}", prefix, client_id),
+            credentials: None,
+            topic_prefix: prefix.clone(),
+            sensor_topics,
+            sensor_wait: Duration::from_secs(5),
+            keep_alive: Duration::from_secs(5),
+            reconnect_delay: Duration::from_millis(100),
+            max_reconnect_delay: Duration::from_secs(1),
+        };
+
+        // Publish a retained sensor reading, then read it back as a new client would.
+        let sensor_topic = format!("{}/sensors/porch", prefix);
+        let publisher = Mqtt::connect(config("publisher", Vec::new()));
+        publisher
+            .publish(
+                sensor_topic.clone(),
+                br#"{"temperature":-3.5,"humidity":80}"#.to_vec(),
+            )
+            .await
+            .unwrap();
+        publisher
+            .publish_status(&Status::new(&Weather {
+                locations: Vec::new(),
+                radar_map: None,
+                radar_station: "CASBV".to_string(),
+                air_quality: None,
+                alerts: Vec::new(),
+            }))
+            .await
+            .unwrap();
+        publisher.disconnect().await;
+
+        let mut subscriber = Mqtt::connect(config(
+            "subscriber",
+            vec![
+                SensorTopic {
+                    field: SensorField::Temperature,
+                    topic: sensor_topic.clone(),
+                },
+                SensorTopic {
+                    field: SensorField::Humidity,
+                    topic: sensor_topic.clone(),
+                },
+            ],
+        ));
+        assert_eq!(
+            SensorValues {
+                temperature: Some(-3.5),
+                humidity: Some(80.),
+                pressure: None,
+            },
+            subscriber.sensor_values().await,
+        );
+
+        // Clear the retained messages.
+        subscriber.publish(sensor_topic, Vec::new()).await.unwrap();
+        subscriber
+            .publish(subscriber.config.status_topic(), Vec::new())
+            .await
+            .unwrap();
+        subscriber.disconnect().await;
+    }
+}
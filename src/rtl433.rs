@@ -0,0 +1,513 @@
+//! Observations from cheap 433 MHz weather sensors, as decoded by `rtl_433 -F json` and read from
+//! a pipe, a file or an MQTT topic. Readings from each configured sensor are combined, and the
+//! rain counter is turned into the past hour's rain.
+
+use std::collections::VecDeque;
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+
+use crate::cache::{Cache, Fetched};
+use crate::config::parse_env;
+use crate::mqtt;
+use crate::station::Observation;
+use crate::weather::Temperature;
+
+const STATE_CACHE: &str = "rtl_433.json";
+
+const METRES_PER_SECOND_PER_KM_H: f32 = 1. / 3.6;
+const METRES_PER_SECOND_PER_MPH: f32 = 0.447_04;
+const MILLIMETRES_PER_INCH: f32 = 25.4;
+
+pub struct Rtl433Config {
+    pub input: Input,
+
+    /// Only events from these sensors are used, since neighbours' sensors are usually in range.
+    pub sensors: Vec<SensorFilter>,
+
+    /// Ignore the stored state once the latest event is this old.
+    pub max_age: Duration,
+}
+
+impl Rtl433Config {
+    /// Read the configuration from `RTL433_SENSORS` (rtl_433 is disabled if it isn't set),
+    /// `RTL433_INPUT` and `RTL433_MAX_AGE` (in minutes, default 15).
+    ///
+    /// `RTL433_SENSORS` is a `;`-separated list of `model` or `model:id` entries, eg.
+    /// `Acurite-5n1:1234;LaCrosse-TX141THBv2`. `RTL433_INPUT` is `-` for standard input (the
+    /// default), `mqtt:<topic>` for rtl_433's MQTT events, or the path of a file or named pipe.
+    pub fn from_env() -> Option<Self> {
+        let sensors: Vec<SensorFilter> = env::var("RTL433_SENSORS")
+            .ok()?
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .filter_map(|entry| match entry.parse() {
+                Ok(sensor) => Some(sensor),
+                Err(e) => {
+                    eprintln!("Ignoring rtl_433 sensor \"{}\": {}", entry, e);
+                    None
+                }
+            })
+            .collect();
+
+        if sensors.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            input: env::var("RTL433_INPUT")
+                .map(|input| Input::from(input.as_str()))
+                .unwrap_or(Input::Stdin),
+            sensors,
+            max_age: Duration::from_secs(60 * parse_env("RTL433_MAX_AGE").unwrap_or(15)),
+        })
+    }
+
+    fn accepts(&self, event: &Map<String, Value>) -> bool {
+        self.sensors.iter().any(|sensor| sensor.matches(event))
+    }
+}
+
+/// Where rtl_433's JSON events are read from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /// Piped in, eg. `rtl_433 -F json -M time:unix | weathervane listen`.
+    Stdin,
+
+    /// A file that rtl_433 appends to, or a named pipe. Either is followed as it grows.
+    File(PathBuf),
+
+    /// An MQTT topic that rtl_433 publishes events to, eg. `rtl_433/+/events`. The broker is the
+    /// one configured by `MQTT_HOST`.
+    Mqtt(String),
+}
+
+impl From<&str> for Input {
+    fn from(raw: &str) -> Self {
+        match raw.trim() {
+            "-" => Self::Stdin,
+            raw => match raw.strip_prefix("mqtt:") {
+                Some(topic) => Self::Mqtt(topic.to_string()),
+                None => Self::File(PathBuf::from(raw)),
+            },
+        }
+    }
+}
+
+/// A sensor's model, as named by rtl_433, and optionally its ID. IDs of most sensors change when
+/// their batteries are replaced, so matching on the model alone is sometimes easier.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SensorFilter {
+    pub model: String,
+    pub id: Option<String>,
+}
+
+impl SensorFilter {
+    fn matches(&self, event: &Map<String, Value>) -> bool {
+        if event.get("model").and_then(Value::as_str) != Some(self.model.as_str()) {
+            return false;
+        }
+
+        match (&self.id, event.get("id")) {
+            (None, _) => true,
+            (Some(expected), Some(Value::Number(id))) => *expected == id.to_string(),
+            (Some(expected), Some(Value::String(id))) => expected == id,
+            (Some(_), _) => false,
+        }
+    }
+}
+
+impl FromStr for SensorFilter {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut parts = raw.splitn(2, ':');
+        let model = parts.next().map(str::trim).unwrap_or("");
+        let id = parts.next().map(str::trim);
+
+        if model.is_empty() || id == Some("") {
+            return Err("Expected model or model:id.".to_string());
+        }
+
+        Ok(Self {
+            model: model.to_string(),
+            id: id.map(str::to_string),
+        })
+    }
+}
+
+/// The combined readings of the configured sensors.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Rtl433State {
+    /// When the latest event was received, as a Unix timestamp.
+    pub observed_at: Option<i64>,
+
+    /// In degrees Celsius.
+    pub temperature: Option<f32>,
+    pub humidity: Option<u8>,
+
+    /// In metres per second.
+    pub wind_speed: Option<f32>,
+    pub wind_gust: Option<f32>,
+    pub wind_direction: Option<u16>,
+
+    /// Readings of the rain counter, in millimetres, over a little more than the past hour.
+    pub rain_counter: VecDeque<(i64, f32)>,
+}
+
+impl Rtl433State {
+    /// Fold in an event. Sensors only report some fields in each message (the Acurite 5-in-1
+    /// alternates between two), so fields an event doesn't have are left alone.
+    fn update(&mut self, event: &Map<String, Value>) {
+        let number = |key: &str| -> Option<f32> {
+            event
+                .get(key)
+                .and_then(Value::as_f64)
+                .map(|value| value as f32)
+                .filter(|value| value.is_finite())
+        };
+
+        // With `-M time:unix`; otherwise the time is in local time, so use the time received.
+        let time = match event.get("time") {
+            Some(Value::Number(time)) => time.as_i64(),
+            Some(Value::String(time)) => time.parse().ok(),
+            _ => None,
+        }
+        .unwrap_or_else(|| time::OffsetDateTime::now_utc().unix_timestamp());
+
+        self.observed_at = Some(time);
+
+        if let Some(temperature) = number("temperature_C")
+            .or_else(|| number("temperature_F").map(|f| Temperature::from_fahrenheit(f).celsius()))
+        {
+            self.temperature = Some(temperature);
+        }
+        if let Some(humidity) = number("humidity") {
+            self.humidity = Some(humidity.round().clamp(0., 100.) as u8);
+        }
+        if let Some(speed) = wind(&number, "wind_avg") {
+            self.wind_speed = Some(speed);
+        }
+        if let Some(gust) = wind(&number, "wind_max") {
+            self.wind_gust = Some(gust);
+        }
+        if let Some(direction) = number("wind_dir_deg") {
+            self.wind_direction = Some(direction.round() as u16 % 360);
+        }
+
+        if let Some(counter) = number("rain_mm")
+            .or_else(|| number("rain_in").map(|inches| inches * MILLIMETRES_PER_INCH))
+        {
+            self.rain_counter.push_back((time, counter));
+        }
+
+        // Keep the latest reading from before the past hour, to measure from.
+        while matches!(self.rain_counter.get(1), Some(&(counter_at, _)) if counter_at <= time - 3600)
+        {
+            self.rain_counter.pop_front();
+        }
+    }
+
+    /// Rain over the past hour, in millimetres, from the increases of the rain counter. The
+    /// counter goes back to zero when the sensor's batteries are replaced.
+    pub fn hourly_rain(&self) -> Option<f32> {
+        if self.rain_counter.len() < 2 {
+            return None;
+        }
+
+        Some(
+            self.rain_counter
+                .iter()
+                .zip(self.rain_counter.iter().skip(1))
+                .map(|(&(_, previous), &(_, counter))| {
+                    if counter >= previous {
+                        counter - previous
+                    } else {
+                        counter
+                    }
+                })
+                .sum(),
+        )
+    }
+
+    /// The readings as a station observation, to apply in the same way.
+    pub fn observation(&self) -> Option<Observation> {
+        Some(Observation {
+            time: self.observed_at?,
+            temperature: self.temperature,
+            humidity: self.humidity,
+            pressure: None,
+            wind_speed: self.wind_speed,
+            wind_gust: self.wind_gust,
+            wind_direction: self.wind_direction,
+            precipitation: self.hourly_rain(),
+        })
+    }
+}
+
+/// A wind speed reported in any of rtl_433's units, in metres per second.
+fn wind(number: &impl Fn(&str) -> Option<f32>, prefix: &str) -> Option<f32> {
+    number(&format!("{}_m_s", prefix))
+        .or_else(|| number(&format!("{}_km_h", prefix)).map(|kmh| kmh * METRES_PER_SECOND_PER_KM_H))
+        .or_else(|| number(&format!("{}_mi_h", prefix)).map(|mph| mph * METRES_PER_SECOND_PER_MPH))
+}
+
+/// The stored readings, unless the latest event is older than `max_age`.
+pub fn latest(cache: &Cache, max_age: Duration) -> Option<Fetched<Observation>> {
+    let cached = cache.load(STATE_CACHE).ok()?;
+    let state: Rtl433State = serde_json::from_slice(&cached.value).ok()?;
+    let observation = state.observation()?;
+
+    let observed_at = time::OffsetDateTime::from_unix_timestamp(observation.time);
+    if time::OffsetDateTime::now_utc() - observed_at >= max_age {
+        return None;
+    }
+
+    Some(Fetched {
+        value: observation,
+        fetched_at: observed_at,
+        stale: false,
+    })
+}
+
+/// Read events until the input ends, storing the state after each one from a configured sensor.
+pub async fn listen(config: Rtl433Config, cache: Cache) -> Result<(), String> {
+    // Carry on from the stored state, so that the rain counter's history survives a restart.
+    let mut receiver = Receiver {
+        state: cache
+            .load(STATE_CACHE)
+            .ok()
+            .and_then(|cached| serde_json::from_slice(&cached.value).ok())
+            .unwrap_or_default(),
+        config: &config,
+        cache: &cache,
+    };
+
+    match &config.input {
+        Input::Stdin => {
+            eprintln!("Reading rtl_433 events from standard input");
+            receiver
+                .read_lines(BufReader::new(tokio::io::stdin()), false)
+                .await
+        }
+        Input::File(path) => {
+            let file = tokio::fs::File::open(path)
+                .await
+                .map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+            eprintln!("Reading rtl_433 events from {}", path.display());
+            receiver.read_lines(BufReader::new(file), true).await
+        }
+        Input::Mqtt(topic) => {
+            let mqtt_config = mqtt::MqttConfig::from_env()
+                .ok_or("MQTT_HOST must be set to read rtl_433 events from MQTT.")?;
+            let mut messages = mqtt::subscribe(
+                &mqtt_config,
+                &format!("{}-rtl_433", mqtt_config.client_id),
+                topic,
+            );
+            eprintln!("Reading rtl_433 events from MQTT topic {}", topic);
+
+            while let Some((_, payload)) = messages.recv().await {
+                receiver.receive(&payload);
+            }
+            Err("MQTT subscription ended.".to_string())
+        }
+    }
+}
+
+struct Receiver<'a> {
+    state: Rtl433State,
+    config: &'a Rtl433Config,
+    cache: &'a Cache,
+}
+
+impl Receiver<'_> {
+    /// Receive an event per line. A followed file is polled for more once it's been read to the
+    /// end; otherwise the end of the input is an error, since rtl_433 has stopped.
+    async fn read_lines(
+        &mut self,
+        mut reader: impl AsyncBufRead + Unpin,
+        follow: bool,
+    ) -> Result<(), String> {
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            let len = reader
+                .read_line(&mut line)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            if len == 0 {
+                if !follow {
+                    return Err("rtl_433 input ended.".to_string());
+                }
+                tokio::time::delay_for(Duration::from_secs(1)).await;
+                continue;
+            }
+
+            if !line.trim().is_empty() {
+                self.receive(line.as_bytes());
+            }
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) {
+        let event: Map<String, Value> = match serde_json::from_slice(data) {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("Ignoring unreadable rtl_433 event: {}", e);
+                return;
+            }
+        };
+
+        if !self.config.accepts(&event) {
+            return;
+        }
+
+        self.state.update(&event);
+
+        if let Err(e) = serde_json::to_vec(&self.state)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                self.cache
+                    .store(STATE_CACHE, &data, time::OffsetDateTime::now_utc())
+                    .map_err(|e| e.to_string())
+            })
+        {
+            eprintln!("Unable to store rtl_433 state: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Sample events in rtl_433's JSON output format, one per line.
+    const EVENTS: &str = include_str!("../tests/fixtures/rtl_433/events.jsonl");
+
+    fn config() -> Rtl433Config {
+        Rtl433Config {
+            input: Input::Stdin,
+            sensors: vec![
+                "Acurite-5n1:1234".parse().unwrap(),
+                "LaCrosse-TX141THBv2".parse().unwrap(),
+            ],
+            max_age: Duration::from_secs(900),
+        }
+    }
+
+    fn event(json: &str) -> Map<String, Value> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn sensor_filter_test() {
+        assert_eq!(
+            Ok(SensorFilter {
+                model: "Acurite-5n1".to_string(),
+                id: Some("1234".to_string()),
+            }),
+            " Acurite-5n1:1234".parse(),
+        );
+        assert!(":1234".parse::<SensorFilter>().is_err());
+        assert!("Acurite-5n1:".parse::<SensorFilter>().is_err());
+
+        let config = config();
+        assert!(config.accepts(&event(r#"{"model":"Acurite-5n1","id":1234}"#)));
+        assert!(!config.accepts(&event(r#"{"model":"Acurite-5n1","id":999}"#)));
+        assert!(config.accepts(&event(r#"{"model":"LaCrosse-TX141THBv2","id":"55"}"#)));
+        assert!(!config.accepts(&event(r#"{"model":"Nexus-TH","id":1234}"#)));
+    }
+
+    #[test]
+    fn input_test() {
+        assert_eq!(Input::Stdin, Input::from("-"));
+        assert_eq!(
+            Input::Mqtt("rtl_433/+/events".to_string()),
+            Input::from("mqtt:rtl_433/+/events")
+        );
+        assert_eq!(
+            Input::File(PathBuf::from("/run/rtl_433.json")),
+            Input::from("/run/rtl_433.json")
+        );
+    }
+
+    #[test]
+    fn update_test() {
+        let config = config();
+        let mut state = Rtl433State::default();
+        for line in EVENTS.lines() {
+            let event = event(line);
+            if config.accepts(&event) {
+                state.update(&event);
+            }
+        }
+
+        let observation = state.observation().unwrap();
+        assert_eq!(1_608_213_660, observation.time);
+
+        // The La Crosse's temperature came after the Acurite's.
+        assert_eq!(Some(-2.1), observation.temperature);
+        assert_eq!(Some(80), observation.humidity);
+
+        assert!((8. / 3.6 - observation.wind_speed.unwrap()).abs() < 0.001);
+        assert_eq!(Some(248), observation.wind_direction);
+        assert!((0.02 * 25.4 - observation.precipitation.unwrap()).abs() < 0.001);
+    }
+
+    #[test]
+    fn hourly_rain_test() {
+        let mut state = Rtl433State::default();
+        for minute in 0..90 {
+            // The counter goes back to zero halfway through, as if the batteries were replaced.
+            let counter = if minute < 45 {
+                100. + 0.1 * minute as f32
+            } else {
+                0.1 * (minute - 44) as f32
+            };
+            state.update(&event(&format!(
+                r#"{{"time":{},"model":"Fineoffset-WH65B","rain_mm":{}}}"#,
+                1_608_210_000 + 60 * minute,
+                counter
+            )));
+        }
+
+        // The past hour, and the reading before it.
+        assert_eq!(61, state.rain_counter.len());
+        assert!((6. - state.hourly_rain().unwrap()).abs() < 0.01);
+
+        state.rain_counter.truncate(1);
+        assert_eq!(None, state.hourly_rain());
+    }
+
+    #[tokio::test]
+    async fn read_lines_test() {
+        let dir = env::temp_dir().join(format!("weathervane-rtl433-test-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let config = config();
+        let mut receiver = Receiver {
+            state: Rtl433State::default(),
+            config: &config,
+            cache: &cache,
+        };
+
+        let result = receiver
+            .read_lines(BufReader::new(EVENTS.as_bytes()), false)
+            .await;
+        assert_eq!(Err("rtl_433 input ended.".to_string()), result);
+
+        let stored: Rtl433State =
+            serde_json::from_slice(&cache.load(STATE_CACHE).unwrap().value).unwrap();
+        assert_eq!(receiver.state, stored);
+        assert_eq!(Some(80), stored.humidity);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
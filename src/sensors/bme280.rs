@@ -0,0 +1,251 @@
+//! Bosch BME280 temperature, humidity and pressure sensors.
+
+use std::thread;
+use std::time::Duration;
+
+use super::i2c::I2cBus;
+use super::Reading;
+use crate::weather::{Pressure, Temperature};
+
+/// The address with SDO pulled low. It's `0x77` when pulled high.
+pub const DEFAULT_ADDRESS: u16 = 0x76;
+
+const CHIP_ID: u8 = 0x60;
+
+const REGISTER_CHIP_ID: u8 = 0xD0;
+const REGISTER_CALIBRATION_1: u8 = 0x88;
+const REGISTER_CALIBRATION_2: u8 = 0xE1;
+const REGISTER_CTRL_HUM: u8 = 0xF2;
+const REGISTER_CTRL_MEAS: u8 = 0xF4;
+const REGISTER_DATA: u8 = 0xF7;
+
+/// 1x humidity oversampling.
+const CTRL_HUM: u8 = 0b001;
+
+/// 1x temperature and pressure oversampling, in forced mode (a single measurement, then sleep).
+const CTRL_MEAS: u8 = (0b001 << 5) | (0b001 << 2) | 0b01;
+
+/// The longest a measurement takes with 1x oversampling.
+const MEASUREMENT_DURATION: Duration = Duration::from_millis(10);
+
+pub struct Bme280 {
+    bus: Box<dyn I2cBus>,
+    address: u16,
+}
+
+impl Bme280 {
+    pub fn new(bus: Box<dyn I2cBus>, address: u16) -> Self {
+        Self { bus, address }
+    }
+
+    pub fn read(&mut self) -> Result<Reading, String> {
+        let mut chip_id = [0];
+        self.read_registers(REGISTER_CHIP_ID, &mut chip_id)?;
+        if chip_id[0] != CHIP_ID {
+            return Err(format!("Unexpected chip ID {:#04x}.", chip_id[0]));
+        }
+
+        let mut calibration_1 = [0; 26];
+        let mut calibration_2 = [0; 7];
+        self.read_registers(REGISTER_CALIBRATION_1, &mut calibration_1)?;
+        self.read_registers(REGISTER_CALIBRATION_2, &mut calibration_2)?;
+        let calibration = Calibration::decode(&calibration_1, &calibration_2);
+
+        // The humidity setting only takes effect after writing `ctrl_meas`.
+        self.write_register(REGISTER_CTRL_HUM, CTRL_HUM)?;
+        self.write_register(REGISTER_CTRL_MEAS, CTRL_MEAS)?;
+
+        thread::sleep(MEASUREMENT_DURATION);
+
+        let mut data = [0; 8];
+        self.read_registers(REGISTER_DATA, &mut data)?;
+        // Pressure and temperature are 20 bits, most significant byte first.
+        let adc_20 = |bytes: &[u8]| {
+            (u32::from(bytes[0]) << 12) | (u32::from(bytes[1]) << 4) | (u32::from(bytes[2]) >> 4)
+        };
+        let adc_pressure = adc_20(&data[0..3]);
+        let adc_temperature = adc_20(&data[3..6]);
+        let adc_humidity = u32::from(u16::from_be_bytes([data[6], data[7]]));
+
+        let (temperature, t_fine) = calibration.temperature(adc_temperature);
+
+        Ok(Reading {
+            sensor: format!("BME280@{:#04x}", self.address),
+            temperature,
+            humidity: Some(calibration.humidity(adc_humidity, t_fine).round() as u8),
+            pressure: calibration.pressure(adc_pressure, t_fine),
+            time: time::OffsetDateTime::now_utc(),
+        })
+    }
+
+    /// Fill the buffer from consecutive registers.
+    fn read_registers(&mut self, register: u8, buffer: &mut [u8]) -> Result<(), String> {
+        self.bus
+            .write_read(self.address, &[register], buffer)
+            .map_err(|e| e.to_string())
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), String> {
+        self.bus
+            .write(self.address, &[register, value])
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// The factory calibration stored in each sensor's non-volatile memory.
+struct Calibration {
+    t1: f64,
+    t2: f64,
+    t3: f64,
+    p: [f64; 9],
+    h1: f64,
+    h2: f64,
+    h3: f64,
+    h4: f64,
+    h5: f64,
+    h6: f64,
+}
+
+impl Calibration {
+    /// Decode the registers from `0x88` to `0xA1`, and from `0xE1` to `0xE7`.
+    fn decode(block_1: &[u8; 26], block_2: &[u8; 7]) -> Self {
+        let unsigned = |i: usize| f64::from(u16::from_le_bytes([block_1[i], block_1[i + 1]]));
+        let signed = |i: usize| f64::from(i16::from_le_bytes([block_1[i], block_1[i + 1]]));
+
+        let mut p = [unsigned(6); 9];
+        for (i, p) in p.iter_mut().enumerate().skip(1) {
+            *p = signed(6 + 2 * i);
+        }
+
+        Self {
+            t1: unsigned(0),
+            t2: signed(2),
+            t3: signed(4),
+            p,
+            h1: f64::from(block_1[25]),
+            h2: f64::from(i16::from_le_bytes([block_2[0], block_2[1]])),
+            h3: f64::from(block_2[2]),
+            // These two are 12-bit signed values sharing a nibble.
+            h4: f64::from((i16::from(block_2[3] as i8) << 4) | i16::from(block_2[4] & 0x0F)),
+            h5: f64::from((i16::from(block_2[5] as i8) << 4) | i16::from(block_2[4] >> 4)),
+            h6: f64::from(block_2[6] as i8),
+        }
+    }
+
+    /// The compensated temperature, along with the fine resolution value which the pressure and
+    /// humidity compensation depend on. The formulas are from the datasheet.
+    fn temperature(&self, adc: u32) -> (Temperature, f64) {
+        let adc = f64::from(adc);
+        let var1 = (adc / 16384. - self.t1 / 1024.) * self.t2;
+        let var2 = (adc / 131_072. - self.t1 / 8192.).powi(2) * self.t3;
+        let t_fine = var1 + var2;
+
+        (Temperature::from_celsius((t_fine / 5120.) as f32), t_fine)
+    }
+
+    /// The compensated station pressure, which isn't adjusted to sea level.
+    fn pressure(&self, adc: u32, t_fine: f64) -> Option<Pressure> {
+        let p = &self.p;
+
+        let var1 = t_fine / 2. - 64000.;
+        let var2 = var1 * var1 * p[5] / 32768. + var1 * p[4] * 2.;
+        let var2 = var2 / 4. + p[3] * 65536.;
+        let var1 = (p[2] * var1 * var1 / 524_288. + p[1] * var1) / 524_288.;
+        let var1 = (1. + var1 / 32768.) * p[0];
+
+        // Avoid dividing by zero if the calibration is blank.
+        if var1 == 0. {
+            return None;
+        }
+
+        let pascals = 1_048_576. - f64::from(adc);
+        let pascals = (pascals - var2 / 4096.) * 6250. / var1;
+        let var1 = p[8] * pascals * pascals / 2_147_483_648.;
+        let var2 = pascals * p[7] / 32768.;
+        let pascals = pascals + (var1 + var2 + p[6]) / 16.;
+
+        Some(Pressure::from_hectopascals((pascals / 100.) as f32))
+    }
+
+    /// The compensated relative humidity, in percent.
+    fn humidity(&self, adc: u32, t_fine: f64) -> f64 {
+        let h = t_fine - 76800.;
+        let h = (f64::from(adc) - (self.h4 * 64. + self.h5 / 16384. * h))
+            * (self.h2 / 65536.
+                * (1. + self.h6 / 67_108_864. * h * (1. + self.h3 / 67_108_864. * h)));
+        let h = h * (1. - self.h1 * h / 524_288.);
+
+        h.clamp(0., 100.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::super::i2c::FakeBus;
+    use super::*;
+
+    /// Registers holding the calibration example from the BMP280 datasheet, with humidity
+    /// calibration and readings added.
+    fn registers() -> [u8; 256] {
+        let mut registers = [0; 256];
+        registers[REGISTER_CHIP_ID as usize] = CHIP_ID;
+
+        let words: [i32; 12] = [
+            27504, 26435, -1000, 36477, -10685, 3024, 2855, 140, -7, 15500, -14600, 6000,
+        ];
+        for (i, word) in words.iter().enumerate() {
+            let bytes = (*word as u16).to_le_bytes();
+            registers[0x88 + 2 * i] = bytes[0];
+            registers[0x89 + 2 * i] = bytes[1];
+        }
+        registers[0xA1] = 75;
+        registers[0xE1..0xE8].copy_from_slice(&[0x6A, 0x01, 0x00, 0x13, 0x29, 0x03, 0x1E]);
+
+        registers[0xF7..0xFF].copy_from_slice(&[0x65, 0x5A, 0xC0, 0x7E, 0xED, 0x00, 0x75, 0x30]);
+        registers
+    }
+
+    #[test]
+    fn read_test() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let writes_clone = writes.clone();
+        let registers = registers();
+
+        let mut sensor = Bme280::new(
+            Box::new(FakeBus {
+                write_callback: Some(Box::new(move |_, data| {
+                    writes_clone.borrow_mut().push(data.to_vec())
+                })),
+                write_read_callback: Some(Box::new(move |address, data, buffer| {
+                    assert_eq!(DEFAULT_ADDRESS, address);
+                    let start = data[0] as usize;
+                    buffer.copy_from_slice(&registers[start..start + buffer.len()]);
+                })),
+                ..Default::default()
+            }),
+            DEFAULT_ADDRESS,
+        );
+
+        let reading = sensor.read().unwrap();
+
+        assert_eq!(
+            vec![
+                vec![REGISTER_CTRL_HUM, CTRL_HUM],
+                vec![REGISTER_CTRL_MEAS, CTRL_MEAS]
+            ],
+            *writes.borrow()
+        );
+        assert!((25.08 - reading.temperature.celsius()).abs() < 0.01);
+        assert!((1006.53 - reading.pressure.unwrap().hectopascals()).abs() < 0.01);
+        assert_eq!(Some(55), reading.humidity);
+    }
+
+    #[test]
+    fn chip_id_test() {
+        let mut sensor = Bme280::new(Box::new(FakeBus::default()), DEFAULT_ADDRESS);
+        assert!(sensor.read().is_err());
+    }
+}
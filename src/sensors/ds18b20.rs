@@ -0,0 +1,244 @@
+//! DS18B20 1-Wire temperature probes, read through the kernel's `w1_therm` driver.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::Reading;
+use crate::weather::Temperature;
+
+/// The 1-Wire family code of the DS18B20, which prefixes its device IDs.
+const FAMILY_CODE: &str = "28-";
+
+/// The temperature register's value on power-up, which is returned if a conversion didn't happen.
+const POWER_ON_RESET_MILLIDEGREES: i32 = 85_000;
+
+pub struct Ds18b20 {
+    devices_path: PathBuf,
+
+    /// Device ID, eg. `28-0316a2795aff`. If `None`, the first DS18B20 found is used.
+    id: Option<String>,
+}
+
+impl Ds18b20 {
+    pub fn new(devices_path: impl Into<PathBuf>, id: Option<String>) -> Self {
+        Self {
+            devices_path: devices_path.into(),
+            id,
+        }
+    }
+
+    /// Configure the sensor from `DS18B20_ID`, which is either a device ID or `auto`, and
+    /// `W1_DEVICES_PATH` (default `/sys/bus/w1/devices`). Returns `None` if `DS18B20_ID` isn't
+    /// set.
+    pub fn from_env() -> Option<Self> {
+        let id = env::var("DS18B20_ID").ok()?;
+
+        Some(Self::new(
+            env::var("W1_DEVICES_PATH").unwrap_or_else(|_| "/sys/bus/w1/devices".to_string()),
+            if id == "auto" { None } else { Some(id) },
+        ))
+    }
+
+    pub fn read(&self) -> Result<Reading, String> {
+        let id = match &self.id {
+            Some(id) => id.clone(),
+            None => self.find_device()?,
+        };
+
+        let path = self.devices_path.join(&id).join("w1_slave");
+        let contents =
+            fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        Ok(Reading {
+            temperature: parse_w1_slave(&contents).map_err(|e| format!("{}: {}", id, e))?,
+            sensor: id,
+            humidity: None,
+            pressure: None,
+            time: time::OffsetDateTime::now_utc(),
+        })
+    }
+
+    /// The ID of the first DS18B20 on the bus, in order of ID so the choice is stable.
+    fn find_device(&self) -> Result<String, String> {
+        let mut ids: Vec<String> = fs::read_dir(&self.devices_path)
+            .map_err(|e| format!("{}: {}", self.devices_path.display(), e))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name.starts_with(FAMILY_CODE))
+            .collect();
+        ids.sort();
+
+        ids.into_iter().next().ok_or_else(|| {
+            format!(
+                "No DS18B20 found in {}.",
+                Path::new(&self.devices_path).display()
+            )
+        })
+    }
+}
+
+/// Parse the contents of a `w1_slave` file, which look like this:
+///
+/// ```text
+/// 72 01 4b 46 7f ff 0e 10 57 : crc=57 YES
+/// 72 01 4b 46 7f ff 0e 10 57 t=23125
+/// ```
+///
+/// The first line is the scratchpad along with the driver's CRC check, and the second the
+/// temperature in thousandths of a degree Celsius. The CRC is checked again here rather than
+/// trusting the driver's verdict, since some kernels report `YES` for an all-zero scratchpad.
+pub fn parse_w1_slave(contents: &str) -> Result<Temperature, String> {
+    let mut lines = contents.lines();
+    let crc_line = lines.next().ok_or("Empty reading.")?;
+    let temperature_line = lines.next().ok_or("Missing temperature line.")?;
+
+    let (scratchpad, verdict) = {
+        let mut parts = crc_line.splitn(2, ':');
+        (
+            parts.next().unwrap_or(""),
+            parts.next().ok_or("Missing CRC check.")?.trim(),
+        )
+    };
+
+    if !verdict.ends_with("YES") {
+        return Err("CRC check failed.".to_string());
+    }
+
+    let bytes = scratchpad
+        .split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| "Invalid scratchpad.")?;
+
+    match bytes.split_last() {
+        Some((crc, data)) if data.len() == 8 => {
+            if crc8(data) != *crc {
+                return Err("CRC mismatch.".to_string());
+            }
+        }
+        _ => return Err("Invalid scratchpad length.".to_string()),
+    }
+
+    if bytes.iter().all(|&byte| byte == 0) {
+        return Err("Empty scratchpad.".to_string());
+    }
+
+    let millidegrees: i32 = temperature_line
+        .rsplit("t=")
+        .next()
+        .filter(|_| temperature_line.contains("t="))
+        .ok_or("Missing temperature.")?
+        .trim()
+        .parse()
+        .map_err(|_| "Invalid temperature.")?;
+
+    if millidegrees == POWER_ON_RESET_MILLIDEGREES {
+        return Err("Sensor returned its power-on value.".to_string());
+    }
+
+    Ok(Temperature::from_celsius(millidegrees as f32 / 1000.))
+}
+
+/// The Dallas/Maxim 1-Wire CRC-8 (polynomial x⁸ + x⁵ + x⁴ + 1).
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8)
+            .fold((crc, byte), |(crc, byte), _| {
+                let mix = (crc ^ byte) & 1;
+                let crc = if mix == 1 {
+                    (crc >> 1) ^ 0x8C
+                } else {
+                    crc >> 1
+                };
+                (crc, byte >> 1)
+            })
+            .0
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DEVICES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/w1/devices");
+
+    fn assert_celsius(expected: f32, temperature: Temperature) {
+        assert!(
+            (expected - temperature.celsius()).abs() < 0.001,
+            "expected {}, got {}",
+            expected,
+            temperature.celsius(),
+        );
+    }
+
+    #[test]
+    fn crc8_test() {
+        assert_eq!(
+            0x57,
+            crc8(&[0x72, 0x01, 0x4b, 0x46, 0x7f, 0xff, 0x0e, 0x10])
+        );
+        assert_eq!(0, crc8(&[]));
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_celsius(
+            23.125,
+            parse_w1_slave(
+                "72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n72 01 4b 46 7f ff 0e 10 57 t=23125\n",
+            )
+            .unwrap(),
+        );
+
+        // Below zero
+        assert_celsius(
+            -10.125,
+            parse_w1_slave(
+                "5e ff 4b 46 7f ff 02 10 b6 : crc=b6 YES\n5e ff 4b 46 7f ff 02 10 b6 t=-10125\n",
+            )
+            .unwrap(),
+        );
+
+        // The driver's CRC check failed.
+        assert!(parse_w1_slave(
+            "72 01 4b 46 7f ff 0e 10 00 : crc=57 NO\n72 01 4b 46 7f ff 0e 10 00 t=23125\n"
+        )
+        .is_err());
+
+        // The driver claims the CRC passed, but it doesn't match.
+        assert!(parse_w1_slave(
+            "72 01 4b 46 7f ff 0e 10 58 : crc=58 YES\n72 01 4b 46 7f ff 0e 10 58 t=23125\n"
+        )
+        .is_err());
+
+        // All zeroes, which has a valid CRC.
+        assert!(parse_w1_slave(
+            "00 00 00 00 00 00 00 00 00 : crc=00 YES\n00 00 00 00 00 00 00 00 00 t=0\n"
+        )
+        .is_err());
+
+        // Power-on reset value
+        assert!(parse_w1_slave(
+            "50 05 4b 46 7f ff 0c 10 1c : crc=1c YES\n50 05 4b 46 7f ff 0c 10 1c t=85000\n"
+        )
+        .is_err());
+
+        assert!(parse_w1_slave("").is_err());
+    }
+
+    #[test]
+    fn read_test() {
+        let reading = Ds18b20::new(DEVICES, None).read().unwrap();
+        assert_eq!("28-0316a2795aff", reading.sensor);
+        assert_celsius(23.125, reading.temperature);
+
+        assert!(Ds18b20::new(DEVICES, Some("28-0417c1b4d2ff".to_string()))
+            .read()
+            .is_err());
+        assert!(Ds18b20::new(DEVICES, Some("28-000000000000".to_string()))
+            .read()
+            .is_err());
+        assert!(Ds18b20::new("/nonexistent", None).read().is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! A minimal I2C bus abstraction, so that sensor drivers can be tested without hardware.
+
+use rppal::i2c;
+
+pub trait I2cBus {
+    /// Write bytes to the device at an address, eg. a command or a register and its value.
+    fn write(&mut self, address: u16, data: &[u8]) -> Result<(), i2c::Error>;
+
+    /// Read bytes from the device at an address.
+    fn read(&mut self, address: u16, buffer: &mut [u8]) -> Result<(), i2c::Error>;
+
+    /// Write bytes (usually a register address) and read the response in a single transaction.
+    fn write_read(
+        &mut self,
+        address: u16,
+        data: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), i2c::Error>;
+}
+
+/// The Raspberry Pi's I2C bus, through `/dev/i2c-*`.
+pub struct HardwareBus {
+    i2c: i2c::I2c,
+}
+
+impl HardwareBus {
+    pub fn open(bus: u8) -> Result<Self, i2c::Error> {
+        Ok(Self {
+            i2c: i2c::I2c::with_bus(bus)?,
+        })
+    }
+}
+
+impl I2cBus for HardwareBus {
+    fn write(&mut self, address: u16, data: &[u8]) -> Result<(), i2c::Error> {
+        self.i2c.set_slave_address(address)?;
+        self.i2c.write(data).map(|_| ())
+    }
+
+    fn read(&mut self, address: u16, buffer: &mut [u8]) -> Result<(), i2c::Error> {
+        self.i2c.set_slave_address(address)?;
+        self.i2c.read(buffer).map(|_| ())
+    }
+
+    fn write_read(
+        &mut self,
+        address: u16,
+        data: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), i2c::Error> {
+        self.i2c.set_slave_address(address)?;
+        self.i2c.write_read(data, buffer)
+    }
+}
+
+#[cfg(test)]
+type WriteCallback = Box<dyn Fn(u16, &[u8])>;
+
+#[cfg(test)]
+type ReadCallback = Box<dyn Fn(u16, &mut [u8])>;
+
+#[cfg(test)]
+type WriteReadCallback = Box<dyn Fn(u16, &[u8], &mut [u8])>;
+
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct FakeBus {
+    pub write_callback: Option<WriteCallback>,
+    pub read_callback: Option<ReadCallback>,
+    pub write_read_callback: Option<WriteReadCallback>,
+}
+
+#[cfg(test)]
+impl I2cBus for FakeBus {
+    fn write(&mut self, address: u16, data: &[u8]) -> Result<(), i2c::Error> {
+        if let Some(f) = &self.write_callback {
+            f(address, data);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, address: u16, buffer: &mut [u8]) -> Result<(), i2c::Error> {
+        if let Some(f) = &self.read_callback {
+            f(address, buffer);
+        }
+        Ok(())
+    }
+
+    fn write_read(
+        &mut self,
+        address: u16,
+        data: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), i2c::Error> {
+        if let Some(f) = &self.write_read_callback {
+            f(address, data, buffer);
+        }
+        Ok(())
+    }
+}
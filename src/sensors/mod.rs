@@ -0,0 +1,140 @@
+//! Local sensors attached to the device, which are more accurate for where it sits than a
+//! forecast model's grid point.
+
+use std::env;
+
+use crate::config::parse_env;
+use crate::weather::{Pressure, Temperature};
+
+pub mod bme280;
+pub mod ds18b20;
+pub mod i2c;
+pub mod sht31;
+
+/// The latest readings from every configured sensor.
+#[derive(Default)]
+pub struct Readings {
+    pub outdoor: Option<Reading>,
+    pub indoor: Option<Reading>,
+
+    /// Other values to show, eg. from Home Assistant.
+    pub entities: Vec<EntityValue>,
+}
+
+impl Readings {
+    /// Read the sensors configured in the environment. Failures are reported to stderr and leave
+    /// the corresponding reading empty.
+    pub fn from_env() -> Self {
+        Self {
+            outdoor: ds18b20::Ds18b20::from_env().and_then(|sensor| match sensor.read() {
+                Ok(reading) => Some(reading),
+                Err(e) => {
+                    eprintln!("Unable to read DS18B20: {}", e);
+                    None
+                }
+            }),
+            indoor: read_indoor_sensor().unwrap_or_else(|e| {
+                eprintln!("Unable to read indoor sensor: {}", e);
+                None
+            }),
+            entities: Vec::new(),
+        }
+    }
+
+    /// Fill in any readings that are missing from another source.
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            outdoor: self.outdoor.or(fallback.outdoor),
+            indoor: self.indoor.or(fallback.indoor),
+            entities: self.entities.into_iter().chain(fallback.entities).collect(),
+        }
+    }
+}
+
+/// Read the I2C sensor given by `INDOOR_SENSOR` (`sht31` or `bme280`), if any. The bus is
+/// `I2C_BUS` (default 1) and the address `INDOOR_SENSOR_ADDRESS`, which defaults to the
+/// sensor's usual one.
+fn read_indoor_sensor() -> Result<Option<Reading>, String> {
+    let model = match env::var("INDOOR_SENSOR") {
+        Ok(model) => model.to_lowercase(),
+        Err(_) => return Ok(None),
+    };
+
+    let address = env::var("INDOOR_SENSOR_ADDRESS")
+        .ok()
+        .map(|address| parse_address(&address))
+        .transpose()?;
+
+    let bus = Box::new(
+        i2c::HardwareBus::open(parse_env("I2C_BUS").unwrap_or(1)).map_err(|e| e.to_string())?,
+    );
+
+    match model.as_str() {
+        "sht31" => sht31::Sht31::new(bus, address.unwrap_or(sht31::DEFAULT_ADDRESS)).read(),
+        "bme280" => bme280::Bme280::new(bus, address.unwrap_or(bme280::DEFAULT_ADDRESS)).read(),
+        _ => Err(format!("Unknown sensor \"{}\".", model)),
+    }
+    .map(Some)
+}
+
+/// Parse an I2C address in hexadecimal (`0x76`) or decimal.
+fn parse_address(raw: &str) -> Result<u16, String> {
+    let raw = raw.trim();
+    match raw.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => raw.parse(),
+    }
+    .map_err(|_| format!("Invalid I2C address \"{}\".", raw))
+}
+
+pub struct Reading {
+    /// Identifies the sensor that took the reading, eg. its 1-Wire ID or I2C address.
+    pub sensor: String,
+    pub temperature: Temperature,
+
+    /// Relative humidity in percent, if the sensor measures it.
+    pub humidity: Option<u8>,
+
+    /// Station pressure (not adjusted to sea level), if the sensor measures it.
+    pub pressure: Option<Pressure>,
+
+    pub time: time::OffsetDateTime,
+}
+
+/// A labelled value to show as-is, eg. "Front door: Open".
+pub struct EntityValue {
+    pub label: String,
+    pub value: String,
+}
+
+/// How to show an outdoor sensor reading alongside the current conditions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SensorDisplay {
+    /// Replace the reported temperature with the sensor's.
+    Override,
+
+    /// Show the sensor's temperature next to the reported one.
+    Beside,
+}
+
+impl SensorDisplay {
+    /// Read from `OUTDOOR_SENSOR_DISPLAY` (`override` or `beside`), defaulting to `beside`.
+    pub fn from_env() -> Self {
+        match env::var("OUTDOOR_SENSOR_DISPLAY").as_deref() {
+            Ok("override") => Self::Override,
+            _ => Self::Beside,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_address_test() {
+        assert_eq!(Ok(0x76), parse_address("0x76"));
+        assert_eq!(Ok(0x44), parse_address(" 68"));
+        assert!(parse_address("0xZZ").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Sensirion SHT31 temperature and humidity sensors.
+
+use std::thread;
+use std::time::Duration;
+
+use super::i2c::I2cBus;
+use super::Reading;
+use crate::weather::Temperature;
+
+/// The address with the ADDR pin pulled low. It's `0x45` when pulled high.
+pub const DEFAULT_ADDRESS: u16 = 0x44;
+
+/// Single-shot measurement with high repeatability and clock stretching disabled.
+const MEASURE_HIGH_REPEATABILITY: [u8; 2] = [0x24, 0x00];
+
+/// The longest a high-repeatability measurement takes.
+const MEASUREMENT_DURATION: Duration = Duration::from_millis(16);
+
+pub struct Sht31 {
+    bus: Box<dyn I2cBus>,
+    address: u16,
+}
+
+impl Sht31 {
+    pub fn new(bus: Box<dyn I2cBus>, address: u16) -> Self {
+        Self { bus, address }
+    }
+
+    pub fn read(&mut self) -> Result<Reading, String> {
+        self.bus
+            .write(self.address, &MEASURE_HIGH_REPEATABILITY)
+            .map_err(|e| e.to_string())?;
+
+        thread::sleep(MEASUREMENT_DURATION);
+
+        let mut data = [0; 6];
+        self.bus
+            .read(self.address, &mut data)
+            .map_err(|e| e.to_string())?;
+
+        let (temperature, humidity) = decode(&data)?;
+
+        Ok(Reading {
+            sensor: format!("SHT31@{:#04x}", self.address),
+            temperature,
+            humidity: Some(humidity),
+            pressure: None,
+            time: time::OffsetDateTime::now_utc(),
+        })
+    }
+}
+
+/// Decode a measurement: the raw temperature and humidity, each followed by its CRC.
+fn decode(data: &[u8; 6]) -> Result<(Temperature, u8), String> {
+    let word = |chunk: &[u8]| -> Result<f32, String> {
+        if crc8(&chunk[..2]) != chunk[2] {
+            return Err("CRC mismatch.".to_string());
+        }
+        Ok(f32::from(u16::from_be_bytes([chunk[0], chunk[1]])))
+    };
+
+    let raw_temperature = word(&data[..3])?;
+    let raw_humidity = word(&data[3..])?;
+
+    Ok((
+        Temperature::from_celsius(-45. + 175. * raw_temperature / 65535.),
+        (100. * raw_humidity / 65535.).round() as u8,
+    ))
+}
+
+/// Sensirion's CRC-8 (polynomial x⁸ + x⁵ + x⁴ + 1, initialized to `0xFF`).
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0xFF, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::super::i2c::FakeBus;
+    use super::*;
+
+    #[test]
+    fn crc8_test() {
+        // The example from the datasheet.
+        assert_eq!(0x92, crc8(&[0xBE, 0xEF]));
+    }
+
+    #[test]
+    fn read_test() {
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let commands_clone = commands.clone();
+
+        let mut sensor = Sht31::new(
+            Box::new(FakeBus {
+                write_callback: Some(Box::new(move |address, data| {
+                    commands_clone.borrow_mut().push((address, data.to_vec()))
+                })),
+                read_callback: Some(Box::new(|_, buffer| {
+                    buffer.copy_from_slice(&[0x66, 0x66, 0x93, 0x80, 0x00, 0xA2])
+                })),
+                ..Default::default()
+            }),
+            DEFAULT_ADDRESS,
+        );
+
+        let reading = sensor.read().unwrap();
+        assert_eq!(vec![(0x44, vec![0x24, 0x00])], *commands.borrow());
+        assert!((25. - reading.temperature.celsius()).abs() < 0.01);
+        assert_eq!(Some(50), reading.humidity);
+        assert!(reading.pressure.is_none());
+    }
+
+    #[test]
+    fn crc_mismatch_test() {
+        assert!(decode(&[0x66, 0x66, 0x92, 0x80, 0x00, 0xA2]).is_err());
+        assert!(decode(&[0x66, 0x66, 0x93, 0x80, 0x01, 0xA2]).is_err());
+    }
+}
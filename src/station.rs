@@ -0,0 +1,389 @@
+//! A receiver for observations pushed by a personal weather station, such as an Ecowitt gateway,
+//! in the Wunderground or Ecowitt upload formats. The latest observation is kept in the cache and
+//! takes precedence over the provider's current conditions.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{Cache, Fetched};
+use crate::config::parse_env;
+use crate::weather::{Precipitation, Pressure, Temperature, WeatherState, Wind};
+
+const OBSERVATION_CACHE: &str = "station.json";
+
+const METRES_PER_SECOND_PER_MPH: f32 = 0.447_04;
+const HECTOPASCALS_PER_INCH_OF_MERCURY: f32 = 33.863_89;
+const MILLIMETRES_PER_INCH: f32 = 25.4;
+
+/// An observation in metric units, as stored in the cache.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Observation {
+    /// When the station took the observation, as a Unix timestamp.
+    pub time: i64,
+
+    /// In degrees Celsius.
+    pub temperature: Option<f32>,
+    pub humidity: Option<u8>,
+
+    /// Sea-level pressure, in hectopascals.
+    pub pressure: Option<f32>,
+
+    /// In metres per second.
+    pub wind_speed: Option<f32>,
+    pub wind_gust: Option<f32>,
+    pub wind_direction: Option<u16>,
+
+    /// Rain over the past hour, in millimetres.
+    pub precipitation: Option<f32>,
+}
+
+impl Observation {
+    /// Decode an upload's fields, which are the same in both formats apart from the pressure and
+    /// hourly rain, and the station identifier (`ID` for Wunderground, `PASSKEY` for Ecowitt).
+    pub fn decode(fields: &HashMap<String, String>) -> Result<Self, String> {
+        let number = |key: &str| -> Option<f32> {
+            fields
+                .get(key)
+                .and_then(|value| value.trim().parse().ok())
+                .filter(|value: &f32| value.is_finite())
+        };
+
+        let time = match fields.get("dateutc").map(|time| time.trim()) {
+            None | Some("now") => time::OffsetDateTime::now_utc(),
+            Some(time) => time::PrimitiveDateTime::parse(time, "%Y-%m-%d %H:%M:%S")
+                .map_err(|e| format!("Invalid dateutc \"{}\": {}", time, e))?
+                .assume_utc(),
+        };
+
+        let observation = Self {
+            time: time.unix_timestamp(),
+            temperature: number("tempf").map(|f| Temperature::from_fahrenheit(f).celsius()),
+            humidity: number("humidity").map(|humidity| humidity.round().clamp(0., 100.) as u8),
+            pressure: number("baromrelin")
+                .or_else(|| number("baromin"))
+                .map(|inches| inches * HECTOPASCALS_PER_INCH_OF_MERCURY),
+            wind_speed: number("windspeedmph").map(|mph| mph * METRES_PER_SECOND_PER_MPH),
+            wind_gust: number("windgustmph").map(|mph| mph * METRES_PER_SECOND_PER_MPH),
+            wind_direction: number("winddir").map(|degrees| degrees.round() as u16 % 360),
+            precipitation: number("hourlyrainin")
+                .or_else(|| number("rainin"))
+                .map(|inches| inches * MILLIMETRES_PER_INCH),
+        };
+
+        if observation.temperature.is_none()
+            && observation.humidity.is_none()
+            && observation.pressure.is_none()
+            && observation.wind_speed.is_none()
+        {
+            return Err("No observations in upload.".to_string());
+        }
+
+        Ok(observation)
+    }
+
+    /// Replace the reported conditions with the station's, keeping anything it doesn't measure.
+    pub fn apply(&self, state: &mut WeatherState) {
+        state.time =
+            time::OffsetDateTime::from_unix_timestamp(self.time).to_offset(state.time.offset());
+
+        if let Some(temperature) = self.temperature {
+            state.temp = Some(Temperature::from_celsius(temperature));
+        }
+        if let Some(humidity) = self.humidity {
+            state.humidity = Some(humidity);
+        }
+        if let Some(pressure) = self.pressure {
+            state.pressure = Some(Pressure::from_hectopascals(pressure));
+        }
+        if let Some(speed) = self.wind_speed {
+            state.wind = Some(Wind {
+                speed,
+                direction: self
+                    .wind_direction
+                    .or_else(|| state.wind.as_ref().and_then(|wind| wind.direction)),
+                gust: self.wind_gust,
+            });
+        }
+        if let Some(precipitation) = self.precipitation {
+            state.precipitation = Some(Precipitation::from_millimetres(precipitation));
+        }
+    }
+}
+
+pub struct StationConfig {
+    pub address: SocketAddr,
+
+    /// Only accept uploads with this `ID` or `PASSKEY`, if set.
+    pub station_id: Option<String>,
+
+    /// Ignore the stored observation once it's this old.
+    pub max_age: Duration,
+}
+
+impl StationConfig {
+    /// Read the configuration from `STATION_LISTEN` (eg. `0.0.0.0:8080`; the listener is disabled
+    /// if it isn't set), `STATION_ID` and `STATION_MAX_AGE` (in minutes, default 15).
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            address: parse_env("STATION_LISTEN")?,
+            station_id: env::var("STATION_ID").ok(),
+            max_age: Duration::from_secs(60 * parse_env("STATION_MAX_AGE").unwrap_or(15)),
+        })
+    }
+}
+
+/// The latest stored observation, unless it's older than `max_age`.
+pub fn latest(cache: &Cache, max_age: Duration) -> Option<Fetched<Observation>> {
+    let cached = cache.load(OBSERVATION_CACHE).ok()?;
+    let observation: Observation = serde_json::from_slice(&cached.value).ok()?;
+
+    if time::OffsetDateTime::now_utc() - cached.fetched_at >= max_age {
+        return None;
+    }
+
+    Some(Fetched {
+        value: observation,
+        fetched_at: cached.fetched_at,
+        stale: false,
+    })
+}
+
+/// Receive uploads until the server fails.
+pub async fn listen(config: StationConfig, cache: Cache) -> Result<(), String> {
+    let incoming = AddrIncoming::bind(&config.address).map_err(|e| e.to_string())?;
+    eprintln!(
+        "Listening for weather station uploads on {}",
+        incoming.local_addr()
+    );
+    serve(incoming, config, cache).await
+}
+
+async fn serve(incoming: AddrIncoming, config: StationConfig, cache: Cache) -> Result<(), String> {
+    let receiver = std::sync::Arc::new((config, cache));
+
+    let make_service = make_service_fn(move |_| {
+        let receiver = receiver.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let receiver = receiver.clone();
+                async move { Ok::<_, Infallible>(receive(request, &receiver.0, &receiver.1).await) }
+            }))
+        }
+    });
+
+    Server::builder(incoming)
+        .serve(make_service)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Handle an upload, which may be a Wunderground GET with the fields in the query string or an
+/// Ecowitt POST with them in a form body, on any path.
+async fn receive(request: Request<Body>, config: &StationConfig, cache: &Cache) -> Response<Body> {
+    let mut fields: HashMap<String, String> = request
+        .uri()
+        .query()
+        .map(|query| {
+            form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => fields.extend(form_urlencoded::parse(&body).into_owned()),
+        Err(e) => return respond(StatusCode::BAD_REQUEST, &e.to_string()),
+    }
+
+    if let Some(station_id) = &config.station_id {
+        if fields.get("ID").or_else(|| fields.get("PASSKEY")) != Some(station_id) {
+            return respond(StatusCode::UNAUTHORIZED, "Unknown station.");
+        }
+    }
+
+    let observation = match Observation::decode(&fields) {
+        Ok(observation) => observation,
+        Err(e) => return respond(StatusCode::BAD_REQUEST, &e),
+    };
+
+    let stored = serde_json::to_vec(&observation)
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            cache
+                .store(OBSERVATION_CACHE, &data, time::OffsetDateTime::now_utc())
+                .map_err(|e| e.to_string())
+        });
+
+    match stored {
+        // Wunderground clients expect this exact response.
+        Ok(()) => respond(StatusCode::OK, "success"),
+        Err(e) => {
+            eprintln!("Unable to store observation: {}", e);
+            respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unable to store observation.",
+            )
+        }
+    }
+}
+
+fn respond(status: StatusCode, body: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(format!("{}\n", body)));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http;
+    use crate::weather::Provider;
+
+    fn fields(query: &str) -> HashMap<String, String> {
+        form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect()
+    }
+
+    fn assert_close(expected: f32, actual: Option<f32>) {
+        let actual = actual.unwrap();
+        assert!(
+            (expected - actual).abs() < 0.01,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn decode_wunderground_test() {
+        let observation = Observation::decode(&fields(
+            "ID=KQCMONTR1&PASSWORD=secret&action=updateraw&dateutc=2020-12-17+14%3A02%3A11\
+             &tempf=23.0&humidity=81&dewptf=18.0&winddir=275&windspeedmph=10.0&windgustmph=18.5\
+             &rainin=0.02&dailyrainin=0.11&baromin=30.12&realtime=1&rtfreq=5",
+        ))
+        .unwrap();
+
+        assert_eq!(1_608_213_731, observation.time);
+        assert_close(-5., observation.temperature);
+        assert_eq!(Some(81), observation.humidity);
+        assert_close(1019.98, observation.pressure);
+        assert_close(4.47, observation.wind_speed);
+        assert_close(8.27, observation.wind_gust);
+        assert_eq!(Some(275), observation.wind_direction);
+        assert_close(0.508, observation.precipitation);
+    }
+
+    #[test]
+    fn apply_test() {
+        let observation = Observation::decode(&fields(
+            "ID=KQCMONTR1&PASSWORD=secret&dateutc=now&tempf=23.0&windspeedmph=10.0",
+        ))
+        .unwrap();
+        let mut state = WeatherState {
+            time: time::OffsetDateTime::now_utc(),
+            sunrise: None,
+            sunset: None,
+            temp: None,
+            temp_min: None,
+            temp_max: None,
+            humidity: None,
+            wind: Some(Wind {
+                speed: 2.,
+                direction: Some(200),
+                gust: None,
+            }),
+            pressure: None,
+            precipitation: None,
+            clouds: None,
+            visibility: None,
+            condition: None,
+            provider: Provider::OpenWeather,
+        };
+
+        // Without a direction in the upload, the provider's is kept, if there is one.
+        observation.apply(&mut state);
+        assert_eq!(Some(200), state.wind.as_ref().unwrap().direction);
+        assert_close(4.47, state.wind.as_ref().map(|wind| wind.speed));
+
+        state.wind = None;
+        observation.apply(&mut state);
+        assert_eq!(None, state.wind.unwrap().direction);
+    }
+
+    #[test]
+    fn decode_ecowitt_test() {
+        let observation = Observation::decode(&fields(
+            "PASSKEY=0123456789ABCDEF&stationtype=GW1000B_V1.6.3&dateutc=2020-12-17+14:02:11\
+             &tempinf=70.5&humidityin=35&baromrelin=29.921&baromabsin=29.5&tempf=41.0&humidity=70\
+             &winddir=90&windspeedmph=0.0&windgustmph=2.2&rainratein=0.000&hourlyrainin=0.100\
+             &dailyrainin=0.2&freq=915M&model=GW1000_Pro",
+        ))
+        .unwrap();
+
+        assert_close(5., observation.temperature);
+        assert_close(1013.24, observation.pressure);
+        assert_close(0., observation.wind_speed);
+        assert_close(2.54, observation.precipitation);
+
+        assert!(Observation::decode(&fields("PASSKEY=0123456789ABCDEF")).is_err());
+        assert!(Observation::decode(&fields("tempf=41.0&dateutc=yesterday")).is_err());
+    }
+
+    #[tokio::test]
+    async fn receive_test() {
+        let dir = env::temp_dir().join(format!("weathervane-station-test-{}", std::process::id()));
+        let incoming = AddrIncoming::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let url = format!("http://{}", incoming.local_addr());
+
+        tokio::spawn(serve(
+            incoming,
+            StationConfig {
+                address: SocketAddr::from(([127, 0, 0, 1], 0)),
+                station_id: Some("KQCMONTR1".to_string()),
+                max_age: Duration::from_secs(900),
+            },
+            Cache::new(&dir),
+        ));
+
+        let client = http::Client::new(http::ClientConfig {
+            max_attempts: 1,
+            ..Default::default()
+        });
+
+        // The wrong station is turned away.
+        assert!(client
+            .get_text(&format!(
+                "{}/weatherstation/updateweatherstation.php?ID=KQCMONTR2&dateutc=now&tempf=23.0",
+                url
+            ))
+            .await
+            .is_err());
+        assert!(latest(&Cache::new(&dir), Duration::from_secs(900)).is_none());
+
+        assert_eq!(
+            Ok("success\n".to_string()),
+            client
+                .get_text(&format!(
+                    "{}/weatherstation/updateweatherstation.php?ID=KQCMONTR1&dateutc=now&tempf=23.0",
+                    url
+                ))
+                .await
+        );
+
+        let observation = latest(&Cache::new(&dir), Duration::from_secs(900)).unwrap();
+        assert!(!observation.stale);
+        assert_close(-5., observation.value.temperature);
+        assert!(latest(&Cache::new(&dir), Duration::from_secs(0)).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
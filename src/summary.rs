@@ -0,0 +1,503 @@
+//! A sentence or two summarizing the hourly forecast, eg. "Snow beginning around 3pm, 5–10 cm,
+//! then clearing overnight; low -18°."
+
+use crate::locale::Locale;
+use crate::units::{PrecipitationUnit, Units};
+use crate::weather::{Forecast, SnowType, WeatherCondition, WeatherState, Wind};
+
+/// How far ahead to summarize.
+const SUMMARY_PERIOD: time::Duration = time::Duration::day();
+
+/// Sustained wind speed (in m/s) worth mentioning, about 30 km/h.
+const WINDY: f32 = 8.3;
+
+/// Total precipitation (in mm) below which the amount isn't worth mentioning.
+const TRACE: f32 = 0.5;
+
+/// Summarize the next day of the hourly forecast, starting from the hour in progress: the sky or
+/// the next spell of precipitation, the next low or high, and any strong wind.
+pub fn summarize(
+    hourly: Forecast,
+    now: time::OffsetDateTime,
+    units: &Units,
+    locale: Locale,
+) -> Option<String> {
+    let forecast = hourly.between(now - time::Duration::hour(), now + SUMMARY_PERIOD);
+    let hours = forecast.states();
+    if hours.len() < 2 {
+        return None;
+    }
+
+    let first = precipitation(hours, units, locale)
+        .or_else(|| sky(hours, locale))
+        .into_iter()
+        .chain(temperature(forecast, units, locale))
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    let sentences: Vec<String> = Some(first)
+        .filter(|first| !first.is_empty())
+        .into_iter()
+        .chain(wind(hours, units, locale))
+        .map(|sentence| format!("{}.", capitalize(&sentence)))
+        .collect();
+
+    if sentences.is_empty() {
+        None
+    } else {
+        Some(sentences.join(" "))
+    }
+}
+
+/// The next spell of precipitation, when it begins or ends, how much will fall and what follows.
+/// A single dry hour doesn't end a spell.
+fn precipitation(hours: &[WeatherState], units: &Units, locale: Locale) -> Option<String> {
+    let start = hours.iter().position(is_precipitation)?;
+    let end = (start + 1..hours.len()).find(|&i| {
+        !is_precipitation(&hours[i])
+            && !matches!(hours.get(i + 1), Some(next) if is_precipitation(next))
+    });
+    let spell = &hours[start..end.unwrap_or(hours.len())];
+
+    let condition = spell
+        .iter()
+        .filter_map(|state| state.condition.as_ref())
+        .max_by_key(|condition| condition.severity())?;
+    let description = locale.condition(condition);
+    let total: f32 = spell
+        .iter()
+        .filter_map(|state| state.precipitation.as_ref())
+        .map(|precipitation| precipitation.millimetres())
+        .sum();
+
+    let mut clause = match (start, end, locale) {
+        (0, None, _) => description.to_string(),
+        (0, Some(end), Locale::EnCa) => {
+            format!(
+                "{} ending around {}",
+                description,
+                hour(&hours[end], locale)
+            )
+        }
+        (0, Some(end), Locale::FrCa) => {
+            format!(
+                "{} se terminant vers {}",
+                description,
+                hour(&hours[end], locale)
+            )
+        }
+        (start, _, Locale::EnCa) => {
+            format!(
+                "{} beginning around {}",
+                description,
+                hour(&hours[start], locale)
+            )
+        }
+        (start, _, Locale::FrCa) => {
+            format!(
+                "{} débutant vers {}",
+                description,
+                hour(&hours[start], locale)
+            )
+        }
+    };
+
+    if let Some(amount) = amount(total, is_snow(condition), units.precipitation, locale) {
+        clause = format!("{}, {}", clause, amount);
+    }
+
+    if let Some(change) = end.and_then(|end| change(&hours[end], locale)) {
+        clause = match locale {
+            Locale::EnCa => format!("{}, then {}", clause, change),
+            Locale::FrCa => format!("{}, puis {}", clause, change),
+        };
+    }
+
+    Some(clause)
+}
+
+/// The sky when there's no precipitation: the current condition and the first time it clears or
+/// clouds over.
+fn sky(hours: &[WeatherState], locale: Locale) -> Option<String> {
+    let first = hours.iter().find_map(|state| state.condition.as_ref())?;
+    let description = locale.condition(first);
+
+    match hours.iter().find(|state| {
+        matches!(&state.condition, Some(condition) if is_clear(condition) != is_clear(first))
+    }) {
+        Some(state) => Some(format!("{}, {}", description, change(state, locale)?)),
+        None => Some(description.to_string()),
+    }
+}
+
+/// The next low, or the next high, whichever comes first. If the temperature is falling from now
+/// on, that's the low, and if it's rising, the high.
+fn temperature(forecast: Forecast, units: &Units, locale: Locale) -> Option<String> {
+    let first = forecast
+        .first_where(|state| state.temp.is_some())?
+        .temp
+        .as_ref()?;
+    let (min_time, min) = forecast.min_temp()?;
+    let (max_time, max) = forecast.max_temp()?;
+
+    let low = if max.kelvin() <= first.kelvin() {
+        true
+    } else if min.kelvin() >= first.kelvin() {
+        false
+    } else {
+        min_time < max_time
+    };
+
+    Some(match (low, locale) {
+        (true, Locale::EnCa) => format!("low {}", min.format(units.temperature)),
+        (true, Locale::FrCa) => format!("minimum {}", min.format(units.temperature)),
+        (false, Locale::EnCa) => format!("high {}", max.format(units.temperature)),
+        (false, Locale::FrCa) => format!("maximum {}", max.format(units.temperature)),
+    })
+}
+
+/// The next stretch of strong wind, with its peak speed and direction.
+fn wind(hours: &[WeatherState], units: &Units, locale: Locale) -> Option<String> {
+    let is_windy = |state: &WeatherState| matches!(&state.wind, Some(wind) if wind.speed >= WINDY);
+
+    let start = hours.iter().position(is_windy)?;
+    let end = (start..hours.len()).find(|&i| !is_windy(&hours[i]));
+    let peak = hours[start..end.unwrap_or(hours.len())]
+        .iter()
+        .filter_map(|state| state.wind.as_ref())
+        .max_by(|a: &&Wind, b: &&Wind| {
+            a.speed
+                .partial_cmp(&b.speed)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+
+    // A variable wind is given without a direction, eg. "wind 40 km/h".
+    let speed = peak.format_speed(units.speed);
+    let velocity = match peak.compass_point() {
+        Some(point) => format!("{} {}", locale.compass_point(point), speed),
+        None => speed,
+    };
+
+    let mut clause = match (start, locale) {
+        (0, Locale::EnCa) => format!("wind {}", velocity),
+        (0, Locale::FrCa) => format!("vents {}", velocity),
+        (start, Locale::EnCa) => format!(
+            "wind becoming {} {}",
+            velocity,
+            period(&hours[start], locale),
+        ),
+        (start, Locale::FrCa) => format!(
+            "vents devenant {} {}",
+            velocity,
+            period(&hours[start], locale),
+        ),
+    };
+
+    if let Some(end) = end {
+        clause = match locale {
+            Locale::EnCa => format!("{}, diminishing {}", clause, period(&hours[end], locale)),
+            Locale::FrCa => format!("{}, diminuant {}", clause, period(&hours[end], locale)),
+        };
+    }
+
+    Some(clause)
+}
+
+/// How the sky changes at the given hour, eg. "clearing overnight".
+fn change(state: &WeatherState, locale: Locale) -> Option<String> {
+    let condition = state.condition.as_ref()?;
+
+    Some(match (is_clear(condition), locale) {
+        (true, Locale::EnCa) => format!("clearing {}", period(state, locale)),
+        (true, Locale::FrCa) => format!("dégagement {}", period(state, locale)),
+        (false, Locale::EnCa) => format!(
+            "becoming {} {}",
+            lowercase(locale.condition(condition)),
+            period(state, locale),
+        ),
+        (false, Locale::FrCa) => format!(
+            "devenant {} {}",
+            lowercase(locale.condition(condition)),
+            period(state, locale),
+        ),
+    })
+}
+
+/// The amount of precipitation as a range, eg. "5–10 cm". Snow is assumed to be ten times as deep
+/// as the water it melts into.
+fn amount(millimetres: f32, snow: bool, unit: PrecipitationUnit, locale: Locale) -> Option<String> {
+    if millimetres < TRACE {
+        return None;
+    }
+
+    let (value, symbol, bounds): (f32, &str, &[f32]) = match (unit, snow) {
+        (PrecipitationUnit::Millimetres, true) => {
+            (millimetres, "cm", &[2., 5., 10., 15., 25., 40.])
+        }
+        (PrecipitationUnit::Millimetres, false) => (millimetres, "mm", &[5., 10., 15., 25., 50.]),
+        (PrecipitationUnit::Inches, true) => {
+            (millimetres / 2.54, "in", &[1., 2., 4., 6., 10., 15.])
+        }
+        (PrecipitationUnit::Inches, false) => (millimetres / 25.4, "in", &[0.25, 0.5, 1., 2.]),
+    };
+
+    let number = |value: f32| {
+        let precision = value
+            .to_string()
+            .split('.')
+            .nth(1)
+            .map_or(0, |decimals| decimals.len());
+        locale.decimal(value, precision)
+    };
+
+    let range = match (bounds.iter().position(|&bound| value < bound), locale) {
+        (Some(0), Locale::EnCa) => format!("up to {}", number(bounds[0])),
+        (Some(0), Locale::FrCa) => format!("jusqu'à {}", number(bounds[0])),
+        (Some(i), _) => format!("{}–{}", number(bounds[i - 1]), number(bounds[i])),
+        (None, Locale::EnCa) => format!("over {}", number(bounds[bounds.len() - 1])),
+        (None, Locale::FrCa) => format!("plus de {}", number(bounds[bounds.len() - 1])),
+    };
+
+    Some(format!("{} {}", range, symbol))
+}
+
+/// The part of the day an hour falls in, eg. "in the evening".
+fn period(state: &WeatherState, locale: Locale) -> &'static str {
+    match (state.time.hour(), locale) {
+        (5..=11, Locale::EnCa) => "in the morning",
+        (5..=11, Locale::FrCa) => "en matinée",
+        (12..=16, Locale::EnCa) => "in the afternoon",
+        (12..=16, Locale::FrCa) => "en après-midi",
+        (17..=21, Locale::EnCa) => "in the evening",
+        (17..=21, Locale::FrCa) => "en soirée",
+        (_, Locale::EnCa) => "overnight",
+        (_, Locale::FrCa) => "pendant la nuit",
+    }
+}
+
+fn hour(state: &WeatherState, locale: Locale) -> String {
+    locale.hour(state.time.hour())
+}
+
+fn is_precipitation(state: &WeatherState) -> bool {
+    matches!(&state.condition, Some(condition) if condition.is_precipitation())
+}
+
+/// Whether there's little enough cloud to call it clear.
+fn is_clear(condition: &WeatherCondition) -> bool {
+    condition.severity() <= 2
+}
+
+/// Whether the amount is better given as a depth of snow than of water.
+fn is_snow(condition: &WeatherCondition) -> bool {
+    matches!(
+        condition,
+        WeatherCondition::Snow(subtype)
+            if !matches!(subtype, SnowType::LightRainAndSnow | SnowType::RainAndSnow)
+    )
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn lowercase(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::weather::{Precipitation, Provider, Temperature};
+
+    fn start() -> time::OffsetDateTime {
+        time::date!(2021 - 01 - 31)
+            .with_time(time::time!(12:00))
+            .assume_offset(time::offset!(-5))
+    }
+
+    /// Hourly states from noon, each with a temperature (in °C), condition code, precipitation (in
+    /// mm) and wind speed (in m/s, from the west).
+    fn series(hours: &[(f32, u16, f32, f32)]) -> Vec<WeatherState> {
+        hours
+            .iter()
+            .enumerate()
+            .map(|(i, &(temp, code, precipitation, wind))| WeatherState {
+                time: start() + time::Duration::hours(i as i64),
+                sunrise: None,
+                sunset: None,
+                temp: Some(Temperature::from_celsius(temp)),
+                temp_min: None,
+                temp_max: None,
+                humidity: None,
+                wind: Some(Wind {
+                    speed: wind,
+                    direction: Some(270),
+                    gust: None,
+                }),
+                pressure: None,
+                precipitation: Some(Precipitation::from_millimetres(precipitation)),
+                clouds: None,
+                visibility: None,
+                condition: Some(WeatherCondition::from(code)),
+                provider: Provider::OpenWeather,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn snow_test() {
+        // Cloudy until 3pm, then 8 hours of snow and a clear, cold night.
+        let hourly = series(
+            &(0..24)
+                .map(|i| match i {
+                    0..=2 => (-5., 804, 0., 3.),
+                    3..=10 => (-6. - i as f32 / 2., 601, 1., 5.),
+                    _ => (-12. - (i as f32 - 10.) / 2., 800, 0., 2.),
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        assert_eq!(
+            Some("Snow beginning around 3pm, 5–10 cm, then clearing overnight; low -19°.".into()),
+            summarize(
+                Forecast::new(&hourly),
+                start(),
+                &Units::METRIC,
+                Locale::EnCa
+            ),
+        );
+        assert_eq!(
+            Some(
+                "Neige débutant vers 15\u{a0}h, 5–10 cm, puis dégagement pendant la nuit; \
+                 minimum -19°."
+                    .into()
+            ),
+            summarize(
+                Forecast::new(&hourly),
+                start(),
+                &Units::METRIC,
+                Locale::FrCa
+            ),
+        );
+    }
+
+    #[test]
+    fn rain_ending_test() {
+        // Light rain until 2pm with a one hour break, then cloudy and mild.
+        let hourly = series(&[
+            (5., 500, 0.5, 3.),
+            (5., 804, 0., 3.),
+            (6., 500, 1., 3.),
+            (7., 803, 0., 3.),
+            (8., 803, 0., 3.),
+            (6., 803, 0., 3.),
+        ]);
+
+        assert_eq!(
+            Some(
+                "Light rain ending around 3pm, up to 5 mm, then becoming mostly cloudy in the \
+                 afternoon; high 8°."
+                    .into()
+            ),
+            summarize(
+                Forecast::new(&hourly),
+                start(),
+                &Units::METRIC,
+                Locale::EnCa
+            ),
+        );
+    }
+
+    #[test]
+    fn sky_and_wind_test() {
+        // Clear and warming, windy in the afternoon, then clouding over in the evening.
+        let mut hourly = series(
+            &(0..12)
+                .map(|i| match i {
+                    0..=1 => (15. + i as f32, 800, 0., 4.),
+                    2..=4 => (18. + i as f32, 801, 0., 10. + i as f32 / 2.),
+                    5..=6 => (20., 801, 0., 5.),
+                    _ => (17., 804, 0., 4.),
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        assert_eq!(
+            Some(
+                "Clear, becoming cloudy in the evening; high 22°. Wind becoming W 43 km/h in the \
+                 afternoon, diminishing in the evening."
+                    .into()
+            ),
+            summarize(
+                Forecast::new(&hourly),
+                start(),
+                &Units::METRIC,
+                Locale::EnCa
+            ),
+        );
+        assert_eq!(
+            Some(
+                "Dégagé, devenant nuageux en soirée; maximum 22°. Vents devenant O 43 km/h en \
+                 après-midi, diminuant en soirée."
+                    .into()
+            ),
+            summarize(
+                Forecast::new(&hourly),
+                start(),
+                &Units::METRIC,
+                Locale::FrCa
+            ),
+        );
+
+        // A variable wind has no direction to give.
+        for state in &mut hourly {
+            state.wind.as_mut().unwrap().direction = None;
+        }
+        assert!(summarize(
+            Forecast::new(&hourly),
+            start(),
+            &Units::METRIC,
+            Locale::EnCa
+        )
+        .unwrap()
+        .contains("Wind becoming 43 km/h in the afternoon"));
+    }
+
+    #[test]
+    fn amount_test() {
+        let metric = PrecipitationUnit::Millimetres;
+        assert_eq!(None, amount(0.2, false, metric, Locale::EnCa));
+        assert_eq!(
+            Some("up to 2 cm".into()),
+            amount(1., true, metric, Locale::EnCa)
+        );
+        assert_eq!(
+            Some("over 50 mm".into()),
+            amount(60., false, metric, Locale::EnCa)
+        );
+        assert_eq!(
+            Some("0,25–0,5 in".into()),
+            amount(10., false, PrecipitationUnit::Inches, Locale::FrCa),
+        );
+    }
+
+    #[test]
+    fn stale_test() {
+        let hourly = series(&[(5., 800, 0., 3.), (5., 800, 0., 3.)]);
+        let later = start() + time::Duration::hours(2);
+
+        assert_eq!(
+            None,
+            summarize(Forecast::new(&hourly), later, &Units::METRIC, Locale::EnCa)
+        );
+    }
+}
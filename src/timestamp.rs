@@ -0,0 +1,55 @@
+//! Parsing of the timestamps in the feeds and APIs we read.
+
+/// An ISO 8601 timestamp with a numeric UTC offset, eg. `2020-12-17T18:12:00-05:00` from a CAP
+/// alert, or with fractional seconds as Home Assistant writes them, eg.
+/// `2020-12-17T14:00:00.042371+00:00`. The fraction is dropped.
+pub fn parse_iso8601(raw: &str) -> Option<time::OffsetDateTime> {
+    if raw.len() < 25 || !raw.is_ascii() {
+        return None;
+    }
+
+    let (local, offset) = raw.split_at(raw.len() - 6);
+    let fraction = match &local[19..] {
+        "" => "",
+        fraction => fraction.strip_prefix('.')?,
+    };
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let time = time::PrimitiveDateTime::parse(&local[..19], "%Y-%m-%dT%H:%M:%S").ok()?;
+    let sign = match &offset[..1] {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    if &offset[3..4] != ":" {
+        return None;
+    }
+    let hours: i32 = offset[1..3].parse().ok()?;
+    let minutes: i32 = offset[4..].parse().ok()?;
+
+    Some(time.assume_offset(time::UtcOffset::seconds(
+        sign * (hours * 3600 + minutes * 60),
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_iso8601_test() {
+        let expected = time::date!(2020 - 12 - 17)
+            .with_time(time::time!(23:12))
+            .assume_utc();
+
+        assert_eq!(Some(expected), parse_iso8601("2020-12-17T18:12:00-05:00"));
+        assert_eq!(
+            Some(expected),
+            parse_iso8601("2020-12-17T23:12:00.042371+00:00")
+        );
+        assert_eq!(None, parse_iso8601("2020-12-17T18:12:00Z"));
+        assert_eq!(None, parse_iso8601("2020-12-17T18:12:00 UTC-05:00"));
+    }
+}
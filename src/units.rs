@@ -0,0 +1,272 @@
+use std::str::FromStr;
+
+use crate::config::parse_env;
+
+/// The units used to display each kind of measurement. Values are always stored in SI-ish units
+/// internally (Kelvin, metres per second, hectopascals, millimetres) and only converted when
+/// formatted for display.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Units {
+    pub temperature: TemperatureUnit,
+    pub speed: SpeedUnit,
+    pub pressure: PressureUnit,
+    pub precipitation: PrecipitationUnit,
+}
+
+impl Units {
+    pub const METRIC: Self = Self {
+        temperature: TemperatureUnit::Celsius,
+        speed: SpeedUnit::KilometresPerHour,
+        pressure: PressureUnit::Hectopascals,
+        precipitation: PrecipitationUnit::Millimetres,
+    };
+
+    pub const IMPERIAL: Self = Self {
+        temperature: TemperatureUnit::Fahrenheit,
+        speed: SpeedUnit::MilesPerHour,
+        pressure: PressureUnit::InchesOfMercury,
+        precipitation: PrecipitationUnit::Inches,
+    };
+
+    /// Read the unit system from the `UNITS` environment variable (`metric` or `imperial`),
+    /// then apply any individual overrides from `TEMPERATURE_UNIT`, `WIND_SPEED_UNIT`,
+    /// `PRESSURE_UNIT` and `PRECIPITATION_UNIT`. Unrecognized values are ignored.
+    pub fn from_env() -> Self {
+        let mut units: Self = parse_env("UNITS").unwrap_or_default();
+
+        if let Some(temperature) = parse_env("TEMPERATURE_UNIT") {
+            units.temperature = temperature;
+        }
+        if let Some(speed) = parse_env("WIND_SPEED_UNIT") {
+            units.speed = speed;
+        }
+        if let Some(pressure) = parse_env("PRESSURE_UNIT") {
+            units.pressure = pressure;
+        }
+        if let Some(precipitation) = parse_env("PRECIPITATION_UNIT") {
+            units.precipitation = precipitation;
+        }
+
+        units
+    }
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self::METRIC
+    }
+}
+
+impl FromStr for Units {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "metric" => Ok(Self::METRIC),
+            "imperial" => Ok(Self::IMPERIAL),
+            _ => Err("Unknown unit system."),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    /// Convert a temperature in Kelvin to this unit.
+    pub fn convert(&self, kelvin: f32) -> f32 {
+        match self {
+            Self::Celsius => kelvin - 273.15,
+            Self::Fahrenheit => (kelvin - 273.15) * 1.8 + 32.,
+        }
+    }
+}
+
+impl FromStr for TemperatureUnit {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "c" | "celsius" => Ok(Self::Celsius),
+            "f" | "fahrenheit" => Ok(Self::Fahrenheit),
+            _ => Err("Unknown temperature unit."),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpeedUnit {
+    KilometresPerHour,
+    MilesPerHour,
+    MetresPerSecond,
+    Knots,
+    Beaufort,
+}
+
+impl SpeedUnit {
+    /// Upper bounds (exclusive, in m/s) of Beaufort forces 0 through 11. Anything faster is 12.
+    const BEAUFORT_SCALE: [f32; 12] = [
+        0.5, 1.6, 3.4, 5.5, 8.0, 10.8, 13.9, 17.2, 20.8, 24.5, 28.5, 32.7,
+    ];
+
+    /// Convert a speed in metres per second to this unit.
+    pub fn convert(&self, speed: f32) -> f32 {
+        match self {
+            Self::KilometresPerHour => speed * 3.6,
+            Self::MilesPerHour => speed * 2.236_936,
+            Self::MetresPerSecond => speed,
+            Self::Knots => speed * 1.943_844,
+            Self::Beaufort => Self::BEAUFORT_SCALE
+                .iter()
+                .position(|&limit| speed < limit)
+                .unwrap_or(Self::BEAUFORT_SCALE.len()) as f32,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::KilometresPerHour => "km/h",
+            Self::MilesPerHour => "mph",
+            Self::MetresPerSecond => "m/s",
+            Self::Knots => "kn",
+            Self::Beaufort => "Bft",
+        }
+    }
+}
+
+impl FromStr for SpeedUnit {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "km/h" | "kmh" | "kph" => Ok(Self::KilometresPerHour),
+            "mph" => Ok(Self::MilesPerHour),
+            "m/s" | "ms" => Ok(Self::MetresPerSecond),
+            "kn" | "kt" | "knots" => Ok(Self::Knots),
+            "bft" | "beaufort" => Ok(Self::Beaufort),
+            _ => Err("Unknown wind speed unit."),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PressureUnit {
+    Hectopascals,
+    Kilopascals,
+    InchesOfMercury,
+}
+
+impl PressureUnit {
+    /// Convert a pressure in hectopascals to this unit.
+    pub fn convert(&self, pressure: f32) -> f32 {
+        match self {
+            Self::Hectopascals => pressure,
+            Self::Kilopascals => pressure / 10.,
+            Self::InchesOfMercury => pressure * 0.029_53,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Hectopascals => "hPa",
+            Self::Kilopascals => "kPa",
+            Self::InchesOfMercury => "inHg",
+        }
+    }
+
+    /// The number of decimal places worth displaying in this unit.
+    pub fn precision(&self) -> usize {
+        match self {
+            Self::Hectopascals => 0,
+            Self::Kilopascals => 1,
+            Self::InchesOfMercury => 2,
+        }
+    }
+}
+
+impl FromStr for PressureUnit {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hpa" | "mb" | "mbar" => Ok(Self::Hectopascals),
+            "kpa" => Ok(Self::Kilopascals),
+            "inhg" => Ok(Self::InchesOfMercury),
+            _ => Err("Unknown pressure unit."),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrecipitationUnit {
+    Millimetres,
+    Inches,
+}
+
+impl PrecipitationUnit {
+    /// Convert an amount in millimetres to this unit.
+    pub fn convert(&self, amount: f32) -> f32 {
+        match self {
+            Self::Millimetres => amount,
+            Self::Inches => amount / 25.4,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Millimetres => "mm",
+            Self::Inches => "in",
+        }
+    }
+
+    /// The number of decimal places worth displaying in this unit.
+    pub fn precision(&self) -> usize {
+        match self {
+            Self::Millimetres => 1,
+            Self::Inches => 2,
+        }
+    }
+}
+
+impl FromStr for PrecipitationUnit {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mm" => Ok(Self::Millimetres),
+            "in" | "inches" => Ok(Self::Inches),
+            _ => Err("Unknown precipitation unit."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn beaufort_test() {
+        assert_eq!(0., SpeedUnit::Beaufort.convert(0.2));
+        assert_eq!(4., SpeedUnit::Beaufort.convert(5.5));
+        assert_eq!(11., SpeedUnit::Beaufort.convert(32.));
+        assert_eq!(12., SpeedUnit::Beaufort.convert(40.));
+    }
+
+    #[test]
+    fn temperature_test() {
+        assert_eq!(0., TemperatureUnit::Celsius.convert(273.15));
+        assert_eq!(32., TemperatureUnit::Fahrenheit.convert(273.15));
+        assert!((TemperatureUnit::Fahrenheit.convert(233.15) + 40.).abs() < 0.01);
+    }
+
+    #[test]
+    fn from_str_test() {
+        assert_eq!(Ok(Units::IMPERIAL), "Imperial".parse());
+        assert_eq!(Ok(SpeedUnit::Knots), "kt".parse());
+        assert_eq!(Ok(PressureUnit::InchesOfMercury), "inHg".parse());
+        assert!("furlongs/fortnight".parse::<SpeedUnit>().is_err());
+    }
+}
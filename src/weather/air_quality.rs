@@ -0,0 +1,171 @@
+use std::env;
+use std::fmt;
+
+use super::{aqhi, open_weather};
+use crate::http;
+use crate::location::Location;
+
+/// Fetch air quality from the MSC Datamart AQHI feed if `AQHI_LOCATION` is configured, falling
+/// back to OpenWeather's air pollution API at `location`.
+pub async fn query(client: &http::Client, location: &Location) -> Option<AirQualityReport> {
+    if let Ok(location) = env::var("AQHI_LOCATION") {
+        let region = env::var("AQHI_REGION").unwrap_or_else(|_| "que".to_string());
+
+        match aqhi::get_aqhi(client, &region, &location).await {
+            Ok(report) => return Some(report),
+            Err(e) => eprintln!("Unable to fetch AQHI: {}", e),
+        }
+    }
+
+    match open_weather::get_air_pollution(client, location).await {
+        Ok(report) => Some(report),
+        Err(e) => {
+            eprintln!("Unable to fetch OpenWeather air pollution: {}", e);
+            None
+        }
+    }
+}
+
+pub struct AirQualityReport {
+    pub current: Option<AirQuality>,
+    pub forecast: Vec<AirQuality>,
+}
+
+pub struct AirQuality {
+    /// The observation time, or the issue time for MSC forecasts, which cover named periods
+    /// rather than specific times.
+    pub time: time::OffsetDateTime,
+
+    /// The forecast period, eg. "Tonight", for forecasts that have one.
+    pub period: Option<String>,
+
+    pub index: AirQualityIndex,
+    pub dominant_pollutant: Option<Pollutant>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AirQualityIndex {
+    /// Canada's Air Quality Health Index, from 1 upwards (nominally to 10, but can exceed it).
+    Aqhi(f32),
+
+    /// OpenWeather's air quality index, from 1 (good) to 5 (very poor).
+    OpenWeather(u8),
+}
+
+impl AirQualityIndex {
+    pub fn category(&self) -> AirQualityCategory {
+        match self {
+            Self::Aqhi(index) if index.round() <= 3. => AirQualityCategory::Low,
+            Self::Aqhi(index) if index.round() <= 6. => AirQualityCategory::Moderate,
+            Self::Aqhi(index) if index.round() <= 10. => AirQualityCategory::High,
+            Self::Aqhi(_) => AirQualityCategory::VeryHigh,
+            Self::OpenWeather(0..=2) => AirQualityCategory::Low,
+            Self::OpenWeather(3) => AirQualityCategory::Moderate,
+            Self::OpenWeather(4) => AirQualityCategory::High,
+            Self::OpenWeather(_) => AirQualityCategory::VeryHigh,
+        }
+    }
+
+    /// The short name of the index, eg. "AQHI".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Aqhi(_) => "AQHI",
+            Self::OpenWeather(_) => "AQI",
+        }
+    }
+}
+
+impl fmt::Display for AirQualityIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            // The AQHI is reported as "10+" above 10.
+            Self::Aqhi(index) if index.round() > 10. => write!(f, "10+"),
+            Self::Aqhi(index) => write!(f, "{}", index.round()),
+            Self::OpenWeather(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+/// Health risk categories, following the AQHI.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum AirQualityCategory {
+    Low,
+    Moderate,
+    High,
+    VeryHigh,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pollutant {
+    CarbonMonoxide,
+    NitrogenDioxide,
+    Ozone,
+    SulphurDioxide,
+    FineParticulateMatter,
+    CoarseParticulateMatter,
+}
+
+impl Pollutant {
+    /// Concentration (in μg/m³) at which OpenWeather considers the pollutant "very poor", used
+    /// to compare pollutants to one another.
+    pub fn very_poor_concentration(&self) -> f32 {
+        match self {
+            Self::CarbonMonoxide => 15_400.,
+            Self::NitrogenDioxide => 200.,
+            Self::Ozone => 180.,
+            Self::SulphurDioxide => 350.,
+            Self::FineParticulateMatter => 75.,
+            Self::CoarseParticulateMatter => 200.,
+        }
+    }
+
+    pub fn abbreviation(&self) -> &'static str {
+        match self {
+            Self::CarbonMonoxide => "CO",
+            Self::NitrogenDioxide => "NO₂",
+            Self::Ozone => "O₃",
+            Self::SulphurDioxide => "SO₂",
+            Self::FineParticulateMatter => "PM2.5",
+            Self::CoarseParticulateMatter => "PM10",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn category_test() {
+        assert_eq!(
+            AirQualityCategory::Low,
+            AirQualityIndex::Aqhi(3.4).category()
+        );
+        assert_eq!(
+            AirQualityCategory::Moderate,
+            AirQualityIndex::Aqhi(3.5).category()
+        );
+        assert_eq!(
+            AirQualityCategory::High,
+            AirQualityIndex::Aqhi(10.).category()
+        );
+        assert_eq!(
+            AirQualityCategory::VeryHigh,
+            AirQualityIndex::Aqhi(12.).category()
+        );
+        assert_eq!(
+            AirQualityCategory::Low,
+            AirQualityIndex::OpenWeather(2).category()
+        );
+        assert_eq!(
+            AirQualityCategory::VeryHigh,
+            AirQualityIndex::OpenWeather(5).category()
+        );
+    }
+
+    #[test]
+    fn display_test() {
+        assert_eq!("3", AirQualityIndex::Aqhi(3.4).to_string());
+        assert_eq!("10+", AirQualityIndex::Aqhi(11.).to_string());
+    }
+}
@@ -0,0 +1,614 @@
+//! Public alerts from Environment Canada, published as CAP-CP XML on the MSC Datamart. Messages
+//! are read as they're published, and the alerts whose areas contain the primary location are kept
+//! in the cache until they expire or are updated or cancelled.
+
+use std::env;
+
+use futures::future;
+use serde::{Deserialize, Serialize};
+
+use super::DecodeError;
+use crate::cache::Cache;
+use crate::http;
+use crate::timestamp::parse_iso8601;
+
+const ALERTS_CACHE: &str = "alerts.json";
+const DATAMART_URL: &str = "https://dd.weather.gc.ca/alerts/cap";
+
+/// How far back to look for messages, when there's no record of the last check.
+const MAX_LOOKBACK_HOURS: i64 = 24;
+
+/// Update the active alerts at `point` (latitude and longitude) with the messages published by
+/// `ALERTS_OFFICE` since the last check, eg. `CWUL` for Quebec. Returns no alerts unless it's set.
+pub async fn query(client: &http::Client, cache: &Cache, point: (f64, f64)) -> Vec<Alert> {
+    let office = match env::var("ALERTS_OFFICE") {
+        Ok(office) => office,
+        Err(_) => return Vec::new(),
+    };
+
+    let now = time::OffsetDateTime::now_utc();
+    let mut state: AlertState = cache
+        .load(ALERTS_CACHE)
+        .ok()
+        .and_then(|cached| serde_json::from_slice(&cached.value).ok())
+        .unwrap_or_default();
+
+    // Alerts already known about are kept if the Datamart can't be reached.
+    state
+        .update(client, DATAMART_URL, &office, point, now)
+        .await;
+    state.expire(now);
+
+    if let Err(e) = serde_json::to_vec(&state)
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            cache
+                .store(ALERTS_CACHE, &data, now)
+                .map_err(|e| e.to_string())
+        })
+    {
+        eprintln!("Unable to cache alerts: {}", e);
+    }
+
+    let mut alerts = state.alerts;
+    alerts.sort_by(|a, b| b.severity.cmp(&a.severity).then(b.sent.cmp(&a.sent)));
+    alerts
+}
+
+/// What's kept in the cache between refreshes.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+struct AlertState {
+    /// When the Datamart was last checked, as a Unix timestamp.
+    checked_at: Option<i64>,
+
+    /// The files already read from the directories checked last time.
+    seen: Vec<String>,
+
+    alerts: Vec<Alert>,
+}
+
+impl AlertState {
+    /// Read any new messages from the hourly directories since the last check (and the hour
+    /// before it, for messages published while it was being checked). If any directory can't be
+    /// listed, the check isn't recorded, so that the same hours are checked again next time.
+    async fn update(
+        &mut self,
+        client: &http::Client,
+        base_url: &str,
+        office: &str,
+        point: (f64, f64),
+        now: time::OffsetDateTime,
+    ) {
+        let hour = now.unix_timestamp() / 3600;
+        let since = self
+            .checked_at
+            .map_or(hour - MAX_LOOKBACK_HOURS, |checked_at| {
+                checked_at / 3600 - 1
+            })
+            .max(hour - MAX_LOOKBACK_HOURS);
+
+        let directories: Vec<String> = (since..=hour)
+            .map(|hour| {
+                let time = time::OffsetDateTime::from_unix_timestamp(hour * 3600);
+                format!(
+                    "{}/{}/{}/{}/",
+                    base_url,
+                    time.format("%Y%m%d"),
+                    office,
+                    time.format("%H")
+                )
+            })
+            .collect();
+
+        let listings =
+            future::join_all(directories.iter().map(|url| client.get_text_if_exists(url))).await;
+
+        let mut complete = true;
+        let mut files = Vec::new();
+        for (url, listing) in directories.iter().zip(listings) {
+            match listing {
+                Ok(Some(listing)) => files.extend(
+                    cap_files(&listing)
+                        .into_iter()
+                        .map(|file| format!("{}{}", url, file)),
+                ),
+                // Directories only exist for hours with messages.
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Unable to list alerts in {}: {}", url, e);
+                    complete = false;
+                }
+            }
+        }
+
+        let new_files: Vec<&String> = files
+            .iter()
+            .filter(|file| !self.seen.contains(file))
+            .collect();
+        let bodies = future::join_all(new_files.iter().map(|url| client.get_text(url))).await;
+
+        let mut messages = Vec::new();
+        let mut failed = Vec::new();
+        for (url, body) in new_files.into_iter().zip(bodies) {
+            match body.and_then(|body| decode(&body).map_err(|e| e.to_string())) {
+                Ok(message) => messages.push(message),
+                Err(e) => {
+                    eprintln!("Unable to read alert {}: {}", url, e);
+                    failed.push(url.clone());
+                }
+            }
+        }
+
+        messages.sort_by_key(|message| message.alert.sent);
+        for message in messages {
+            self.apply(message, point);
+        }
+
+        // Files that couldn't be read are tried again next time.
+        let read = files.into_iter().filter(|file| !failed.contains(file));
+        if complete {
+            self.seen = read.collect();
+            self.checked_at = Some(now.unix_timestamp());
+        } else {
+            for file in read {
+                if !self.seen.contains(&file) {
+                    self.seen.push(file);
+                }
+            }
+        }
+    }
+
+    /// Replace or remove the alerts a message refers to, and add its alert if it's in effect at
+    /// `point`.
+    fn apply(&mut self, message: Message, point: (f64, f64)) {
+        if !message.actual {
+            return;
+        }
+
+        self.alerts
+            .retain(|alert| !message.references.contains(&alert.identifier));
+
+        if message.kind != MessageType::Cancel
+            && message.contains(point)
+            && !self
+                .alerts
+                .iter()
+                .any(|alert| alert.identifier == message.alert.identifier)
+        {
+            self.alerts.push(message.alert);
+        }
+    }
+
+    fn expire(&mut self, now: time::OffsetDateTime) {
+        let now = now.unix_timestamp();
+        self.alerts
+            .retain(|alert| !matches!(alert.expires, Some(expires) if expires <= now));
+    }
+}
+
+/// The names of the CAP files in a Datamart directory listing.
+fn cap_files(listing: &str) -> Vec<String> {
+    let mut files: Vec<String> = listing
+        .split("href=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .filter(|href| href.ends_with(".cap") && !href.contains('/'))
+        .map(str::to_string)
+        .collect();
+
+    files.sort();
+    files.dedup();
+    files
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Alert {
+    /// Unique to each message, including updates of the same alert.
+    pub identifier: String,
+
+    /// When the message was sent, as a Unix timestamp.
+    pub sent: i64,
+
+    /// When the alert stops being in effect, as a Unix timestamp.
+    pub expires: Option<i64>,
+
+    pub severity: Severity,
+
+    /// The alert in each language it was issued in.
+    pub texts: Vec<AlertText>,
+}
+
+impl Alert {
+    /// The text in a language, eg. `en` or `fr`, or else the first language available.
+    pub fn text(&self, language: &str) -> Option<&AlertText> {
+        self.texts
+            .iter()
+            .find(|text| {
+                text.language
+                    .to_lowercase()
+                    .starts_with(&language.to_lowercase())
+            })
+            .or_else(|| self.texts.first())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AlertText {
+    /// eg. `en-CA` or `fr-CA`.
+    pub language: String,
+
+    /// eg. "freezing rain".
+    pub event: String,
+
+    /// eg. "freezing rain warning in effect".
+    pub headline: Option<String>,
+
+    pub description: Option<String>,
+    pub instruction: Option<String>,
+
+    /// The names of the areas the alert is in effect for.
+    pub area: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum Severity {
+    Unknown,
+    Minor,
+    Moderate,
+    Severe,
+    Extreme,
+}
+
+impl Severity {
+    fn from_cap(severity: &str) -> Self {
+        match severity {
+            "Extreme" => Self::Extreme,
+            "Severe" => Self::Severe,
+            "Moderate" => Self::Moderate,
+            "Minor" => Self::Minor,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MessageType {
+    Alert,
+    Update,
+    Cancel,
+}
+
+/// A CAP message, with the parts used to decide whether and how it changes the active alerts.
+#[derive(Debug)]
+struct Message {
+    alert: Alert,
+    kind: MessageType,
+
+    /// False for exercises, tests and drafts.
+    actual: bool,
+
+    /// The identifiers of earlier messages that this one updates or cancels.
+    references: Vec<String>,
+
+    /// Each area's polygon, as latitude and longitude pairs.
+    polygons: Vec<Vec<(f64, f64)>>,
+}
+
+impl Message {
+    fn contains(&self, point: (f64, f64)) -> bool {
+        self.polygons
+            .iter()
+            .any(|polygon| polygon_contains(polygon, point))
+    }
+}
+
+/// ```xml
+/// <alert xmlns="urn:oasis:names:tc:emergency:cap:1.2">
+///     <identifier>urn:oid:2.49.0.1.124.1234567890.2020</identifier>
+///     <sender>cap-pac@canada.ca</sender>
+///     <sent>2020-12-17T18:12:00-00:00</sent>
+///     <status>Actual</status>
+///     <msgType>Update</msgType>
+///     <references>cap-pac@canada.ca,urn:oid:2.49.0.1.124.0987654321.2020,2020-12-17T10:00:00-00:00</references>
+///     <info>
+///         <language>en-CA</language>
+///         <event>freezing rain</event>
+///         <severity>Moderate</severity>
+///         <expires>2020-12-18T10:12:00-00:00</expires>
+///         <headline>freezing rain warning in effect</headline>
+///         <area>
+///             <areaDesc>Montréal Island</areaDesc>
+///             <polygon>45.41,-73.98 45.70,-73.47 45.41,-73.47 45.41,-73.98</polygon>
+///         </area>
+///     </info>
+/// </alert>
+/// ```
+fn decode(xml: &str) -> Result<Message, DecodeError> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| DecodeError {
+        path: String::new(),
+        message: e.to_string(),
+    })?;
+    let root = document.root_element();
+
+    let sent = parse_iso8601(child_text(&root, "sent").ok_or_else(|| missing("sent"))?)
+        .ok_or_else(|| invalid("sent"))?;
+    let kind = match child_text(&root, "msgType") {
+        Some("Alert") => MessageType::Alert,
+        Some("Update") => MessageType::Update,
+        Some("Cancel") => MessageType::Cancel,
+        _ => return Err(invalid("msgType")),
+    };
+
+    let infos: Vec<roxmltree::Node> = root
+        .children()
+        .filter(|node| node.has_tag_name("info"))
+        .collect();
+
+    let mut polygons = Vec::new();
+    let mut texts = Vec::new();
+    for info in &infos {
+        let areas: Vec<roxmltree::Node> = info
+            .children()
+            .filter(|node| node.has_tag_name("area"))
+            .collect();
+
+        // Each language has the same areas.
+        if texts.is_empty() {
+            for area in &areas {
+                for polygon in area.children().filter(|node| node.has_tag_name("polygon")) {
+                    polygons.push(parse_polygon(polygon.text().unwrap_or("")));
+                }
+            }
+        }
+
+        texts.push(AlertText {
+            language: child_text(info, "language").unwrap_or("en-US").to_string(),
+            event: child_text(info, "event")
+                .ok_or_else(|| missing("info.event"))?
+                .to_string(),
+            headline: child_text(info, "headline").map(str::to_string),
+            description: child_text(info, "description").map(str::to_string),
+            instruction: child_text(info, "instruction").map(str::to_string),
+            area: areas
+                .iter()
+                .filter_map(|area| child_text(area, "areaDesc"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        });
+    }
+
+    Ok(Message {
+        alert: Alert {
+            identifier: child_text(&root, "identifier")
+                .ok_or_else(|| missing("identifier"))?
+                .to_string(),
+            sent: sent.unix_timestamp(),
+            expires: infos
+                .iter()
+                .filter_map(|info| child_text(info, "expires").and_then(parse_iso8601))
+                .map(|expires| expires.unix_timestamp())
+                .max(),
+            severity: infos
+                .iter()
+                .map(|info| Severity::from_cap(child_text(info, "severity").unwrap_or("")))
+                .max()
+                .unwrap_or(Severity::Unknown),
+            texts,
+        },
+        kind,
+        actual: child_text(&root, "status") == Some("Actual"),
+        // Each reference is `sender,identifier,sent`.
+        references: child_text(&root, "references")
+            .unwrap_or("")
+            .split_whitespace()
+            .filter_map(|reference| reference.split(',').nth(1))
+            .map(str::to_string)
+            .collect(),
+        polygons,
+    })
+}
+
+fn child_text<'a>(node: &roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|node| node.has_tag_name(name))
+        .and_then(|node| node.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+}
+
+fn missing(path: &str) -> DecodeError {
+    DecodeError {
+        path: path.to_string(),
+        message: "Missing element.".to_string(),
+    }
+}
+
+fn invalid(path: &str) -> DecodeError {
+    DecodeError {
+        path: path.to_string(),
+        message: "Invalid value.".to_string(),
+    }
+}
+
+/// A polygon as space-separated `latitude,longitude` pairs.
+fn parse_polygon(raw: &str) -> Vec<(f64, f64)> {
+    raw.split_whitespace()
+        .filter_map(|pair| {
+            let mut coordinates = pair.split(',').map(|c| c.parse::<f64>().ok());
+            Some((coordinates.next()??, coordinates.next()??))
+        })
+        .collect()
+}
+
+/// Whether a polygon contains a point, by counting how many of its edges a ray from the point
+/// crosses. Polygons are small enough to treat latitude and longitude as planar.
+fn polygon_contains(polygon: &[(f64, f64)], (latitude, longitude): (f64, f64)) -> bool {
+    let mut inside = false;
+
+    for (i, &(lat_a, lon_a)) in polygon.iter().enumerate() {
+        let (lat_b, lon_b) = polygon[(i + 1) % polygon.len()];
+
+        if (lat_a > latitude) != (lat_b > latitude)
+            && longitude < lon_a + (latitude - lat_a) / (lat_b - lat_a) * (lon_b - lon_a)
+        {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::stub::StubServer;
+
+    const MONTREAL: (f64, f64) = (45.5, -73.6);
+    const QUEBEC_CITY: (f64, f64) = (46.8, -71.2);
+
+    fn message(xml: &str) -> Message {
+        decode(xml).unwrap()
+    }
+
+    #[test]
+    fn decode_test() {
+        let message = message(include_str!("../../tests/fixtures/msc/cap_alert.xml"));
+
+        assert_eq!(MessageType::Alert, message.kind);
+        assert!(message.actual);
+        assert_eq!(Severity::Moderate, message.alert.severity);
+        assert_eq!(
+            Some(
+                time::date!(2020 - 12 - 18)
+                    .with_time(time::time!(10:00))
+                    .assume_utc()
+                    .unix_timestamp()
+            ),
+            message.alert.expires,
+        );
+
+        assert_eq!(2, message.alert.texts.len());
+        let english = message.alert.text("en").unwrap();
+        assert_eq!("freezing rain", english.event);
+        assert_eq!(
+            Some("freezing rain warning in effect"),
+            english.headline.as_deref()
+        );
+        assert_eq!("Montréal Island, Laval", english.area);
+        assert_eq!(
+            Some("avertissement de pluie verglaçante en vigueur"),
+            message.alert.text("fr").unwrap().headline.as_deref()
+        );
+
+        assert_eq!(2, message.polygons.len());
+        assert!(message.contains(MONTREAL));
+        assert!(!message.contains(QUEBEC_CITY));
+    }
+
+    #[test]
+    fn apply_test() {
+        let mut state = AlertState::default();
+
+        state.apply(
+            message(include_str!("../../tests/fixtures/msc/cap_alert.xml")),
+            QUEBEC_CITY,
+        );
+        assert!(state.alerts.is_empty());
+
+        state.apply(
+            message(include_str!("../../tests/fixtures/msc/cap_alert.xml")),
+            MONTREAL,
+        );
+        assert_eq!(1, state.alerts.len());
+
+        // The update replaces the original alert.
+        let update = message(include_str!("../../tests/fixtures/msc/cap_update.xml"));
+        let identifier = update.alert.identifier.clone();
+        state.apply(update, MONTREAL);
+        assert_eq!(1, state.alerts.len());
+        assert_eq!(identifier, state.alerts[0].identifier);
+        assert_eq!(Severity::Severe, state.alerts[0].severity);
+
+        state.apply(
+            message(include_str!("../../tests/fixtures/msc/cap_cancel.xml")),
+            MONTREAL,
+        );
+        assert!(state.alerts.is_empty());
+    }
+
+    #[test]
+    fn expire_test() {
+        let mut state = AlertState::default();
+        state.apply(
+            message(include_str!("../../tests/fixtures/msc/cap_alert.xml")),
+            MONTREAL,
+        );
+
+        let expires = time::OffsetDateTime::from_unix_timestamp(state.alerts[0].expires.unwrap());
+        state.expire(expires - time::Duration::minute());
+        assert_eq!(1, state.alerts.len());
+        state.expire(expires);
+        assert!(state.alerts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn update_test() {
+        let client = http::Client::new(http::ClientConfig {
+            max_attempts: 1,
+            ..Default::default()
+        });
+        let now = time::date!(2020 - 12 - 17)
+            .with_time(time::time!(14:30))
+            .assume_utc();
+        let previous = || AlertState {
+            checked_at: Some((now - time::Duration::minutes(20)).unix_timestamp()),
+            seen: vec!["https://example.com/20201217/CWUL/13/alert.cap".to_string()],
+            alerts: Vec::new(),
+        };
+
+        // The 13:00 and 14:00 directories can't be listed, so they're checked again next time.
+        let server = StubServer::start(vec![(500, b""), (500, b"")]).await;
+        let mut state = previous();
+        state
+            .update(&client, &server.url, "CWUL", MONTREAL, now)
+            .await;
+        assert_eq!(previous(), state);
+
+        // Hours without messages don't have directories.
+        let server = StubServer::start(vec![(404, b""), (404, b"")]).await;
+        state
+            .update(&client, &server.url, "CWUL", MONTREAL, now)
+            .await;
+        assert_eq!(Some(now.unix_timestamp()), state.checked_at);
+        assert!(state.seen.is_empty());
+    }
+
+    #[test]
+    fn cap_files_test() {
+        let listing = r#"<a href="?C=N;O=D">Name</a> <a href="/alerts/cap/20201217/">Parent Directory</a>
+<a href="T_WWCN11_C_CWUL_202012171812_1234567890.cap">T_WWCN11_C_CWUL_202012171812_1234567890.cap</a>
+<a href="T_WWCN11_C_CWUL_202012171745_0987654321.cap">T_WWCN11_C_CWUL_202012171745_0987654321.cap</a>"#;
+
+        assert_eq!(
+            vec![
+                "T_WWCN11_C_CWUL_202012171745_0987654321.cap",
+                "T_WWCN11_C_CWUL_202012171812_1234567890.cap",
+            ],
+            cap_files(listing)
+        );
+    }
+
+    #[test]
+    fn polygon_contains_test() {
+        let square = [
+            (45., -74.),
+            (46., -74.),
+            (46., -73.),
+            (45., -73.),
+            (45., -74.),
+        ];
+        assert!(polygon_contains(&square, MONTREAL));
+        assert!(!polygon_contains(&square, QUEBEC_CITY));
+        assert!(!polygon_contains(&[], MONTREAL));
+    }
+}
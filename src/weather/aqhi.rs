@@ -0,0 +1,170 @@
+//! Canada's Air Quality Health Index, from the MSC Datamart's XML feeds.
+
+use super::air_quality::{AirQuality, AirQualityIndex, AirQualityReport};
+use super::DecodeError;
+use crate::http;
+
+/// Fetch the current AQHI observation and forecast for a location. `region` is the Datamart's
+/// regional directory (`atl`, `ont`, `pnr`, `pyr` or `que`) and `location` the AQHI location ID.
+pub async fn get_aqhi(
+    client: &http::Client,
+    region: &str,
+    location: &str,
+) -> Result<AirQualityReport, String> {
+    let base_url = format!("https://dd.weather.gc.ca/air_quality/aqhi/{}", region);
+
+    let observation_url = format!(
+        "{}/observation/realtime/xml/AQ_OBS_{}_CURRENT.xml",
+        base_url, location
+    );
+    let forecast_url = format!(
+        "{}/forecast/realtime/xml/AQ_FCST_{}_CURRENT.xml",
+        base_url, location
+    );
+
+    let (observation, forecast) = tokio::join!(
+        client.get_text(&observation_url),
+        client.get_text(&forecast_url),
+    );
+
+    Ok(AirQualityReport {
+        current: Some(decode_observation(&observation?).map_err(|e| e.to_string())?),
+        forecast: forecast
+            .and_then(|forecast| decode_forecast(&forecast).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Unable to fetch AQHI forecast: {}", e);
+                Vec::new()
+            }),
+    })
+}
+
+/// ```xml
+/// <conditionAirQuality>
+///     <region nameEn="Montréal" nameFr="Montréal">FAMXX</region>
+///     <dateStamp name="Observation">
+///         <UTCStamp>20210615100000</UTCStamp>
+///     </dateStamp>
+///     <airQualityHealthIndex>3.4</airQualityHealthIndex>
+/// </conditionAirQuality>
+/// ```
+pub fn decode_observation(xml: &str) -> Result<AirQuality, DecodeError> {
+    let document = parse(xml)?;
+    let root = document.root_element();
+
+    Ok(AirQuality {
+        time: utc_stamp(&root)?,
+        period: None,
+        index: AirQualityIndex::Aqhi(index(&root)?),
+        dominant_pollutant: None,
+    })
+}
+
+/// ```xml
+/// <forecastAirQuality>
+///     <dateStamp name="forecast issue">
+///         <UTCStamp>20210615103000</UTCStamp>
+///     </dateStamp>
+///     <forecastGroup>
+///         <forecast periodID="1">
+///             <period lang="EN" forecastName="Today">Today</period>
+///             <period lang="FR" forecastName="Aujourd'hui">Aujourd'hui</period>
+///             <airQualityHealthIndex>4</airQualityHealthIndex>
+///         </forecast>
+///     </forecastGroup>
+/// </forecastAirQuality>
+/// ```
+pub fn decode_forecast(xml: &str) -> Result<Vec<AirQuality>, DecodeError> {
+    let document = parse(xml)?;
+    let root = document.root_element();
+    let issued = utc_stamp(&root)?;
+
+    root.descendants()
+        .filter(|node| node.has_tag_name("forecast"))
+        .map(|forecast| {
+            Ok(AirQuality {
+                time: issued,
+                period: forecast
+                    .children()
+                    .find(|node| {
+                        node.has_tag_name("period")
+                            && matches!(node.attribute("lang"), Some(lang) if lang.eq_ignore_ascii_case("en"))
+                    })
+                    .and_then(|node| node.text())
+                    .map(|text| text.trim().to_string()),
+                index: AirQualityIndex::Aqhi(index(&forecast)?),
+                dominant_pollutant: None,
+            })
+        })
+        .collect()
+}
+
+fn parse(xml: &str) -> Result<roxmltree::Document<'_>, DecodeError> {
+    roxmltree::Document::parse(xml).map_err(|e| DecodeError {
+        path: String::new(),
+        message: e.to_string(),
+    })
+}
+
+fn child_text<'a>(node: &roxmltree::Node<'a, '_>, name: &str) -> Result<&'a str, DecodeError> {
+    node.descendants()
+        .find(|node| node.has_tag_name(name))
+        .and_then(|node| node.text())
+        .map(str::trim)
+        .ok_or_else(|| DecodeError {
+            path: name.to_string(),
+            message: "Missing element.".to_string(),
+        })
+}
+
+fn utc_stamp(node: &roxmltree::Node) -> Result<time::OffsetDateTime, DecodeError> {
+    time::PrimitiveDateTime::parse(child_text(node, "UTCStamp")?, "%Y%m%d%H%M%S")
+        .map(|time| time.assume_utc())
+        .map_err(|e| DecodeError {
+            path: "UTCStamp".to_string(),
+            message: e.to_string(),
+        })
+}
+
+fn index(node: &roxmltree::Node) -> Result<f32, DecodeError> {
+    child_text(node, "airQualityHealthIndex")?
+        .parse()
+        .map_err(|_| DecodeError {
+            path: "airQualityHealthIndex".to_string(),
+            message: "Invalid index.".to_string(),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_observation_test() {
+        let observation = decode_observation(include_str!(
+            "../../tests/fixtures/msc/aqhi_observation.xml"
+        ))
+        .unwrap();
+
+        assert_eq!(AirQualityIndex::Aqhi(3.4), observation.index);
+        assert_eq!(
+            time::date!(2021 - 06 - 15).with_time(time::time!(10:00)),
+            time::PrimitiveDateTime::new(observation.time.date(), observation.time.time()),
+        );
+    }
+
+    #[test]
+    fn decode_forecast_test() {
+        let forecast =
+            decode_forecast(include_str!("../../tests/fixtures/msc/aqhi_forecast.xml")).unwrap();
+
+        assert_eq!(4, forecast.len());
+        assert_eq!(Some("Tonight"), forecast[1].period.as_deref());
+        assert_eq!(AirQualityIndex::Aqhi(7.), forecast[2].index);
+    }
+
+    #[test]
+    fn decode_invalid_test() {
+        assert!(decode_observation("<conditionAirQuality/>").is_err());
+        assert!(decode_observation("not xml").is_err());
+    }
+}
@@ -0,0 +1,359 @@
+//! Queries over a series of forecasts, such as the hourly forecast of a report.
+
+use super::{Precipitation, Pressure, Temperature, WeatherCondition, WeatherState, Wind};
+
+/// A view of forecasts in chronological order, eg. `Forecast::new(&report.hourly)`.
+#[derive(Clone, Copy)]
+pub struct Forecast<'a> {
+    states: &'a [WeatherState],
+}
+
+impl<'a> Forecast<'a> {
+    /// Wrap forecasts that are already sorted by time, as providers return them.
+    pub fn new(states: &'a [WeatherState]) -> Self {
+        Self { states }
+    }
+
+    pub fn states(&self) -> &'a [WeatherState] {
+        self.states
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'a, WeatherState> {
+        self.states.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// The forecasts from `start` (inclusive) to `end` (exclusive).
+    pub fn between(&self, start: time::OffsetDateTime, end: time::OffsetDateTime) -> Self {
+        self.slice(|state| state.time >= start, |state| state.time >= end)
+    }
+
+    /// The forecasts strictly after `time`.
+    pub fn after(&self, time: time::OffsetDateTime) -> Self {
+        self.slice(|state| state.time > time, |_| false)
+    }
+
+    /// The forecasts for a calendar day, from midnight to midnight at the given UTC offset
+    /// (rather than whatever offset the forecasts happen to be in).
+    pub fn day(&self, date: time::Date, offset: time::UtcOffset) -> Self {
+        self.slice(
+            |state| state.time.to_offset(offset).date() >= date,
+            |state| state.time.to_offset(offset).date() > date,
+        )
+    }
+
+    /// The lowest temperature and when it's first reached.
+    pub fn min_temp(&self) -> Option<(time::OffsetDateTime, &'a Temperature)> {
+        self.extreme_temp(|candidate, extreme| candidate < extreme)
+    }
+
+    /// The highest temperature and when it's first reached.
+    pub fn max_temp(&self) -> Option<(time::OffsetDateTime, &'a Temperature)> {
+        self.extreme_temp(|candidate, extreme| candidate > extreme)
+    }
+
+    pub fn first_where<P: Fn(&WeatherState) -> bool>(
+        &self,
+        predicate: P,
+    ) -> Option<&'a WeatherState> {
+        self.states.iter().find(|state| predicate(state))
+    }
+
+    pub fn first_time_where<P: Fn(&WeatherState) -> bool>(
+        &self,
+        predicate: P,
+    ) -> Option<time::OffsetDateTime> {
+        self.first_where(predicate).map(|state| state.time)
+    }
+
+    /// The conditions at any time within the forecast. Measurements are interpolated linearly
+    /// between the forecasts on either side, while the condition is taken from the nearer one
+    /// and the precipitation from the earlier one (since it's the amount over the period that
+    /// starts then).
+    pub fn at(&self, time: time::OffsetDateTime) -> Option<WeatherState> {
+        let next = self.states.iter().position(|state| state.time >= time)?;
+        let after = &self.states[next];
+
+        if after.time == time {
+            return Some(interpolate(after, after, time, 0.));
+        }
+
+        let before = &self.states[next.checked_sub(1)?];
+        let fraction =
+            (time - before.time).as_seconds_f32() / (after.time - before.time).as_seconds_f32();
+
+        Some(interpolate(before, after, time, fraction))
+    }
+
+    /// The forecasts from the first that matches `from` up to (but excluding) the first after it
+    /// that matches `until`.
+    fn slice<F, U>(&self, from: F, until: U) -> Self
+    where
+        F: Fn(&WeatherState) -> bool,
+        U: Fn(&WeatherState) -> bool,
+    {
+        let start = self
+            .states
+            .iter()
+            .position(from)
+            .unwrap_or(self.states.len());
+        let end = self.states[start..]
+            .iter()
+            .position(until)
+            .map_or(self.states.len(), |i| start + i);
+
+        Self {
+            states: &self.states[start..end],
+        }
+    }
+
+    fn extreme_temp<F: Fn(f32, f32) -> bool>(
+        &self,
+        replaces: F,
+    ) -> Option<(time::OffsetDateTime, &'a Temperature)> {
+        self.states
+            .iter()
+            .filter_map(|state| state.temp.as_ref().map(|temp| (state.time, temp)))
+            .fold(None, |extreme, (time, temp)| match extreme {
+                Some((_, current)) if !replaces(temp.kelvin(), current.kelvin()) => extreme,
+                _ => Some((time, temp)),
+            })
+    }
+}
+
+impl<'a> IntoIterator for Forecast<'a> {
+    type Item = &'a WeatherState;
+    type IntoIter = std::slice::Iter<'a, WeatherState>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.states.iter()
+    }
+}
+
+/// A forecast `fraction` of the way from `before` to `after`.
+fn interpolate(
+    before: &WeatherState,
+    after: &WeatherState,
+    time: time::OffsetDateTime,
+    fraction: f32,
+) -> WeatherState {
+    let lerp = |a: Option<f32>, b: Option<f32>| Some(a? + (b? - a?) * fraction);
+    let nearer = if fraction < 0.5 { before } else { after };
+
+    WeatherState {
+        time,
+        sunrise: before.sunrise,
+        sunset: before.sunset,
+        temp: lerp(
+            before.temp.as_ref().map(Temperature::kelvin),
+            after.temp.as_ref().map(Temperature::kelvin),
+        )
+        .map(Temperature::from_kelvin),
+        temp_min: before
+            .temp_min
+            .as_ref()
+            .map(|temp| Temperature::from_kelvin(temp.kelvin())),
+        temp_max: before
+            .temp_max
+            .as_ref()
+            .map(|temp| Temperature::from_kelvin(temp.kelvin())),
+        humidity: lerp(
+            before.humidity.map(f32::from),
+            after.humidity.map(f32::from),
+        )
+        .map(|humidity| humidity.round() as u8),
+        wind: match (&before.wind, &after.wind) {
+            (Some(before), Some(after)) => Some(Wind {
+                speed: before.speed + (after.speed - before.speed) * fraction,
+                direction: match (before.direction, after.direction) {
+                    (Some(from), Some(to)) => Some(interpolate_direction(from, to, fraction)),
+                    _ if fraction < 0.5 => before.direction,
+                    _ => after.direction,
+                },
+                gust: lerp(before.gust, after.gust),
+            }),
+            _ => None,
+        },
+        pressure: lerp(
+            before.pressure.as_ref().map(Pressure::hectopascals),
+            after.pressure.as_ref().map(Pressure::hectopascals),
+        )
+        .map(Pressure::from_hectopascals),
+        precipitation: before
+            .precipitation
+            .as_ref()
+            .map(|precipitation| Precipitation::from_millimetres(precipitation.millimetres())),
+        clouds: lerp(before.clouds.map(f32::from), after.clouds.map(f32::from))
+            .map(|clouds| clouds.round() as u8),
+        visibility: lerp(
+            before.visibility.map(|visibility| visibility as f32),
+            after.visibility.map(|visibility| visibility as f32),
+        )
+        .map(|visibility| visibility.round() as u32),
+        condition: nearer
+            .condition
+            .as_ref()
+            .map(|condition| WeatherCondition::from(condition.code())),
+        provider: nearer.provider,
+    }
+}
+
+/// Turn the shorter way round the compass, so that halfway between 350° and 10° is 0°.
+fn interpolate_direction(before: u16, after: u16, fraction: f32) -> u16 {
+    let delta = (i32::from(after) - i32::from(before) + 540).rem_euclid(360) - 180;
+    (f32::from(before) + delta as f32 * fraction)
+        .round()
+        .rem_euclid(360.) as u16
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::weather::Provider;
+
+    fn start() -> time::OffsetDateTime {
+        time::date!(2021 - 01 - 31)
+            .with_time(time::time!(20:00))
+            .assume_offset(time::offset!(-5))
+    }
+
+    /// Hourly forecasts from 8pm with the given temperatures (in °C), and the wind from the west
+    /// backing 100° each hour (to 170°, 70°, 330° and so on).
+    fn series(temps: &[f32]) -> Vec<WeatherState> {
+        temps
+            .iter()
+            .enumerate()
+            .map(|(i, &temp)| WeatherState {
+                time: start() + time::Duration::hours(i as i64),
+                sunrise: None,
+                sunset: None,
+                temp: Some(Temperature::from_celsius(temp)),
+                temp_min: None,
+                temp_max: None,
+                humidity: Some(80 + i as u8),
+                wind: Some(Wind {
+                    speed: 4. + i as f32,
+                    direction: Some((270 - 100 * i as i32).rem_euclid(360) as u16),
+                    gust: None,
+                }),
+                pressure: None,
+                precipitation: None,
+                clouds: None,
+                visibility: None,
+                condition: Some(WeatherCondition::from(if i < 2 { 800 } else { 600 })),
+                provider: Provider::OpenWeather,
+            })
+            .collect()
+    }
+
+    fn hours(forecast: Forecast) -> Vec<u8> {
+        forecast.iter().map(|state| state.time.hour()).collect()
+    }
+
+    #[test]
+    fn between_test() {
+        let states = series(&[0.; 6]);
+        let forecast = Forecast::new(&states);
+
+        assert_eq!(
+            vec![21, 22],
+            hours(forecast.between(
+                start() + time::Duration::minutes(30),
+                start() + time::Duration::hours(3),
+            )),
+        );
+        assert_eq!(
+            vec![0, 1],
+            hours(forecast.after(start() + time::Duration::hours(3)))
+        );
+        assert!(forecast
+            .between(start() - time::Duration::day(), start())
+            .is_empty());
+    }
+
+    #[test]
+    fn day_test() {
+        let states = series(&[0.; 6]);
+        let forecast = Forecast::new(&states);
+
+        // The forecasts run from 8pm to 1am in Montreal, but in UTC that's 1am to 6am the next day.
+        assert_eq!(
+            vec![20, 21, 22, 23],
+            hours(forecast.day(time::date!(2021 - 01 - 31), time::offset!(-5))),
+        );
+        assert_eq!(
+            vec![0, 1],
+            hours(forecast.day(time::date!(2021 - 02 - 01), time::offset!(-5))),
+        );
+        assert_eq!(
+            6,
+            forecast
+                .day(time::date!(2021 - 02 - 01), time::offset!(UTC))
+                .states()
+                .len()
+        );
+    }
+
+    #[test]
+    fn extreme_temp_test() {
+        let states = series(&[-5., -8., -10., -10., -9.]);
+        let forecast = Forecast::new(&states);
+
+        let (time, min) = forecast.min_temp().unwrap();
+        assert_eq!(22, time.hour());
+        assert!((min.celsius() + 10.).abs() < 0.01);
+
+        let (time, max) = forecast.max_temp().unwrap();
+        assert_eq!(20, time.hour());
+        assert!((max.celsius() + 5.).abs() < 0.01);
+
+        assert_eq!(None, Forecast::new(&[]).max_temp().map(|(time, _)| time));
+    }
+
+    #[test]
+    fn first_time_where_test() {
+        let states = series(&[0.; 4]);
+        let forecast = Forecast::new(&states);
+
+        assert_eq!(
+            Some(start() + time::Duration::hours(2)),
+            forecast.first_time_where(
+                |state| matches!(&state.condition, Some(condition) if condition.is_precipitation())
+            ),
+        );
+        assert_eq!(
+            None,
+            forecast.first_time_where(|state| state.temp.is_none())
+        );
+    }
+
+    #[test]
+    fn at_test() {
+        let states = series(&[-10., -4., 0., 2.]);
+        let forecast = Forecast::new(&states);
+
+        let state = forecast.at(start() + time::Duration::minutes(30)).unwrap();
+        assert!((state.temp.unwrap().celsius() + 7.).abs() < 0.01);
+        assert_eq!(Some(81), state.humidity);
+        assert_eq!(800, state.condition.unwrap().code());
+
+        // Halfway between 170° and 70°, then between 70° and 330°, the short way round.
+        let state = forecast.at(start() + time::Duration::minutes(90)).unwrap();
+        let wind = state.wind.unwrap();
+        assert_eq!(Some(120), wind.direction);
+        assert!((wind.speed - 5.5).abs() < 0.01);
+        assert_eq!(600, state.condition.unwrap().code());
+
+        let state = forecast.at(start() + time::Duration::minutes(150)).unwrap();
+        assert_eq!(Some(20), state.wind.unwrap().direction);
+
+        assert!(forecast.at(start() + time::Duration::hours(3)).is_some());
+        assert!(forecast.at(start() - time::Duration::minutes(1)).is_none());
+        assert!(forecast
+            .at(start() + time::Duration::minutes(181))
+            .is_none());
+    }
+}
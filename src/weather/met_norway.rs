@@ -0,0 +1,415 @@
+//! Raw response types for the MET Norway Locationforecast API, and the mapping from them to the
+//! domain model in the parent module.
+
+use serde::Deserialize;
+
+use super::{
+    DecodeError, DecodeMode, Decoded, Precipitation, Provider, Temperature, WeatherCondition,
+    WeatherReport, WeatherState, Wind,
+};
+use crate::astronomy::SunEvents;
+use crate::http;
+use crate::location::Location;
+
+pub async fn call_met_norway_api(
+    client: &http::Client,
+    location: &Location,
+) -> Result<String, String> {
+    // The terms of service ask for coordinates to be truncated to four decimals, so that nearby
+    // requests can be served from their cache.
+    client
+        .get_text(&format!(
+            "https://api.met.no/weatherapi/locationforecast/2.0/compact?lat={:.4}&lon={:.4}",
+            location.latitude, location.longitude,
+        ))
+        .await
+}
+
+/// Decode a Locationforecast response. The response only contains a time series, so the first
+/// entry is used for the current conditions, and daily entries are summarized from the series
+/// in `tz_offset`'s local time.
+pub fn decode(
+    body: &str,
+    mode: DecodeMode,
+    tz_offset: time::UtcOffset,
+) -> Result<Decoded<WeatherReport>, DecodeError> {
+    let response: LocationforecastResponse =
+        serde_json::from_str(body).map_err(|e| DecodeError {
+            path: String::new(),
+            message: e.to_string(),
+        })?;
+
+    let (longitude, latitude) = match response.geometry.coordinates[..] {
+        [longitude, latitude, ..] => (longitude, latitude),
+        _ => {
+            return Err(DecodeError {
+                path: "geometry.coordinates".to_string(),
+                message: "Expected longitude and latitude.".to_string(),
+            })
+        }
+    };
+
+    let mut skipped = Vec::new();
+    let mut timesteps = Vec::with_capacity(response.properties.timeseries.len());
+
+    for (i, entry) in response.properties.timeseries.into_iter().enumerate() {
+        match serde_json::from_value::<RawTimestep>(entry)
+            .map_err(|e| e.to_string())
+            .and_then(|raw| {
+                time::PrimitiveDateTime::parse(&raw.time, "%Y-%m-%dT%H:%M:%SZ")
+                    .map(|time| (time.assume_utc().to_offset(tz_offset), raw.data))
+                    .map_err(|e| e.to_string())
+            }) {
+            Ok(timestep) => timesteps.push(timestep),
+            Err(message) => {
+                let error = DecodeError {
+                    path: format!("properties.timeseries[{}]", i),
+                    message,
+                };
+
+                match mode {
+                    DecodeMode::Strict => return Err(error),
+                    DecodeMode::Lenient => skipped.push(error),
+                }
+            }
+        }
+    }
+
+    let state = |time: time::OffsetDateTime, data: &RawData, period: Period| {
+        let sun = SunEvents::new(time.date(), latitude, longitude);
+        let details = &data.instant.details;
+
+        WeatherState {
+            time,
+            sunrise: sun.sunrise.map(|t| t.to_offset(tz_offset)),
+            sunset: sun.sunset.map(|t| t.to_offset(tz_offset)),
+            temp: details.air_temperature.map(Temperature::from_celsius),
+            temp_min: None,
+            temp_max: None,
+            humidity: details
+                .relative_humidity
+                .map(|humidity| humidity.round() as u8),
+            wind: details.wind_speed.map(|speed| Wind {
+                speed,
+                direction: details
+                    .wind_from_direction
+                    .map(|direction| direction.round() as u16 % 360),
+                gust: details.wind_speed_of_gust,
+            }),
+            pressure: details
+                .air_pressure_at_sea_level
+                .map(|pressure| pressure.into()),
+            precipitation: data
+                .next_1_hours
+                .as_ref()
+                .and_then(|next| next.details.as_ref())
+                .and_then(|details| details.precipitation_amount)
+                .map(Precipitation::from_millimetres),
+            clouds: details
+                .cloud_area_fraction
+                .map(|clouds| clouds.round() as u8),
+            visibility: None,
+            condition: data
+                .symbol_code(period)
+                .and_then(condition_code)
+                .map(WeatherCondition::from),
+            provider: Provider::MetNorway,
+        }
+    };
+
+    let current = match timesteps.first() {
+        Some((time, data)) => state(*time, data, Period::Hour),
+        None => {
+            return Err(DecodeError {
+                path: "properties.timeseries".to_string(),
+                message: "No valid entries.".to_string(),
+            })
+        }
+    };
+
+    // The series is hourly for the first couple of days, then six-hourly.
+    let hourly = timesteps
+        .iter()
+        .filter(|(_, data)| data.next_1_hours.is_some())
+        .map(|(time, data)| state(*time, data, Period::Hour))
+        .collect();
+
+    let mut daily: Vec<WeatherState> = Vec::new();
+    let mut days = timesteps.iter().peekable();
+    while let Some((first_time, _)) = days.peek() {
+        let date = first_time.date();
+        let mut day = Vec::new();
+        while let Some(timestep) = days.next_if(|(time, _)| time.date() == date) {
+            day.push(timestep);
+        }
+
+        let temps = day
+            .iter()
+            .filter_map(|(_, data)| data.instant.details.air_temperature);
+        let temp_min = temps.clone().fold(f32::NAN, f32::min);
+        let temp_max = temps.fold(f32::NAN, f32::max);
+
+        // Summarize the day with the entry closest to noon.
+        let noon = date.with_time(time::time!(12:00)).assume_offset(tz_offset);
+        if let Some((time, data)) = day
+            .iter()
+            .min_by_key(|(time, _)| (*time - noon).whole_minutes().abs())
+        {
+            let mut state = state(*time, data, Period::Day);
+            if !temp_min.is_nan() {
+                state.temp_min = Some(Temperature::from_celsius(temp_min));
+                state.temp_max = Some(Temperature::from_celsius(temp_max));
+            }
+            daily.push(state);
+        }
+    }
+
+    Ok(Decoded {
+        value: WeatherReport {
+            current,
+            minutely: Vec::new(),
+            hourly,
+            daily,
+        },
+        skipped,
+    })
+}
+
+/// Map a MET Norway weather symbol, eg. `lightrainshowers_day`, to the equivalent OpenWeather
+/// condition code.
+fn condition_code(symbol_code: &str) -> Option<u16> {
+    // Strip the variant, eg. `_day`, `_night` or `_polartwilight`.
+    let symbol = symbol_code.split('_').next()?;
+
+    Some(match symbol {
+        "clearsky" => 800,
+        "fair" => 801,
+        "partlycloudy" => 802,
+        "cloudy" => 804,
+        "fog" => 741,
+        "lightrain" => 500,
+        "rain" => 501,
+        "heavyrain" => 502,
+        "lightrainshowers" => 520,
+        "rainshowers" => 521,
+        "heavyrainshowers" => 522,
+        "lightrainandthunder" | "lightrainshowersandthunder" => 200,
+        "rainandthunder" | "rainshowersandthunder" => 201,
+        "heavyrainandthunder" | "heavyrainshowersandthunder" => 202,
+        "lightsleet" | "sleet" | "heavysleet" => 611,
+        "lightsleetshowers" => 612,
+        "sleetshowers" | "heavysleetshowers" => 613,
+        "lightsnow" => 600,
+        "snow" => 601,
+        "heavysnow" => 602,
+        "lightsnowshowers" => 620,
+        "snowshowers" => 621,
+        "heavysnowshowers" => 622,
+        symbol if symbol.ends_with("thunder") => 211,
+        _ => return None,
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Period {
+    Hour,
+    Day,
+}
+
+/// ```json
+/// {
+///     "type": "Feature",
+///     "geometry": { "type": "Point", "coordinates": [-73.6, 45.5, 41] },
+///     "properties": {
+///         "meta": { "updated_at": "2021-06-15T09:31:12Z", "units": { ... } },
+///         "timeseries": [ ... ]
+///     }
+/// }
+/// ```
+#[derive(Deserialize)]
+struct LocationforecastResponse {
+    geometry: RawGeometry,
+    properties: RawProperties,
+}
+
+#[derive(Deserialize)]
+struct RawGeometry {
+    /// Longitude, latitude and altitude.
+    coordinates: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct RawProperties {
+    // Entries are decoded individually so a single bad entry can be skipped.
+    timeseries: Vec<serde_json::Value>,
+}
+
+/// ```json
+/// {
+///     "time": "2021-06-15T10:00:00Z",
+///     "data": {
+///         "instant": {
+///             "details": {
+///                 "air_pressure_at_sea_level": 1014.8,
+///                 "air_temperature": 14.2,
+///                 "cloud_area_fraction": 21.9,
+///                 "relative_humidity": 78.4,
+///                 "wind_from_direction": 244.1,
+///                 "wind_speed": 2.7
+///             }
+///         },
+///         "next_12_hours": { "summary": { "symbol_code": "partlycloudy_day" } },
+///         "next_1_hours": {
+///             "summary": { "symbol_code": "fair_day" },
+///             "details": { "precipitation_amount": 0.0 }
+///         },
+///         "next_6_hours": { ... }
+///     }
+/// }
+/// ```
+#[derive(Deserialize)]
+struct RawTimestep {
+    time: String,
+    data: RawData,
+}
+
+#[derive(Deserialize)]
+struct RawData {
+    instant: RawInstant,
+    next_1_hours: Option<RawPeriod>,
+    next_6_hours: Option<RawPeriod>,
+    next_12_hours: Option<RawPeriod>,
+}
+
+impl RawData {
+    /// The symbol for the hour following this entry, or for a longer period if that's all that's
+    /// available. Days prefer the longest period.
+    fn symbol_code(&self, period: Period) -> Option<&str> {
+        let periods = match period {
+            Period::Hour => [&self.next_1_hours, &self.next_6_hours, &self.next_12_hours],
+            Period::Day => [&self.next_12_hours, &self.next_6_hours, &self.next_1_hours],
+        };
+
+        periods.iter().find_map(|period| {
+            period
+                .as_ref()
+                .and_then(|period| period.summary.as_ref())
+                .map(|summary| summary.symbol_code.as_str())
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct RawInstant {
+    details: RawInstantDetails,
+}
+
+#[derive(Deserialize)]
+struct RawInstantDetails {
+    air_temperature: Option<f32>,
+    relative_humidity: Option<f32>,
+    air_pressure_at_sea_level: Option<f32>,
+    wind_speed: Option<f32>,
+    wind_from_direction: Option<f32>,
+
+    /// Only included in the `complete` variant of the API.
+    wind_speed_of_gust: Option<f32>,
+
+    cloud_area_fraction: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct RawPeriod {
+    summary: Option<RawSummary>,
+    details: Option<RawPeriodDetails>,
+}
+
+#[derive(Deserialize)]
+struct RawSummary {
+    symbol_code: String,
+}
+
+#[derive(Deserialize)]
+struct RawPeriodDetails {
+    precipitation_amount: Option<f32>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{CloudsType, RainType, ThunderstormType};
+    use super::*;
+
+    const COMPACT: &str = include_str!("../../tests/fixtures/met_norway/compact.json");
+
+    #[test]
+    fn decode_test() {
+        let report = decode(COMPACT, DecodeMode::Strict, time::UtcOffset::hours(-4)).unwrap();
+        assert!(report.skipped.is_empty());
+
+        let report = report.value;
+        assert_eq!(time::time!(6:00), report.current.time.time());
+        assert_eq!(
+            14,
+            report.current.temp.as_ref().unwrap().celsius().round() as i32
+        );
+        assert_eq!(Some(244), report.current.wind.as_ref().unwrap().direction);
+        assert!(matches!(report.current.provider, Provider::MetNorway));
+        assert!(matches!(
+            report.current.condition,
+            Some(WeatherCondition::Clouds(CloudsType::FewClouds))
+        ));
+
+        assert_eq!(3, report.hourly.len());
+        assert!(matches!(
+            report.hourly[2].condition,
+            Some(WeatherCondition::Rain(RainType::LightIntensityShowerRain))
+        ));
+
+        assert_eq!(2, report.daily.len());
+        let today = &report.daily[0];
+        assert_eq!(time::time!(14:00), today.time.time());
+        assert_eq!(
+            14,
+            today.temp_min.as_ref().unwrap().celsius().round() as i32
+        );
+        assert_eq!(
+            23,
+            today.temp_max.as_ref().unwrap().celsius().round() as i32
+        );
+        assert!(matches!(
+            today.condition,
+            Some(WeatherCondition::Rain(RainType::ModerateRain))
+        ));
+    }
+
+    #[test]
+    fn decode_malformed_test() {
+        let body = r#"{
+            "geometry": { "coordinates": [-73.6, 45.5] },
+            "properties": { "timeseries": [
+                { "time": "2021-06-15T10:00:00Z", "data": { "instant": { "details": {} } } },
+                { "time": "yesterday", "data": { "instant": { "details": {} } } }
+            ] }
+        }"#;
+
+        assert!(decode(body, DecodeMode::Strict, time::UtcOffset::UTC).is_err());
+
+        let report = decode(body, DecodeMode::Lenient, time::UtcOffset::UTC).unwrap();
+        assert_eq!(1, report.skipped.len());
+        assert_eq!("properties.timeseries[1]", report.skipped[0].path);
+    }
+
+    #[test]
+    fn condition_code_test() {
+        assert_eq!(Some(800), condition_code("clearsky_night"));
+        assert_eq!(Some(521), condition_code("rainshowers_polartwilight"));
+        assert_eq!(Some(201), condition_code("rainshowersandthunder_day"));
+        assert_eq!(Some(211), condition_code("heavysnowandthunder"));
+        assert_eq!(None, condition_code("sunny"));
+
+        assert!(matches!(
+            WeatherCondition::from(condition_code("lightsnowandthunder").unwrap()),
+            WeatherCondition::Thunderstorm(ThunderstormType::Thunderstorm)
+        ));
+    }
+}
@@ -0,0 +1,1087 @@
+//! A decoder for the METAR and TAF reports of an airport, from aviationweather.gov. The METAR
+//! gives the current conditions, and the TAF an hourly forecast of the wind, visibility, weather
+//! and cloud (but not the temperature) for the next day or so.
+
+use std::env;
+use std::str::FromStr;
+
+use super::{
+    AtmosphereType, CloudsType, DecodeError, DecodeMode, Decoded, DrizzleType, Intensity,
+    Precipitation, Pressure, Provider, RainType, SnowType, Temperature, ThunderstormType,
+    WeatherCondition, WeatherReport, WeatherState, Wind,
+};
+use crate::astronomy::SunEvents;
+use crate::http;
+use crate::location::Location;
+
+const METRES_PER_SECOND_PER_KNOT: f32 = 0.514_444;
+const METRES_PER_STATUTE_MILE: f32 = 1609.344;
+const HECTOPASCALS_PER_INCH_OF_MERCURY: f32 = 33.863_89;
+const MILLIMETRES_PER_INCH: f32 = 25.4;
+
+/// Visibility reported as `9999` or `CAVOK`, meaning 10 km or more.
+const UNLIMITED_VISIBILITY: u32 = 10_000;
+
+const DESCRIPTORS: [&str; 8] = ["MI", "PR", "BC", "DR", "BL", "SH", "TS", "FZ"];
+const PHENOMENA: [&str; 22] = [
+    "DZ", "RA", "SN", "SG", "IC", "PL", "GR", "GS", "UP", "BR", "FG", "FU", "VA", "DU", "SA", "HZ",
+    "PY", "PO", "SQ", "FC", "SS", "DS",
+];
+
+/// Fetch the latest METAR and TAF for the location's station.
+pub async fn call_metar_api(client: &http::Client, location: &Location) -> Result<String, String> {
+    let station = env::var("METAR_STATION")
+        .ok()
+        .and_then(|stations| station(&stations, &location.name))
+        .ok_or_else(|| format!("No METAR_STATION for {}.", location.name))?;
+
+    let url = |product: &str| {
+        format!(
+            "https://aviationweather.gov/api/data/{}?ids={}&format=raw",
+            product, station
+        )
+    };
+    let (metar_url, taf_url) = (url("metar"), url("taf"));
+    let (metar, taf) = tokio::join!(client.get_text(&metar_url), client.get_text(&taf_url));
+
+    // Not every airport issues a TAF, so the METAR is enough on its own.
+    let taf = taf.unwrap_or_else(|e| {
+        eprintln!("Unable to fetch TAF for {}: {}", station, e);
+        String::new()
+    });
+    let taf = taf.trim();
+
+    Ok(if taf.is_empty() || taf.starts_with("TAF") {
+        format!("{}\n{}", metar?.trim(), taf)
+    } else {
+        format!("{}\nTAF {}", metar?.trim(), taf)
+    })
+}
+
+/// The station for a location from `METAR_STATION`, a `;`-separated list of ICAO codes, each
+/// optionally preceded by the location's name, eg. `CYUL` or `Home=CYUL;Cottage=CYOW`. A code
+/// without a name is used for every other location.
+fn station(stations: &str, location_name: &str) -> Option<String> {
+    let mut default = None;
+
+    for entry in stations.split(';') {
+        let mut parts = entry.splitn(2, '=');
+        match (parts.next().map(str::trim), parts.next().map(str::trim)) {
+            (Some(name), Some(code)) if name.eq_ignore_ascii_case(location_name) => {
+                return Some(code.to_uppercase())
+            }
+            (Some(code), None) if !code.is_empty() && default.is_none() => {
+                default = Some(code.to_uppercase())
+            }
+            _ => {}
+        }
+    }
+
+    default
+}
+
+/// Decode a METAR, optionally followed by a TAF starting with `TAF`. Reports only give the day of
+/// the month, which is taken to be the one closest to `reference`. Unrecognized groups are skipped
+/// in `DecodeMode::Lenient`.
+pub fn decode(
+    body: &str,
+    mode: DecodeMode,
+    location: &Location,
+    reference: time::OffsetDateTime,
+    tz_offset: time::UtcOffset,
+) -> Result<Decoded<WeatherReport>, DecodeError> {
+    let lines: Vec<&str> = body
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let taf_start = lines
+        .iter()
+        .position(|line| line.starts_with("TAF"))
+        .unwrap_or(lines.len());
+
+    let mut skipped = Vec::new();
+
+    let metar = match lines[..taf_start].first() {
+        Some(metar) => parse_metar(metar, reference, &mut skipped)?,
+        None => {
+            return Err(DecodeError {
+                path: "metar".to_string(),
+                message: "No METAR in response.".to_string(),
+            })
+        }
+    };
+
+    let mut current = metar
+        .conditions
+        .state(metar.time.to_offset(tz_offset), location);
+    current.temp = metar.temp.map(Temperature::from_celsius);
+    current.humidity = metar.temp.zip(metar.dew_point).map(|(temp, dew_point)| {
+        Temperature::from_celsius(temp).relative_humidity(&Temperature::from_celsius(dew_point))
+    });
+    current.pressure = metar.pressure.map(Pressure::from_hectopascals);
+    current.precipitation = metar.precipitation.map(Precipitation::from_millimetres);
+
+    let taf = lines[taf_start..].join(" ");
+    let hourly = match parse_taf(&taf, reference, &mut skipped)? {
+        Some(taf) => {
+            // Start from the current hour, since a TAF is valid from when it was issued.
+            let now = reference.unix_timestamp();
+            let mut time = taf
+                .valid_from
+                .max(time::OffsetDateTime::from_unix_timestamp(now - now % 3600));
+            let mut hourly = Vec::new();
+
+            while time < taf.valid_until {
+                if let Some((_, conditions)) =
+                    taf.periods.iter().rev().find(|(from, _)| *from <= time)
+                {
+                    hourly.push(conditions.state(time.to_offset(tz_offset), location));
+                }
+                time += time::Duration::hour();
+            }
+
+            hourly
+        }
+        None => Vec::new(),
+    };
+
+    if mode == DecodeMode::Strict && !skipped.is_empty() {
+        return Err(skipped.remove(0));
+    }
+
+    Ok(Decoded {
+        value: WeatherReport {
+            current,
+            minutely: Vec::new(),
+            hourly,
+            daily: Vec::new(),
+        },
+        skipped,
+    })
+}
+
+struct Metar {
+    time: time::OffsetDateTime,
+    conditions: Conditions,
+
+    /// In degrees Celsius.
+    temp: Option<f32>,
+    dew_point: Option<f32>,
+
+    /// In hectopascals.
+    pressure: Option<f32>,
+
+    /// Over the past hour, in millimetres, from the remarks of US stations.
+    precipitation: Option<f32>,
+}
+
+fn parse_metar(
+    text: &str,
+    reference: time::OffsetDateTime,
+    skipped: &mut Vec<DecodeError>,
+) -> Result<Metar, DecodeError> {
+    let error = |message: &str| DecodeError {
+        path: "metar".to_string(),
+        message: message.to_string(),
+    };
+
+    let groups = groups(text);
+    let mut groups = groups
+        .iter()
+        .map(String::as_str)
+        .skip_while(|group| *group == "METAR" || *group == "SPECI")
+        .skip(1);
+
+    let time = groups
+        .next()
+        .and_then(|group| group.strip_suffix('Z'))
+        .filter(|group| group.len() == 6 && group.is_ascii())
+        .and_then(|group| day_time(reference, &group[..2], &group[2..4], &group[4..]))
+        .ok_or_else(|| error("Expected the observation time."))?;
+
+    let mut metar = Metar {
+        time,
+        conditions: Conditions::default(),
+        temp: None,
+        dew_point: None,
+        pressure: None,
+        precipitation: None,
+    };
+    let mut remarks = false;
+
+    for group in groups {
+        if remarks {
+            // Hourly precipitation in hundredths of an inch, eg. `P0012`.
+            if let Some(hundredths) = group.strip_prefix('P').and_then(number::<u16>) {
+                if group.len() == 5 {
+                    metar.precipitation = Some(f32::from(hundredths) / 100. * MILLIMETRES_PER_INCH);
+                }
+            }
+            continue;
+        }
+
+        match group {
+            "RMK" => remarks = true,
+            "AUTO" | "COR" => {}
+
+            // The trend forecast, which the TAF covers.
+            "NOSIG" | "BECMG" | "TEMPO" => break,
+
+            _ => {
+                if let Some((temp, dew_point)) = temperatures(group) {
+                    metar.temp = Some(temp);
+                    metar.dew_point = dew_point;
+                } else if let Some(pressure) = pressure(group) {
+                    metar.pressure = Some(pressure);
+                } else if !metar.conditions.parse(group) && !is_recent_weather(group) {
+                    skipped.push(error(&format!("Unrecognized group \"{}\".", group)));
+                }
+            }
+        }
+    }
+
+    Ok(metar)
+}
+
+struct Taf {
+    valid_from: time::OffsetDateTime,
+    valid_until: time::OffsetDateTime,
+
+    /// The conditions expected from each time until the next, in order.
+    periods: Vec<(time::OffsetDateTime, Conditions)>,
+}
+
+/// A TAF's change groups. Temporary and probable conditions are left out, since they only apply
+/// for part of their period.
+enum Change {
+    From(time::OffsetDateTime),
+    Becoming(time::OffsetDateTime),
+    Temporary,
+}
+
+/// Parse a TAF, or return `None` if there isn't one.
+fn parse_taf(
+    text: &str,
+    reference: time::OffsetDateTime,
+    skipped: &mut Vec<DecodeError>,
+) -> Result<Option<Taf>, DecodeError> {
+    let error = |message: &str| DecodeError {
+        path: "taf".to_string(),
+        message: message.to_string(),
+    };
+
+    let groups = groups(text);
+    let mut groups = groups
+        .iter()
+        .map(String::as_str)
+        .skip_while(|group| matches!(*group, "TAF" | "AMD" | "COR"))
+        .skip(1)
+        .skip_while(|group| group.ends_with('Z'));
+
+    let (valid_from, valid_until) = match groups.next() {
+        None | Some("NIL") => return Ok(None),
+        Some(group) => {
+            period(reference, group).ok_or_else(|| error("Expected the valid period."))?
+        }
+    };
+
+    let mut taf = Taf {
+        valid_from,
+        valid_until,
+        periods: Vec::new(),
+    };
+    let mut change = Change::From(valid_from);
+    let mut conditions = Conditions::default();
+
+    loop {
+        let group = groups.next();
+
+        let next_change = match group {
+            None | Some("RMK") => None,
+            Some("BECMG") => Some(
+                match groups.next().and_then(|group| period(reference, group)) {
+                    Some((_, until)) => Change::Becoming(until),
+                    None => {
+                        skipped.push(error("Expected a period after BECMG."));
+                        Change::Temporary
+                    }
+                },
+            ),
+            Some("TEMPO") => Some(Change::Temporary),
+            Some(group) if group.starts_with("PROB") => Some(Change::Temporary),
+            Some(group) if group.starts_with("FM") && group.len() == 8 && group.is_ascii() => {
+                match day_time(reference, &group[2..4], &group[4..6], &group[6..]) {
+                    Some(from) => Some(Change::From(from)),
+                    None => {
+                        skipped.push(error(&format!("Invalid change group \"{}\".", group)));
+                        Some(Change::Temporary)
+                    }
+                }
+            }
+            Some(group) => {
+                // Elements of temporary groups are ignored, along with their periods.
+                if !matches!(change, Change::Temporary)
+                    && !conditions.parse(group)
+                    && !is_temperature_forecast(group)
+                {
+                    skipped.push(error(&format!("Unrecognized group \"{}\".", group)));
+                }
+                continue;
+            }
+        };
+
+        // The previous group is complete.
+        match change {
+            Change::From(from) => taf.periods.push((from, conditions)),
+            Change::Becoming(from) => {
+                let mut merged = taf
+                    .periods
+                    .last()
+                    .map(|(_, previous)| previous.clone())
+                    .unwrap_or_default();
+                merged.merge(conditions);
+                taf.periods.push((from, merged));
+            }
+            Change::Temporary => {}
+        }
+        conditions = Conditions::default();
+
+        match next_change {
+            Some(next_change) => change = next_change,
+            None => break,
+        }
+    }
+
+    taf.periods.sort_by_key(|(from, _)| *from);
+    Ok(Some(taf))
+}
+
+/// Split a report into groups, keeping whole and fractional miles of visibility together (eg.
+/// `1 1/2SM`).
+fn groups(text: &str) -> Vec<String> {
+    let mut groups: Vec<String> = Vec::new();
+
+    for group in text
+        .split_whitespace()
+        .map(|group| group.trim_end_matches('='))
+    {
+        match groups.last_mut() {
+            Some(last)
+                if last.len() == 1
+                    && number::<u8>(last).is_some()
+                    && group.ends_with("SM")
+                    && group.contains('/') =>
+            {
+                last.push(' ');
+                last.push_str(group);
+            }
+            _ => groups.push(group.to_string()),
+        }
+    }
+
+    groups
+}
+
+/// The elements common to METARs and TAFs.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Conditions {
+    wind: Option<ReportedWind>,
+
+    /// In metres.
+    visibility: Option<u32>,
+
+    /// Empty if no significant weather is reported, or `None` if nothing is.
+    weather: Option<Vec<WeatherGroup>>,
+
+    /// The cover of the most extensive cloud layer, in percent.
+    clouds: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ReportedWind {
+    /// `None` if the direction is variable.
+    direction: Option<u16>,
+
+    /// In metres per second.
+    speed: f32,
+    gust: Option<f32>,
+}
+
+impl Conditions {
+    /// Read a group into these conditions, returning false if it isn't one of their elements.
+    fn parse(&mut self, group: &str) -> bool {
+        if group == "CAVOK" {
+            self.visibility = Some(UNLIMITED_VISIBILITY);
+            self.weather = Some(Vec::new());
+            self.clouds = Some(0);
+        } else if group == "NSW" {
+            self.weather = Some(Vec::new());
+        } else if let Some(wind) = wind(group) {
+            self.wind = Some(wind);
+        } else if let Some(visibility) = visibility(group) {
+            self.visibility = Some(visibility);
+        } else if let Some(cover) = cloud_cover(group) {
+            self.clouds = Some(self.clouds.unwrap_or(0).max(cover));
+        } else if let Some(weather) = WeatherGroup::parse(group) {
+            self.weather.get_or_insert_with(Vec::new).push(weather);
+        } else {
+            // Missing elements (eg. `/////KT`), variable wind directions (eg. `180V240`) and
+            // runway visual ranges (eg. `R24L/1200FT`).
+            return group.starts_with("//")
+                || (group.len() == 7
+                    && group.is_ascii()
+                    && &group[3..4] == "V"
+                    && number::<u16>(&group[..3]).is_some()
+                    && number::<u16>(&group[4..]).is_some())
+                || (group.starts_with('R') && group.contains('/'));
+        }
+
+        true
+    }
+
+    /// Replace the elements that `changes` reports.
+    fn merge(&mut self, changes: Self) {
+        if changes.wind.is_some() {
+            self.wind = changes.wind;
+        }
+        if changes.visibility.is_some() {
+            self.visibility = changes.visibility;
+        }
+        if changes.weather.is_some() {
+            self.weather = changes.weather;
+        }
+        if changes.clouds.is_some() {
+            self.clouds = changes.clouds;
+        }
+    }
+
+    /// The most severe weather reported at the station (the first reported, if several are as
+    /// severe), or else the cloud cover.
+    fn condition(&self) -> Option<WeatherCondition> {
+        self.weather
+            .iter()
+            .flatten()
+            .filter(|weather| !weather.vicinity)
+            .filter_map(WeatherGroup::condition)
+            .rev()
+            .max_by_key(WeatherCondition::severity)
+            .or_else(|| {
+                self.clouds.map(|clouds| match clouds {
+                    0 => WeatherCondition::Clear,
+                    1..=25 => WeatherCondition::Clouds(CloudsType::FewClouds),
+                    26..=50 => WeatherCondition::Clouds(CloudsType::ScatteredClouds),
+                    51..=87 => WeatherCondition::Clouds(CloudsType::BrokenClouds),
+                    _ => WeatherCondition::Clouds(CloudsType::OvercastClouds),
+                })
+            })
+    }
+
+    fn state(&self, time: time::OffsetDateTime, location: &Location) -> WeatherState {
+        let sun = SunEvents::new(time.date(), location.latitude, location.longitude);
+
+        WeatherState {
+            time,
+            sunrise: sun.sunrise.map(|t| t.to_offset(time.offset())),
+            sunset: sun.sunset.map(|t| t.to_offset(time.offset())),
+            temp: None,
+            temp_min: None,
+            temp_max: None,
+            humidity: None,
+            wind: self.wind.map(|wind| Wind {
+                speed: wind.speed,
+                direction: wind.direction,
+                gust: wind.gust,
+            }),
+            pressure: None,
+            precipitation: None,
+            clouds: self.clouds,
+            visibility: self.visibility,
+            condition: self.condition(),
+            provider: Provider::Metar,
+        }
+    }
+}
+
+/// A present weather group, eg. `-FZRA` or `VCSH`.
+#[derive(Clone, Debug, PartialEq)]
+struct WeatherGroup {
+    intensity: Intensity,
+
+    /// Whether the weather is near the station rather than at it.
+    vicinity: bool,
+
+    descriptor: Option<String>,
+    phenomena: Vec<String>,
+}
+
+impl WeatherGroup {
+    fn parse(group: &str) -> Option<Self> {
+        let (intensity, vicinity, codes) = if let Some(codes) = group.strip_prefix('-') {
+            (Intensity::Light, false, codes)
+        } else if let Some(codes) = group.strip_prefix('+') {
+            (Intensity::Heavy, false, codes)
+        } else if let Some(codes) = group.strip_prefix("VC") {
+            (Intensity::Moderate, true, codes)
+        } else {
+            (Intensity::Moderate, false, group)
+        };
+
+        if codes.is_empty() || codes.len() % 2 != 0 || !codes.is_ascii() {
+            return None;
+        }
+
+        let mut weather = Self {
+            intensity,
+            vicinity,
+            descriptor: None,
+            phenomena: Vec::new(),
+        };
+
+        for i in (0..codes.len()).step_by(2) {
+            let code = &codes[i..i + 2];
+            if i == 0 && DESCRIPTORS.contains(&code) {
+                weather.descriptor = Some(code.to_string());
+            } else if PHENOMENA.contains(&code) {
+                weather.phenomena.push(code.to_string());
+            } else {
+                return None;
+            }
+        }
+
+        Some(weather)
+    }
+
+    fn has(&self, phenomenon: &str) -> bool {
+        self.phenomena.iter().any(|code| code == phenomenon)
+    }
+
+    fn by_intensity<T>(&self, light: T, moderate: T, heavy: T) -> T {
+        match self.intensity {
+            Intensity::Light => light,
+            Intensity::Moderate => moderate,
+            // METAR has no extreme intensity.
+            Intensity::Heavy | Intensity::Extreme => heavy,
+        }
+    }
+
+    fn condition(&self) -> Option<WeatherCondition> {
+        let descriptor = self.descriptor.as_deref();
+        let shower = descriptor == Some("SH");
+
+        let condition = if descriptor == Some("TS") {
+            let thunderstorm = if self.has("DZ") {
+                self.by_intensity(
+                    ThunderstormType::ThunderstormWithLightDrizzle,
+                    ThunderstormType::ThunderstormWithDrizzle,
+                    ThunderstormType::ThunderstormWithHeavyDrizzle,
+                )
+            } else if self.phenomena.is_empty() {
+                self.by_intensity(
+                    ThunderstormType::LightThunderstorm,
+                    ThunderstormType::Thunderstorm,
+                    ThunderstormType::HeavyThunderstorm,
+                )
+            } else {
+                self.by_intensity(
+                    ThunderstormType::ThunderstormWithLightRain,
+                    ThunderstormType::ThunderstormWithRain,
+                    ThunderstormType::ThunderstormWithHeavyRain,
+                )
+            };
+            WeatherCondition::Thunderstorm(thunderstorm)
+        } else if descriptor == Some("FZ") && (self.has("RA") || self.has("DZ")) {
+            WeatherCondition::Rain(RainType::FreezingRain)
+        } else if self.has("PL") || self.has("GR") || self.has("GS") {
+            WeatherCondition::Snow(if shower {
+                self.by_intensity(
+                    SnowType::LightShowerSleet,
+                    SnowType::ShowerSleet,
+                    SnowType::ShowerSleet,
+                )
+            } else {
+                SnowType::Sleet
+            })
+        } else if self.has("SN") && (self.has("RA") || self.has("DZ")) {
+            WeatherCondition::Snow(self.by_intensity(
+                SnowType::LightRainAndSnow,
+                SnowType::RainAndSnow,
+                SnowType::RainAndSnow,
+            ))
+        } else if self.has("SN") || self.has("SG") || self.has("IC") {
+            WeatherCondition::Snow(if shower {
+                self.by_intensity(
+                    SnowType::LightShowerSnow,
+                    SnowType::ShowerSnow,
+                    SnowType::HeavyShowerSnow,
+                )
+            } else {
+                self.by_intensity(SnowType::LightSnow, SnowType::Snow, SnowType::HeavySnow)
+            })
+        } else if self.has("RA") && self.has("DZ") {
+            WeatherCondition::Drizzle(if shower {
+                self.by_intensity(
+                    DrizzleType::ShowerRainAndDrizzle,
+                    DrizzleType::ShowerRainAndDrizzle,
+                    DrizzleType::HeavyShowerRainAndDrizzle,
+                )
+            } else {
+                self.by_intensity(
+                    DrizzleType::LightIntensityDrizzleRain,
+                    DrizzleType::DrizzleRain,
+                    DrizzleType::HeavyIntensityDrizzleRain,
+                )
+            })
+        } else if self.has("RA") || self.has("UP") || (shower && self.phenomena.is_empty()) {
+            WeatherCondition::Rain(if shower {
+                self.by_intensity(
+                    RainType::LightIntensityShowerRain,
+                    RainType::ShowerRain,
+                    RainType::HeavyIntensityShowerRain,
+                )
+            } else {
+                self.by_intensity(
+                    RainType::LightRain,
+                    RainType::ModerateRain,
+                    RainType::HeavyIntensityRain,
+                )
+            })
+        } else if self.has("DZ") {
+            WeatherCondition::Drizzle(if shower {
+                DrizzleType::ShowerDrizzle
+            } else {
+                self.by_intensity(
+                    DrizzleType::LightIntensityDrizzle,
+                    DrizzleType::Drizzle,
+                    DrizzleType::HeavyIntensityDrizzle,
+                )
+            })
+        } else {
+            let atmosphere = self.phenomena.iter().find_map(|code| match code.as_str() {
+                "FC" => Some(AtmosphereType::Tornado),
+                "SQ" => Some(AtmosphereType::Squalls),
+                "FG" => Some(AtmosphereType::Fog),
+                "BR" => Some(AtmosphereType::Mist),
+                "HZ" => Some(AtmosphereType::Haze),
+                "FU" => Some(AtmosphereType::Smoke),
+                "VA" => Some(AtmosphereType::VolcanicAsh),
+                "DU" | "DS" => Some(AtmosphereType::Dust),
+                "SA" | "SS" => Some(AtmosphereType::Sand),
+                "PO" => Some(AtmosphereType::SandDustWhirls),
+                _ => None,
+            })?;
+            WeatherCondition::Atmosphere(atmosphere)
+        };
+
+        Some(condition)
+    }
+}
+
+/// A wind group, eg. `24015G25KT` or `VRB03MPS`.
+fn wind(group: &str) -> Option<ReportedWind> {
+    let (value, factor) = if let Some(value) = group.strip_suffix("KT") {
+        (value, METRES_PER_SECOND_PER_KNOT)
+    } else if let Some(value) = group.strip_suffix("MPS") {
+        (value, 1.)
+    } else if let Some(value) = group.strip_suffix("KMH") {
+        (value, 1. / 3.6)
+    } else {
+        return None;
+    };
+
+    if value.len() < 5 || !value.is_ascii() {
+        return None;
+    }
+
+    let direction = match &value[..3] {
+        "VRB" => None,
+        direction => Some(number::<u16>(direction)? % 360),
+    };
+    let mut speeds = value[3..].splitn(2, 'G');
+    let speed = f32::from(number::<u16>(speeds.next()?)?) * factor;
+    let gust = match speeds.next() {
+        Some(gust) => Some(f32::from(number::<u16>(gust)?) * factor),
+        None => None,
+    };
+
+    Some(ReportedWind {
+        direction,
+        speed,
+        gust,
+    })
+}
+
+/// A visibility group in metres (eg. `0800` or `9999NDV`) or statute miles (eg. `1 1/2SM` or
+/// `P6SM`), in metres.
+fn visibility(group: &str) -> Option<u32> {
+    if let Some(miles) = group.strip_suffix("SM") {
+        let miles: f32 = miles
+            .trim_start_matches(&['P', 'M'][..])
+            .split(' ')
+            .map(|part| {
+                let mut fraction = part.splitn(2, '/');
+                let numerator = f32::from(number::<u16>(fraction.next()?)?);
+                match fraction.next() {
+                    Some(denominator) => match number::<u16>(denominator)? {
+                        0 => None,
+                        denominator => Some(numerator / f32::from(denominator)),
+                    },
+                    None => Some(numerator),
+                }
+            })
+            .sum::<Option<f32>>()?;
+
+        return Some((miles * METRES_PER_STATUTE_MILE).round() as u32);
+    }
+
+    let metres = group.strip_suffix("NDV").unwrap_or(group);
+    if metres.len() != 4 {
+        return None;
+    }
+
+    match number(metres)? {
+        9999 => Some(UNLIMITED_VISIBILITY),
+        metres => Some(metres),
+    }
+}
+
+/// A cloud group, eg. `BKN030CB`, as the percentage of the sky covered.
+fn cloud_cover(group: &str) -> Option<u8> {
+    if matches!(group, "SKC" | "CLR" | "NSC" | "NCD") {
+        return Some(0);
+    }
+
+    if !group.is_ascii() || group.len() < 5 {
+        return None;
+    }
+
+    // An obscured sky, with the vertical visibility.
+    if group.starts_with("VV") && group.len() == 5 {
+        return Some(100);
+    }
+
+    let height = &group[3..6.min(group.len())];
+    if height.len() != 3 || (height != "///" && number::<u16>(height).is_none()) {
+        return None;
+    }
+    if !matches!(&group[6..], "" | "CB" | "TCU" | "///") {
+        return None;
+    }
+
+    // The middle of each range of eighths.
+    match &group[..3] {
+        "FEW" => Some(19),
+        "SCT" => Some(44),
+        "BKN" => Some(75),
+        "OVC" => Some(100),
+        _ => None,
+    }
+}
+
+/// A temperature and dew point group, eg. `M02/M04`, in degrees Celsius. The dew point is
+/// sometimes missing.
+fn temperatures(group: &str) -> Option<(f32, Option<f32>)> {
+    let temperature = |raw: &str| -> Option<f32> {
+        let (sign, digits) = match raw.strip_prefix('M') {
+            Some(digits) => (-1., digits),
+            None => (1., raw),
+        };
+        if digits.len() != 2 {
+            return None;
+        }
+        Some(sign * f32::from(number::<u8>(digits)?))
+    };
+
+    let mut parts = group.splitn(2, '/');
+    let temp = temperature(parts.next()?)?;
+    let dew_point = match parts.next()? {
+        "" | "//" => None,
+        dew_point => Some(temperature(dew_point)?),
+    };
+
+    Some((temp, dew_point))
+}
+
+/// A pressure group, in hectopascals (eg. `Q1013`) or hundredths of an inch of mercury (eg.
+/// `A2992`), in hectopascals.
+fn pressure(group: &str) -> Option<f32> {
+    if group.len() != 5 {
+        return None;
+    }
+
+    if let Some(hectopascals) = group.strip_prefix('Q') {
+        Some(f32::from(number::<u16>(hectopascals)?))
+    } else if let Some(hundredths) = group.strip_prefix('A') {
+        Some(f32::from(number::<u16>(hundredths)?) / 100. * HECTOPASCALS_PER_INCH_OF_MERCURY)
+    } else {
+        None
+    }
+}
+
+/// Weather since the previous report (eg. `RERA`) and wind shear (eg. `WS`), which aren't used.
+fn is_recent_weather(group: &str) -> bool {
+    group == "WS" || (group.starts_with("RE") && group.len() > 2)
+}
+
+/// The maximum and minimum temperature groups of a TAF, eg. `TX05/1720Z`, which aren't used.
+fn is_temperature_forecast(group: &str) -> bool {
+    (group.starts_with("TX") || group.starts_with("TN")) && group.ends_with('Z')
+}
+
+/// A period in `DDHH/DDHH` form.
+fn period(
+    reference: time::OffsetDateTime,
+    group: &str,
+) -> Option<(time::OffsetDateTime, time::OffsetDateTime)> {
+    if group.len() != 9 || !group.is_ascii() || &group[4..5] != "/" {
+        return None;
+    }
+
+    Some((
+        day_time(reference, &group[..2], &group[2..4], "00")?,
+        day_time(reference, &group[5..7], &group[7..], "00")?,
+    ))
+}
+
+/// The time on a day of the month, in whichever month puts it closest to `reference`. Reports
+/// only give the day, and use hour 24 for the end of a day.
+fn day_time(
+    reference: time::OffsetDateTime,
+    day: &str,
+    hour: &str,
+    minute: &str,
+) -> Option<time::OffsetDateTime> {
+    let (day, hour, minute) = (
+        number::<u8>(day)?,
+        number::<i64>(hour)?,
+        number::<i64>(minute)?,
+    );
+    if hour > 24 || minute > 59 {
+        return None;
+    }
+
+    let month = reference.year() * 12 + i32::from(reference.month()) - 1;
+
+    (month - 1..=month + 1)
+        .filter_map(|month| {
+            time::Date::try_from_ymd(month.div_euclid(12), month.rem_euclid(12) as u8 + 1, day).ok()
+        })
+        .map(|date| {
+            date.midnight().assume_utc()
+                + time::Duration::hours(hour)
+                + time::Duration::minutes(minute)
+        })
+        .min_by_key(|time| (*time - reference).abs())
+}
+
+/// A number written out in digits only, without a sign.
+fn number<T: FromStr>(digits: &str) -> Option<T> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A sample METAR and TAF, in the format returned by aviationweather.gov.
+    const REPORTS: &str = include_str!("../../tests/fixtures/metar/cyul.txt");
+
+    fn montreal() -> Location {
+        Location {
+            name: "Montreal".to_string(),
+            latitude: 45.47,
+            longitude: -73.74,
+            timezone: None,
+        }
+    }
+
+    fn reference() -> time::OffsetDateTime {
+        time::PrimitiveDateTime::parse("2020-12-17 18:20", "%Y-%m-%d %H:%M")
+            .unwrap()
+            .assume_utc()
+    }
+
+    fn code(group: &str) -> Option<u16> {
+        let mut conditions = Conditions::default();
+        assert!(conditions.parse(group), "{} wasn't parsed", group);
+        conditions.condition().map(|condition| condition.code())
+    }
+
+    #[test]
+    fn station_test() {
+        assert_eq!(Some("CYUL".to_string()), station("cyul", "Home"));
+        assert_eq!(
+            Some("CYOW".to_string()),
+            station("CYUL; Cottage=CYOW", "cottage")
+        );
+        assert_eq!(
+            Some("CYUL".to_string()),
+            station("Cottage=CYOW;CYUL", "Home")
+        );
+        assert_eq!(None, station("Cottage=CYOW", "Home"));
+    }
+
+    #[test]
+    fn condition_test() {
+        assert_eq!(Some(511), code("-FZRA"));
+        assert_eq!(Some(511), code("FZDZ"));
+        assert_eq!(Some(202), code("+TSRA"));
+        assert_eq!(Some(211), code("TS"));
+        assert_eq!(Some(620), code("-SHSN"));
+        assert_eq!(Some(615), code("-RASN"));
+        assert_eq!(Some(611), code("PL"));
+        assert_eq!(Some(741), code("BCFG"));
+        assert_eq!(Some(701), code("BR"));
+        assert_eq!(Some(781), code("+FC"));
+        assert_eq!(Some(804), code("OVC008"));
+        assert_eq!(Some(802), code("SCT040CB"));
+        assert_eq!(Some(800), code("CAVOK"));
+
+        // Showers in the vicinity don't count.
+        assert_eq!(None, code("VCSH"));
+
+        // Precipitation is more significant than the mist that comes with it.
+        let mut conditions = Conditions::default();
+        for group in &["BR", "-RA", "BKN010"] {
+            assert!(conditions.parse(group));
+        }
+        assert_eq!(Some(500), conditions.condition().map(|c| c.code()));
+    }
+
+    #[test]
+    fn elements_test() {
+        assert_eq!(
+            Some(ReportedWind {
+                direction: Some(240),
+                speed: 15. * METRES_PER_SECOND_PER_KNOT,
+                gust: Some(25. * METRES_PER_SECOND_PER_KNOT),
+            }),
+            wind("24015G25KT")
+        );
+        assert_eq!(
+            Some(ReportedWind {
+                direction: None,
+                speed: 3.,
+                gust: None,
+            }),
+            wind("VRB03MPS")
+        );
+        assert_eq!(None, wind("2401KT"));
+
+        assert_eq!(Some(800), visibility("0800"));
+        assert_eq!(Some(10_000), visibility("9999NDV"));
+        assert_eq!(Some(2414), visibility("1 1/2SM"));
+        assert_eq!(Some(402), visibility("M1/4SM"));
+        assert_eq!(Some(9656), visibility("P6SM"));
+        assert_eq!(vec!["5", "1 1/2SM", "BR"], groups("5 1 1/2SM BR="));
+
+        assert_eq!(Some((-2., Some(-4.))), temperatures("M02/M04"));
+        assert_eq!(Some((12., None)), temperatures("12/"));
+        assert_eq!(None, temperatures("1718/1824"));
+
+        assert!((1013.2 - pressure("A2992").unwrap()).abs() < 0.1);
+        assert_eq!(Some(1008.), pressure("Q1008"));
+    }
+
+    #[test]
+    fn day_time_test() {
+        let new_year = time::PrimitiveDateTime::parse("2021-01-01 01:00", "%Y-%m-%d %H:%M")
+            .unwrap()
+            .assume_utc();
+
+        assert_eq!(
+            time::PrimitiveDateTime::parse("2020-12-31 23:50", "%Y-%m-%d %H:%M")
+                .unwrap()
+                .assume_utc(),
+            day_time(new_year, "31", "23", "50").unwrap(),
+        );
+        assert_eq!(
+            time::PrimitiveDateTime::parse("2021-01-02 00:00", "%Y-%m-%d %H:%M")
+                .unwrap()
+                .assume_utc(),
+            day_time(new_year, "01", "24", "00").unwrap(),
+        );
+    }
+
+    #[test]
+    fn decode_test() {
+        let report = decode(
+            REPORTS,
+            DecodeMode::Strict,
+            &montreal(),
+            reference(),
+            time::UtcOffset::hours(-5),
+        )
+        .unwrap()
+        .value;
+
+        let current = &report.current;
+        assert_eq!(reference() - time::Duration::minutes(20), current.time);
+        assert_eq!(time::UtcOffset::hours(-5), current.time.offset());
+        assert!((-2. - current.temp.as_ref().unwrap().celsius()).abs() < 0.001);
+        assert_eq!(Some(86), current.humidity);
+        assert!((1013.2 - current.pressure.as_ref().unwrap().hectopascals()).abs() < 0.1);
+        assert_eq!(Some(4828), current.visibility);
+        assert_eq!(Some(100), current.clouds);
+        assert_eq!(Some(511), current.condition.as_ref().map(|c| c.code()));
+        assert!(current.sunset.is_some());
+
+        let wind = current.wind.as_ref().unwrap();
+        assert_eq!(Some(240), wind.direction);
+        assert!((15. * METRES_PER_SECOND_PER_KNOT - wind.speed).abs() < 0.001);
+
+        // From the current hour until the end of the TAF, ignoring the temporary conditions.
+        assert_eq!(30, report.hourly.len());
+        assert_eq!(
+            Some(511),
+            report.hourly[0].condition.as_ref().map(|c| c.code())
+        );
+        assert_eq!(Some(4828), report.hourly[3].visibility);
+
+        // FM172200
+        assert_eq!(
+            Some(620),
+            report.hourly[4].condition.as_ref().map(|c| c.code())
+        );
+        assert_eq!(Some(270), report.hourly[4].wind.as_ref().unwrap().direction);
+
+        // BECMG 1802/1804 only changes the wind.
+        assert_eq!(None, report.hourly[10].wind.as_ref().unwrap().direction);
+        assert_eq!(Some(75), report.hourly[10].clouds);
+
+        // FM181200
+        assert_eq!(
+            Some(802),
+            report.hourly[18].condition.as_ref().map(|c| c.code())
+        );
+    }
+
+    #[test]
+    fn decode_mode_test() {
+        let body = "CYUL 171800Z 24015KT 15SM XYZ BKN030 M02/M04 A2992";
+
+        assert!(decode(
+            body,
+            DecodeMode::Strict,
+            &montreal(),
+            reference(),
+            time::UtcOffset::UTC
+        )
+        .is_err());
+
+        let decoded = decode(
+            body,
+            DecodeMode::Lenient,
+            &montreal(),
+            reference(),
+            time::UtcOffset::UTC,
+        )
+        .unwrap();
+        assert_eq!(1, decoded.skipped.len());
+        assert_eq!(Some(803), decoded.value.current.condition.map(|c| c.code()));
+        assert!(decoded.value.hourly.is_empty());
+    }
+}
@@ -0,0 +1,1283 @@
+use std::env;
+use std::fmt;
+
+use futures::future;
+
+use crate::cache::{Cache, Fetched};
+use crate::http;
+use crate::location::{Location, LocationConfig};
+use crate::units::{PrecipitationUnit, PressureUnit, SpeedUnit, TemperatureUnit};
+
+pub mod air_quality;
+pub mod alerts;
+pub mod aqhi;
+pub mod forecast;
+pub mod met_norway;
+pub mod metar;
+pub mod open_weather;
+pub mod provider;
+pub mod radar;
+
+pub use forecast::Forecast;
+use provider::Composite;
+pub use provider::Provider;
+
+const RADAR_CACHE: &str = "radar.gif";
+
+/// Everything fetched for a single refresh.
+pub struct Weather {
+    /// The primary location first, followed by any others.
+    pub locations: Vec<LocationReport>,
+    pub radar_map: Option<Fetched<Vec<u8>>>,
+
+    /// The station the radar map is from, eg. `CASBV`.
+    pub radar_station: String,
+
+    /// Air quality at the primary location.
+    pub air_quality: Option<air_quality::AirQualityReport>,
+
+    /// Alerts in effect at the primary location, most severe first.
+    pub alerts: Vec<alerts::Alert>,
+}
+
+pub struct LocationReport {
+    pub location: Location,
+    pub report: Option<Fetched<WeatherReport>>,
+}
+
+/// Fetch the weather report for each location, the radar map, the air quality and alerts. The
+/// reports and radar map fall back to the last good copy of each from the cache if they can't be
+/// fetched. Secondary locations that can't be found are left out, but the primary is required.
+pub async fn query(client: &http::Client) -> Result<Weather, String> {
+    let cache = Cache::from_env();
+    let decode_mode = DecodeMode::from_env();
+    let providers = Composite::from_env();
+    let resolved = future::join_all(
+        LocationConfig::all_from_env()
+            .into_iter()
+            .map(|config| config.resolve(client, &cache)),
+    )
+    .await;
+
+    let mut locations = Vec::with_capacity(resolved.len());
+    for (i, location) in resolved.into_iter().enumerate() {
+        match location {
+            Ok(location) => locations.push(location),
+            // Skipping the primary would show the next location in its place.
+            Err(e) if i == 0 => return Err(format!("Unable to find primary location: {}", e)),
+            Err(e) => eprintln!("Unable to find location: {}", e),
+        }
+    }
+
+    let primary = locations.first();
+    let radar_station =
+        radar::station_id(primary.map(|location| (location.latitude, location.longitude)));
+    let reports = locations
+        .iter()
+        .map(|location| providers.fetch(client, &cache, location, decode_mode));
+    let air_quality = async {
+        match primary {
+            Some(primary) => air_quality::query(client, primary).await,
+            None => None,
+        }
+    };
+
+    let alerts = async {
+        match primary {
+            Some(primary) => {
+                alerts::query(client, &cache, (primary.latitude, primary.longitude)).await
+            }
+            None => Vec::new(),
+        }
+    };
+
+    let (reports, radar_map, air_quality, alerts) = tokio::join!(
+        future::join_all(reports),
+        radar::get_weather_radar(client, &radar_station),
+        air_quality,
+        alerts,
+    );
+
+    let radar_map = match radar_map {
+        Ok(radar_map) => {
+            let fetched = Fetched::fresh(radar_map);
+            if let Err(e) = cache.store(RADAR_CACHE, &fetched.value, fetched.fetched_at) {
+                eprintln!("Unable to cache radar map: {}", e);
+            }
+            Some(fetched)
+        }
+        Err(e) => {
+            eprintln!("Unable to fetch radar map: {}", e);
+            cache.load(RADAR_CACHE).ok()
+        }
+    };
+
+    Ok(Weather {
+        locations: locations
+            .into_iter()
+            .zip(reports)
+            .map(|(location, report)| LocationReport { location, report })
+            .collect(),
+        radar_map,
+        radar_station,
+        air_quality,
+        alerts,
+    })
+}
+
+pub struct WeatherReport {
+    pub current: WeatherState,
+    pub minutely: Vec<WeatherState>,
+    pub hourly: Vec<WeatherState>,
+    pub daily: Vec<WeatherState>,
+}
+
+pub struct WeatherState {
+    pub time: time::OffsetDateTime,
+    pub sunrise: Option<time::OffsetDateTime>,
+    pub sunset: Option<time::OffsetDateTime>,
+    pub temp: Option<Temperature>,
+
+    /// The day's low and high, for daily forecasts.
+    pub temp_min: Option<Temperature>,
+    pub temp_max: Option<Temperature>,
+
+    pub humidity: Option<u8>,
+    pub wind: Option<Wind>,
+    pub pressure: Option<Pressure>,
+    pub precipitation: Option<Precipitation>,
+    pub clouds: Option<u8>,
+
+    /// Horizontal visibility, in metres.
+    pub visibility: Option<u32>,
+
+    pub condition: Option<WeatherCondition>,
+
+    /// The provider that supplied this state.
+    pub provider: Provider,
+}
+
+/// How to handle provider responses that are only partly valid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecodeMode {
+    /// Fail the whole response if any part of it is malformed.
+    Strict,
+
+    /// Skip malformed forecast entries, reporting them in `Decoded::skipped`.
+    Lenient,
+}
+
+impl DecodeMode {
+    /// Read the mode from the `DECODE_MODE` environment variable, defaulting to lenient.
+    pub fn from_env() -> Self {
+        match env::var("DECODE_MODE").as_deref() {
+            Ok("strict") => Self::Strict,
+            _ => Self::Lenient,
+        }
+    }
+}
+
+/// A decoded response along with any entries that had to be skipped to produce it.
+pub struct Decoded<T> {
+    pub value: T,
+    pub skipped: Vec<DecodeError>,
+}
+
+impl Decoded<WeatherReport> {
+    /// Report any skipped entries to stderr and return the decoded report.
+    pub fn report(self) -> WeatherReport {
+        for error in &self.skipped {
+            eprintln!("Skipped malformed entry: {}", error);
+        }
+
+        self.value
+    }
+}
+
+#[derive(Debug)]
+pub struct DecodeError {
+    /// Where in the response the error occurred, eg. `hourly[3]`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl WeatherState {
+    /// The dew point, calculated from the temperature and relative humidity.
+    pub fn dew_point(&self) -> Option<Temperature> {
+        Some(self.temp.as_ref()?.dew_point(self.humidity?))
+    }
+
+    pub fn wind_chill(&self) -> Option<Temperature> {
+        self.temp
+            .as_ref()?
+            .wind_chill(self.wind.as_ref()?.speed_km_h())
+    }
+
+    pub fn humidex(&self) -> Option<Temperature> {
+        Some(self.temp.as_ref()?.humidex(&self.dew_point()?))
+    }
+
+    pub fn heat_index(&self) -> Option<Temperature> {
+        self.temp.as_ref()?.heat_index(self.humidity?)
+    }
+
+    /// Pick whichever comfort index is relevant to the current conditions, if any: wind chill at
+    /// or below freezing, and humidex (for Celsius users) or the NWS heat index (for Fahrenheit
+    /// users) in the heat. Indices that round to the actual temperature are ignored.
+    pub fn feels_like(&self, unit: TemperatureUnit) -> Option<FeelsLike> {
+        let temp = self.temp.as_ref()?;
+
+        let feels_like = if temp.celsius() <= 0. {
+            FeelsLike::WindChill(self.wind_chill()?)
+        } else if temp.celsius() >= 20. {
+            match unit {
+                TemperatureUnit::Celsius => FeelsLike::Humidex(self.humidex()?),
+                TemperatureUnit::Fahrenheit => FeelsLike::HeatIndex(self.heat_index()?),
+            }
+        } else {
+            return None;
+        };
+
+        if (feels_like.temperature().in_unit(unit) - temp.in_unit(unit)).abs() < 1. {
+            None
+        } else {
+            Some(feels_like)
+        }
+    }
+}
+
+pub enum FeelsLike {
+    WindChill(Temperature),
+    Humidex(Temperature),
+    HeatIndex(Temperature),
+}
+
+impl FeelsLike {
+    pub fn temperature(&self) -> &Temperature {
+        match self {
+            Self::WindChill(temp) | Self::Humidex(temp) | Self::HeatIndex(temp) => temp,
+        }
+    }
+}
+
+pub struct Temperature(f32);
+
+impl Temperature {
+    pub const fn from_kelvin(kelvin: f32) -> Self {
+        Self(kelvin)
+    }
+
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self(celsius + 273.15)
+    }
+
+    pub fn from_fahrenheit(fahrenheit: f32) -> Self {
+        Self::from_celsius((fahrenheit - 32.) / 1.8)
+    }
+
+    pub fn kelvin(&self) -> f32 {
+        self.0
+    }
+
+    pub fn celsius(&self) -> f32 {
+        self.in_unit(TemperatureUnit::Celsius)
+    }
+
+    pub fn fahrenheit(&self) -> f32 {
+        self.in_unit(TemperatureUnit::Fahrenheit)
+    }
+
+    pub fn in_unit(&self, unit: TemperatureUnit) -> f32 {
+        unit.convert(self.0)
+    }
+
+    /// Format the temperature as a whole number of degrees, eg. "-12°".
+    pub fn format(&self, unit: TemperatureUnit) -> String {
+        format!("{}°", self.in_unit(unit).round())
+    }
+
+    /// Dew point for the given relative humidity (in percent), using the Magnus approximation.
+    pub fn dew_point(&self, humidity: u8) -> Self {
+        const A: f32 = 17.27;
+        const B: f32 = 237.7;
+
+        let t = self.celsius();
+        let gamma = (f32::from(humidity.max(1)) / 100.).ln() + A * t / (B + t);
+        Self::from_celsius(B * gamma / (A - gamma))
+    }
+
+    /// Relative humidity (in percent) for the given dew point, using the Magnus approximation.
+    pub fn relative_humidity(&self, dew_point: &Self) -> u8 {
+        const A: f32 = 17.27;
+        const B: f32 = 237.7;
+
+        let (t, td) = (self.celsius(), dew_point.celsius());
+        let humidity = 100. * (A * td / (B + td) - A * t / (B + t)).exp();
+        humidity.round().clamp(0., 100.) as u8
+    }
+
+    /// Wind chill using the Environment Canada formula, including the light wind variant for
+    /// speeds under 5 km/h. Returns `None` above 10°C, where the index is not defined.
+    pub fn wind_chill(&self, wind_speed_km_h: f32) -> Option<Self> {
+        let t = self.celsius();
+
+        if t > 10. {
+            None
+        } else if wind_speed_km_h < 5. {
+            Some(Self::from_celsius(
+                t + (-1.59 + 0.1345 * t) / 5. * wind_speed_km_h,
+            ))
+        } else {
+            let v = wind_speed_km_h.powf(0.16);
+            Some(Self::from_celsius(
+                13.12 + 0.6215 * t - 11.37 * v + 0.3965 * t * v,
+            ))
+        }
+    }
+
+    /// Environment Canada's humidex, calculated from the dew point.
+    pub fn humidex(&self, dew_point: &Self) -> Self {
+        let e = 6.11 * (5417.753 * (1. / 273.16 - 1. / dew_point.0)).exp();
+        Self::from_celsius(self.celsius() + 0.5555 * (e - 10.))
+    }
+
+    /// The US National Weather Service heat index for the given relative humidity (in percent).
+    /// Returns `None` below 80°F, where the index is not meaningful.
+    pub fn heat_index(&self, humidity: u8) -> Option<Self> {
+        let (t, rh) = (self.fahrenheit(), f32::from(humidity));
+
+        if t < 80. {
+            return None;
+        }
+
+        // Steadman's simple formula is used if it gives a result under 80°F, per the NWS.
+        let simple = 0.5 * (t + 61. + (t - 68.) * 1.2 + rh * 0.094);
+        if (simple + t) / 2. < 80. {
+            return Some(Self::from_fahrenheit(simple));
+        }
+
+        let mut hi = -42.379 + 2.049_015 * t + 10.143_332 * rh
+            - 0.224_755_4 * t * rh
+            - 0.006_837_83 * t * t
+            - 0.054_817_17 * rh * rh
+            + 0.001_228_74 * t * t * rh
+            + 0.000_852_82 * t * rh * rh
+            - 0.000_001_99 * t * t * rh * rh;
+
+        if rh < 13. && t <= 112. {
+            hi -= (13. - rh) / 4. * ((17. - (t - 95.).abs()) / 17.).sqrt();
+        } else if rh > 85. && t <= 87. {
+            hi += (rh - 85.) / 10. * (87. - t) / 5.;
+        }
+
+        Some(Self::from_fahrenheit(hi))
+    }
+}
+
+impl From<f32> for Temperature {
+    fn from(input: f32) -> Self {
+        Self::from_kelvin(input)
+    }
+}
+
+pub struct Wind {
+    pub speed: f32,
+
+    /// The direction the wind is coming from in degrees, unless it's variable.
+    pub direction: Option<u16>,
+    pub gust: Option<f32>,
+}
+
+impl Wind {
+    pub fn speed_km_h(&self) -> f32 {
+        self.speed_in(SpeedUnit::KilometresPerHour)
+    }
+
+    pub fn gust_km_h(&self) -> Option<f32> {
+        self.gust_in(SpeedUnit::KilometresPerHour)
+    }
+
+    pub fn speed_in(&self, unit: SpeedUnit) -> f32 {
+        unit.convert(self.speed)
+    }
+
+    pub fn gust_in(&self, unit: SpeedUnit) -> Option<f32> {
+        self.gust.map(|gust| unit.convert(gust))
+    }
+
+    /// Format the wind speed, eg. "15 km/h". Gusts are included as a range ("15-30 km/h") if
+    /// they are significantly stronger than the sustained wind.
+    pub fn format_speed(&self, unit: SpeedUnit) -> String {
+        match self.gust {
+            Some(gust) if gust > self.speed + 3. => format!(
+                "{}-{} {}",
+                self.speed_in(unit).round(),
+                unit.convert(gust).round(),
+                unit.symbol(),
+            ),
+            _ => format!("{} {}", self.speed_in(unit).round(), unit.symbol()),
+        }
+    }
+
+    pub fn compass_point(&self) -> Option<CompassPoint> {
+        self.direction.map(CompassPoint::from_degrees)
+    }
+}
+
+/// The 16 points of the compass, used to describe the direction the wind is coming from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompassPoint {
+    North,
+    NorthNorthEast,
+    NorthEast,
+    EastNorthEast,
+    East,
+    EastSouthEast,
+    SouthEast,
+    SouthSouthEast,
+    South,
+    SouthSouthWest,
+    SouthWest,
+    WestSouthWest,
+    West,
+    WestNorthWest,
+    NorthWest,
+    NorthNorthWest,
+}
+
+impl CompassPoint {
+    const ALL: [Self; 16] = [
+        Self::North,
+        Self::NorthNorthEast,
+        Self::NorthEast,
+        Self::EastNorthEast,
+        Self::East,
+        Self::EastSouthEast,
+        Self::SouthEast,
+        Self::SouthSouthEast,
+        Self::South,
+        Self::SouthSouthWest,
+        Self::SouthWest,
+        Self::WestSouthWest,
+        Self::West,
+        Self::WestNorthWest,
+        Self::NorthWest,
+        Self::NorthNorthWest,
+    ];
+
+    pub fn from_degrees(degrees: u16) -> Self {
+        Self::ALL[(f32::from(degrees % 360) / 22.5).round() as usize % 16]
+    }
+
+    pub fn abbreviation(&self) -> &'static str {
+        match self {
+            Self::North => "N",
+            Self::NorthNorthEast => "NNE",
+            Self::NorthEast => "NE",
+            Self::EastNorthEast => "ENE",
+            Self::East => "E",
+            Self::EastSouthEast => "ESE",
+            Self::SouthEast => "SE",
+            Self::SouthSouthEast => "SSE",
+            Self::South => "S",
+            Self::SouthSouthWest => "SSW",
+            Self::SouthWest => "SW",
+            Self::WestSouthWest => "WSW",
+            Self::West => "W",
+            Self::WestNorthWest => "WNW",
+            Self::NorthWest => "NW",
+            Self::NorthNorthWest => "NNW",
+        }
+    }
+}
+
+impl fmt::Display for CompassPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.abbreviation())
+    }
+}
+
+/// Atmospheric pressure, stored in hectopascals.
+pub struct Pressure(f32);
+
+impl Pressure {
+    pub const fn from_hectopascals(hectopascals: f32) -> Self {
+        Self(hectopascals)
+    }
+
+    pub fn hectopascals(&self) -> f32 {
+        self.0
+    }
+
+    pub fn in_unit(&self, unit: PressureUnit) -> f32 {
+        unit.convert(self.0)
+    }
+
+    pub fn format(&self, unit: PressureUnit) -> String {
+        format!(
+            "{:.*} {}",
+            unit.precision(),
+            self.in_unit(unit),
+            unit.symbol(),
+        )
+    }
+}
+
+impl From<f32> for Pressure {
+    fn from(input: f32) -> Self {
+        Self::from_hectopascals(input)
+    }
+}
+
+/// An amount of precipitation (liquid equivalent), stored in millimetres.
+pub struct Precipitation(f32);
+
+impl Precipitation {
+    pub const fn from_millimetres(millimetres: f32) -> Self {
+        Self(millimetres)
+    }
+
+    pub fn millimetres(&self) -> f32 {
+        self.0
+    }
+
+    pub fn in_unit(&self, unit: PrecipitationUnit) -> f32 {
+        unit.convert(self.0)
+    }
+
+    pub fn format(&self, unit: PrecipitationUnit) -> String {
+        format!(
+            "{:.*} {}",
+            unit.precision(),
+            self.in_unit(unit),
+            unit.symbol(),
+        )
+    }
+}
+
+impl From<f32> for Precipitation {
+    fn from(input: f32) -> Self {
+        Self::from_millimetres(input)
+    }
+}
+
+pub enum WeatherCondition {
+    Thunderstorm(ThunderstormType),
+    Drizzle(DrizzleType),
+    Rain(RainType),
+    Snow(SnowType),
+    Atmosphere(AtmosphereType),
+    Clear,
+    Clouds(CloudsType),
+    Unknown(u16),
+}
+
+impl From<u16> for WeatherCondition {
+    fn from(data: u16) -> Self {
+        match data {
+            200..=299 => Self::Thunderstorm(data.into()),
+            300..=399 => Self::Drizzle(data.into()),
+            500..=599 => Self::Rain(data.into()),
+            600..=699 => Self::Snow(data.into()),
+            700..=799 => Self::Atmosphere(data.into()),
+            800 => Self::Clear,
+            801..=899 => Self::Clouds(data.into()),
+            _ => Self::Unknown(data),
+        }
+    }
+}
+
+impl WeatherCondition {
+    /// The OpenWeather condition code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Thunderstorm(subtype) => subtype.code(),
+            Self::Drizzle(subtype) => subtype.code(),
+            Self::Rain(subtype) => subtype.code(),
+            Self::Snow(subtype) => subtype.code(),
+            Self::Atmosphere(subtype) => subtype.code(),
+            Self::Clear => 800,
+            Self::Clouds(subtype) => subtype.code(),
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// A short description, eg. "Freezing rain".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Thunderstorm(subtype) => subtype.description(),
+            Self::Drizzle(subtype) => subtype.description(),
+            Self::Rain(subtype) => subtype.description(),
+            Self::Snow(subtype) => subtype.description(),
+            Self::Atmosphere(subtype) => subtype.description(),
+            Self::Clear => "Clear",
+            Self::Clouds(subtype) => subtype.description(),
+            Self::Unknown(_) => "Unknown",
+        }
+    }
+
+    /// Whether anything is falling. Thunderstorms count, since they rarely come without rain.
+    pub fn is_precipitation(&self) -> bool {
+        matches!(
+            self,
+            Self::Thunderstorm(_) | Self::Drizzle(_) | Self::Rain(_) | Self::Snow(_)
+        )
+    }
+
+    /// How heavy the precipitation or thunderstorm is, if there is any.
+    pub fn intensity(&self) -> Option<Intensity> {
+        match self {
+            Self::Thunderstorm(subtype) => Some(subtype.intensity()),
+            Self::Drizzle(subtype) => Some(subtype.intensity()),
+            Self::Rain(subtype) => Some(subtype.intensity()),
+            Self::Snow(subtype) => Some(subtype.intensity()),
+            _ => None,
+        }
+    }
+
+    /// Whether the weather is dangerous to be out in or to travel through, roughly the conditions
+    /// Environment Canada issues warnings and advisories for.
+    pub fn is_hazardous(&self) -> bool {
+        match self {
+            Self::Thunderstorm(_) => true,
+            Self::Rain(subtype) => matches!(
+                subtype,
+                RainType::FreezingRain | RainType::VeryHeavyRain | RainType::ExtremeRain
+            ),
+            Self::Snow(subtype) => matches!(
+                subtype,
+                SnowType::HeavySnow
+                    | SnowType::HeavyShowerSnow
+                    | SnowType::Sleet
+                    | SnowType::ShowerSleet
+            ),
+            Self::Atmosphere(subtype) => matches!(
+                subtype,
+                AtmosphereType::Fog
+                    | AtmosphereType::Sand
+                    | AtmosphereType::Dust
+                    | AtmosphereType::VolcanicAsh
+                    | AtmosphereType::Squalls
+                    | AtmosphereType::Tornado
+            ),
+            _ => false,
+        }
+    }
+
+    /// A rank for picking the most significant of several conditions: hazardous weather first,
+    /// then precipitation, each by intensity, then obscurations and finally cloud cover.
+    pub fn severity(&self) -> u8 {
+        let intensity = self.intensity().map_or(0, |intensity| intensity as u8);
+
+        if self.is_hazardous() {
+            10 + intensity
+        } else if self.is_precipitation() {
+            6 + intensity
+        } else {
+            match self {
+                Self::Atmosphere(_) => 5,
+                Self::Clouds(CloudsType::FewClouds) => 1,
+                Self::Clouds(CloudsType::ScatteredClouds) => 2,
+                Self::Clouds(CloudsType::BrokenClouds) => 3,
+                Self::Clouds(_) => 4,
+                _ => 0,
+            }
+        }
+    }
+}
+
+impl fmt::Display for WeatherCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.description())
+    }
+}
+
+/// How heavy precipitation is, from lightest to heaviest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Intensity {
+    Light,
+    Moderate,
+    Heavy,
+    Extreme,
+}
+
+pub enum ThunderstormType {
+    ThunderstormWithLightRain,
+    ThunderstormWithRain,
+    ThunderstormWithHeavyRain,
+    LightThunderstorm,
+    Thunderstorm,
+    HeavyThunderstorm,
+    RaggedThunderstorm,
+    ThunderstormWithLightDrizzle,
+    ThunderstormWithDrizzle,
+    ThunderstormWithHeavyDrizzle,
+    Unknown(u16),
+}
+
+impl From<u16> for ThunderstormType {
+    fn from(data: u16) -> Self {
+        match data {
+            200 => Self::ThunderstormWithLightRain,
+            201 => Self::ThunderstormWithRain,
+            202 => Self::ThunderstormWithHeavyRain,
+            210 => Self::LightThunderstorm,
+            211 => Self::Thunderstorm,
+            212 => Self::HeavyThunderstorm,
+            221 => Self::RaggedThunderstorm,
+            230 => Self::ThunderstormWithLightDrizzle,
+            231 => Self::ThunderstormWithDrizzle,
+            232 => Self::ThunderstormWithHeavyDrizzle,
+            _ => Self::Unknown(data),
+        }
+    }
+}
+
+impl ThunderstormType {
+    /// The OpenWeather condition code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::ThunderstormWithLightRain => 200,
+            Self::ThunderstormWithRain => 201,
+            Self::ThunderstormWithHeavyRain => 202,
+            Self::LightThunderstorm => 210,
+            Self::Thunderstorm => 211,
+            Self::HeavyThunderstorm => 212,
+            Self::RaggedThunderstorm => 221,
+            Self::ThunderstormWithLightDrizzle => 230,
+            Self::ThunderstormWithDrizzle => 231,
+            Self::ThunderstormWithHeavyDrizzle => 232,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// A short description, eg. "Thunderstorm with light rain".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::ThunderstormWithLightRain => "Thunderstorm with light rain",
+            Self::ThunderstormWithRain => "Thunderstorm with rain",
+            Self::ThunderstormWithHeavyRain => "Thunderstorm with heavy rain",
+            Self::LightThunderstorm => "Light thunderstorm",
+            Self::Thunderstorm | Self::Unknown(_) => "Thunderstorm",
+            Self::HeavyThunderstorm => "Heavy thunderstorm",
+            Self::RaggedThunderstorm => "Isolated thunderstorms",
+            Self::ThunderstormWithLightDrizzle => "Thunderstorm with light drizzle",
+            Self::ThunderstormWithDrizzle => "Thunderstorm with drizzle",
+            Self::ThunderstormWithHeavyDrizzle => "Thunderstorm with heavy drizzle",
+        }
+    }
+
+    pub fn intensity(&self) -> Intensity {
+        match self {
+            Self::ThunderstormWithLightRain
+            | Self::LightThunderstorm
+            | Self::ThunderstormWithLightDrizzle => Intensity::Light,
+            Self::ThunderstormWithHeavyRain
+            | Self::HeavyThunderstorm
+            | Self::ThunderstormWithHeavyDrizzle => Intensity::Heavy,
+            _ => Intensity::Moderate,
+        }
+    }
+}
+
+impl fmt::Display for ThunderstormType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.description())
+    }
+}
+
+pub enum DrizzleType {
+    LightIntensityDrizzle,
+    Drizzle,
+    HeavyIntensityDrizzle,
+    LightIntensityDrizzleRain,
+    DrizzleRain,
+    HeavyIntensityDrizzleRain,
+    ShowerRainAndDrizzle,
+    HeavyShowerRainAndDrizzle,
+    ShowerDrizzle,
+    Unknown(u16),
+}
+
+impl From<u16> for DrizzleType {
+    fn from(data: u16) -> Self {
+        match data {
+            300 => Self::LightIntensityDrizzle,
+            301 => Self::Drizzle,
+            302 => Self::HeavyIntensityDrizzle,
+            310 => Self::LightIntensityDrizzleRain,
+            311 => Self::DrizzleRain,
+            312 => Self::HeavyIntensityDrizzleRain,
+            313 => Self::ShowerRainAndDrizzle,
+            314 => Self::HeavyShowerRainAndDrizzle,
+            321 => Self::ShowerDrizzle,
+            _ => Self::Unknown(data),
+        }
+    }
+}
+
+impl DrizzleType {
+    /// The OpenWeather condition code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::LightIntensityDrizzle => 300,
+            Self::Drizzle => 301,
+            Self::HeavyIntensityDrizzle => 302,
+            Self::LightIntensityDrizzleRain => 310,
+            Self::DrizzleRain => 311,
+            Self::HeavyIntensityDrizzleRain => 312,
+            Self::ShowerRainAndDrizzle => 313,
+            Self::HeavyShowerRainAndDrizzle => 314,
+            Self::ShowerDrizzle => 321,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// A short description, eg. "Light drizzle".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::LightIntensityDrizzle => "Light drizzle",
+            Self::Drizzle | Self::Unknown(_) => "Drizzle",
+            Self::HeavyIntensityDrizzle => "Heavy drizzle",
+            Self::LightIntensityDrizzleRain => "Light rain and drizzle",
+            Self::DrizzleRain => "Rain and drizzle",
+            Self::HeavyIntensityDrizzleRain => "Heavy rain and drizzle",
+            Self::ShowerRainAndDrizzle => "Showers and drizzle",
+            Self::HeavyShowerRainAndDrizzle => "Heavy showers and drizzle",
+            Self::ShowerDrizzle => "Drizzle showers",
+        }
+    }
+
+    pub fn intensity(&self) -> Intensity {
+        match self {
+            Self::LightIntensityDrizzle | Self::LightIntensityDrizzleRain => Intensity::Light,
+            Self::HeavyIntensityDrizzle
+            | Self::HeavyIntensityDrizzleRain
+            | Self::HeavyShowerRainAndDrizzle => Intensity::Heavy,
+            _ => Intensity::Moderate,
+        }
+    }
+}
+
+impl fmt::Display for DrizzleType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.description())
+    }
+}
+
+pub enum RainType {
+    LightRain,
+    ModerateRain,
+    HeavyIntensityRain,
+    VeryHeavyRain,
+    ExtremeRain,
+    FreezingRain,
+    LightIntensityShowerRain,
+    ShowerRain,
+    HeavyIntensityShowerRain,
+    RaggedShowerRain,
+    Unknown(u16),
+}
+
+impl From<u16> for RainType {
+    fn from(data: u16) -> Self {
+        match data {
+            500 => Self::LightRain,
+            501 => Self::ModerateRain,
+            502 => Self::HeavyIntensityRain,
+            503 => Self::VeryHeavyRain,
+            504 => Self::ExtremeRain,
+            511 => Self::FreezingRain,
+            520 => Self::LightIntensityShowerRain,
+            521 => Self::ShowerRain,
+            522 => Self::HeavyIntensityShowerRain,
+            531 => Self::RaggedShowerRain,
+            _ => Self::Unknown(data),
+        }
+    }
+}
+
+impl RainType {
+    /// The OpenWeather condition code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::LightRain => 500,
+            Self::ModerateRain => 501,
+            Self::HeavyIntensityRain => 502,
+            Self::VeryHeavyRain => 503,
+            Self::ExtremeRain => 504,
+            Self::FreezingRain => 511,
+            Self::LightIntensityShowerRain => 520,
+            Self::ShowerRain => 521,
+            Self::HeavyIntensityShowerRain => 522,
+            Self::RaggedShowerRain => 531,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// A short description, eg. "Light rain".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::LightRain => "Light rain",
+            Self::ModerateRain | Self::Unknown(_) => "Rain",
+            Self::HeavyIntensityRain => "Heavy rain",
+            Self::VeryHeavyRain => "Very heavy rain",
+            Self::ExtremeRain => "Torrential rain",
+            Self::FreezingRain => "Freezing rain",
+            Self::LightIntensityShowerRain => "Light showers",
+            Self::ShowerRain => "Showers",
+            Self::HeavyIntensityShowerRain => "Heavy showers",
+            Self::RaggedShowerRain => "Isolated showers",
+        }
+    }
+
+    pub fn intensity(&self) -> Intensity {
+        match self {
+            Self::LightRain | Self::LightIntensityShowerRain => Intensity::Light,
+            Self::HeavyIntensityRain | Self::HeavyIntensityShowerRain => Intensity::Heavy,
+            Self::VeryHeavyRain | Self::ExtremeRain => Intensity::Extreme,
+            _ => Intensity::Moderate,
+        }
+    }
+}
+
+impl fmt::Display for RainType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.description())
+    }
+}
+
+pub enum SnowType {
+    LightSnow,
+    Snow,
+    HeavySnow,
+    Sleet,
+    LightShowerSleet,
+    ShowerSleet,
+    LightRainAndSnow,
+    RainAndSnow,
+    LightShowerSnow,
+    ShowerSnow,
+    HeavyShowerSnow,
+    Unknown(u16),
+}
+
+impl From<u16> for SnowType {
+    fn from(data: u16) -> Self {
+        match data {
+            600 => Self::LightSnow,
+            601 => Self::Snow,
+            602 => Self::HeavySnow,
+            611 => Self::Sleet,
+            612 => Self::LightShowerSleet,
+            613 => Self::ShowerSleet,
+            615 => Self::LightRainAndSnow,
+            616 => Self::RainAndSnow,
+            620 => Self::LightShowerSnow,
+            621 => Self::ShowerSnow,
+            622 => Self::HeavyShowerSnow,
+            _ => Self::Unknown(data),
+        }
+    }
+}
+
+impl SnowType {
+    /// The OpenWeather condition code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::LightSnow => 600,
+            Self::Snow => 601,
+            Self::HeavySnow => 602,
+            Self::Sleet => 611,
+            Self::LightShowerSleet => 612,
+            Self::ShowerSleet => 613,
+            Self::LightRainAndSnow => 615,
+            Self::RainAndSnow => 616,
+            Self::LightShowerSnow => 620,
+            Self::ShowerSnow => 621,
+            Self::HeavyShowerSnow => 622,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// A short description, eg. "Light snow".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::LightSnow => "Light snow",
+            Self::Snow | Self::Unknown(_) => "Snow",
+            Self::HeavySnow => "Heavy snow",
+            Self::Sleet => "Ice pellets",
+            Self::LightShowerSleet => "Light ice pellet showers",
+            Self::ShowerSleet => "Ice pellet showers",
+            Self::LightRainAndSnow => "Light rain and snow",
+            Self::RainAndSnow => "Rain and snow",
+            Self::LightShowerSnow => "Light flurries",
+            Self::ShowerSnow => "Flurries",
+            Self::HeavyShowerSnow => "Heavy flurries",
+        }
+    }
+
+    pub fn intensity(&self) -> Intensity {
+        match self {
+            Self::LightSnow
+            | Self::LightShowerSleet
+            | Self::LightRainAndSnow
+            | Self::LightShowerSnow => Intensity::Light,
+            Self::HeavySnow | Self::HeavyShowerSnow => Intensity::Heavy,
+            _ => Intensity::Moderate,
+        }
+    }
+}
+
+impl fmt::Display for SnowType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.description())
+    }
+}
+
+pub enum AtmosphereType {
+    Mist,
+    Smoke,
+    Haze,
+    SandDustWhirls,
+    Fog,
+    Sand,
+    Dust,
+    VolcanicAsh,
+    Squalls,
+    Tornado,
+    Unknown(u16),
+}
+
+impl From<u16> for AtmosphereType {
+    fn from(data: u16) -> Self {
+        match data {
+            701 => Self::Mist,
+            711 => Self::Smoke,
+            721 => Self::Haze,
+            731 => Self::SandDustWhirls,
+            741 => Self::Fog,
+            751 => Self::Sand,
+            761 => Self::Dust,
+            762 => Self::VolcanicAsh,
+            771 => Self::Squalls,
+            781 => Self::Tornado,
+            _ => Self::Unknown(data),
+        }
+    }
+}
+
+impl AtmosphereType {
+    /// The OpenWeather condition code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Mist => 701,
+            Self::Smoke => 711,
+            Self::Haze => 721,
+            Self::SandDustWhirls => 731,
+            Self::Fog => 741,
+            Self::Sand => 751,
+            Self::Dust => 761,
+            Self::VolcanicAsh => 762,
+            Self::Squalls => 771,
+            Self::Tornado => 781,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// A short description, eg. "Mist".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Mist => "Mist",
+            Self::Smoke => "Smoke",
+            Self::Haze => "Haze",
+            Self::SandDustWhirls => "Dust whirls",
+            Self::Fog | Self::Unknown(_) => "Fog",
+            Self::Sand => "Blowing sand",
+            Self::Dust => "Blowing dust",
+            Self::VolcanicAsh => "Volcanic ash",
+            Self::Squalls => "Squalls",
+            Self::Tornado => "Tornado",
+        }
+    }
+}
+
+impl fmt::Display for AtmosphereType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.description())
+    }
+}
+
+pub enum CloudsType {
+    FewClouds,
+    ScatteredClouds,
+    BrokenClouds,
+    OvercastClouds,
+    Unknown(u16),
+}
+
+impl From<u16> for CloudsType {
+    fn from(data: u16) -> Self {
+        match data {
+            801 => Self::FewClouds,
+            802 => Self::ScatteredClouds,
+            803 => Self::BrokenClouds,
+            804 => Self::OvercastClouds,
+            _ => Self::Unknown(data),
+        }
+    }
+}
+
+impl CloudsType {
+    /// The OpenWeather condition code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::FewClouds => 801,
+            Self::ScatteredClouds => 802,
+            Self::BrokenClouds => 803,
+            Self::OvercastClouds => 804,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// A short description, eg. "Mainly clear".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::FewClouds => "Mainly clear",
+            Self::ScatteredClouds => "Partly cloudy",
+            Self::BrokenClouds => "Mostly cloudy",
+            Self::OvercastClouds | Self::Unknown(_) => "Cloudy",
+        }
+    }
+}
+
+impl fmt::Display for CloudsType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.description())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_near(expected: f32, actual: f32) {
+        assert!(
+            (expected - actual).abs() < 0.5,
+            "expected {}, got {}",
+            expected,
+            actual,
+        );
+    }
+
+    #[test]
+    fn compass_point_test() {
+        assert_eq!(CompassPoint::North, CompassPoint::from_degrees(0));
+        assert_eq!(CompassPoint::North, CompassPoint::from_degrees(11));
+        assert_eq!(CompassPoint::NorthNorthEast, CompassPoint::from_degrees(12));
+        assert_eq!(CompassPoint::WestSouthWest, CompassPoint::from_degrees(248));
+        assert_eq!(CompassPoint::North, CompassPoint::from_degrees(355));
+        assert_eq!("NNW", CompassPoint::from_degrees(340).to_string());
+    }
+
+    #[test]
+    fn dew_point_test() {
+        assert_near(9.3, Temperature::from_celsius(20.).dew_point(50).celsius());
+        assert_near(20., Temperature::from_celsius(20.).dew_point(100).celsius());
+    }
+
+    #[test]
+    fn relative_humidity_test() {
+        let temp = Temperature::from_celsius(20.);
+        assert_eq!(50, temp.relative_humidity(&temp.dew_point(50)));
+        assert_eq!(100, temp.relative_humidity(&Temperature::from_celsius(20.)));
+    }
+
+    #[test]
+    fn condition_test() {
+        let freezing_rain = WeatherCondition::from(511);
+        assert_eq!("Freezing rain", freezing_rain.to_string());
+        assert!(freezing_rain.is_precipitation());
+        assert!(freezing_rain.is_hazardous());
+        assert_eq!(Some(Intensity::Moderate), freezing_rain.intensity());
+
+        let flurries = WeatherCondition::from(620);
+        assert_eq!("Light flurries", flurries.to_string());
+        assert_eq!(Some(Intensity::Light), flurries.intensity());
+        assert!(!flurries.is_hazardous());
+
+        let mist = WeatherCondition::from(701);
+        assert!(!mist.is_precipitation());
+        assert_eq!(None, mist.intensity());
+
+        assert_eq!(
+            Some(Intensity::Extreme),
+            WeatherCondition::from(504).intensity()
+        );
+        assert_eq!("Mostly cloudy", CloudsType::BrokenClouds.to_string());
+    }
+
+    #[test]
+    fn severity_test() {
+        let mut conditions: Vec<WeatherCondition> = vec![
+            800.into(),
+            502.into(),
+            741.into(),
+            804.into(),
+            500.into(),
+            701.into(),
+            212.into(),
+        ];
+        conditions.sort_by_key(WeatherCondition::severity);
+
+        assert_eq!(
+            vec![800, 804, 701, 500, 502, 741, 212],
+            conditions
+                .iter()
+                .map(WeatherCondition::code)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn wind_chill_test() {
+        // Values from Environment Canada's wind chill table.
+        let temp = Temperature::from_celsius(-20.);
+        assert_near(-32.6, temp.wind_chill(30.).unwrap().celsius());
+        assert_near(-21.7, temp.wind_chill(2.).unwrap().celsius());
+        assert!(Temperature::from_celsius(15.).wind_chill(30.).is_none());
+    }
+
+    #[test]
+    fn humidex_test() {
+        // Values from Environment Canada's humidex table.
+        let dew_point = Temperature::from_celsius(15.);
+        assert_near(
+            34.,
+            Temperature::from_celsius(30.).humidex(&dew_point).celsius(),
+        );
+    }
+
+    #[test]
+    fn heat_index_test() {
+        // Values from the NWS heat index chart.
+        assert_near(
+            100.,
+            Temperature::from_fahrenheit(90.)
+                .heat_index(60)
+                .unwrap()
+                .fahrenheit(),
+        );
+        assert!(Temperature::from_fahrenheit(70.).heat_index(60).is_none());
+    }
+}
@@ -0,0 +1,451 @@
+//! Raw response types for the OpenWeather One Call API, and the mapping from them to the domain
+//! model in the parent module.
+
+use std::env;
+
+use serde::Deserialize;
+
+use super::air_quality::{AirQuality, AirQualityIndex, AirQualityReport, Pollutant};
+use super::{
+    DecodeError, DecodeMode, Decoded, Precipitation, Provider, Temperature, WeatherCondition,
+    WeatherReport, WeatherState, Wind,
+};
+use crate::astronomy::SunEvents;
+use crate::http;
+use crate::location::Location;
+
+pub async fn call_open_weather_api(
+    client: &http::Client,
+    location: &Location,
+) -> Result<String, String> {
+    client
+        .get_text(&format!(
+        "https://api.openweathermap.org/data/2.5/onecall?lat={}&lon={}&exclude=minutely&appid={}",
+        location.latitude,
+        location.longitude,
+        api_key()?,
+    ))
+        .await
+}
+
+fn api_key() -> Result<String, String> {
+    env::var("OPEN_WEATHER_API_KEY").map_err(|_| "Missing OPEN_WEATHER_API_KEY.".to_string())
+}
+
+/// Decode a One Call API response. The top level and the `current` entry must always be valid;
+/// in `DecodeMode::Lenient`, malformed `minutely`, `hourly` and `daily` entries are skipped.
+pub fn decode(body: &str, mode: DecodeMode) -> Result<Decoded<WeatherReport>, DecodeError> {
+    let response: OneCallResponse = serde_json::from_str(body).map_err(|e| DecodeError {
+        path: String::new(),
+        message: e.to_string(),
+    })?;
+
+    let tz_offset = time::UtcOffset::seconds(response.timezone_offset);
+    let coordinates = response.lat.zip(response.lon);
+
+    // Convert times to local time, and fill in sunrise and sunset for entries that don't include
+    // them (eg. hourly forecasts) based on the entry's own date.
+    let localize = |raw: RawWeatherState| {
+        let mut state = WeatherState::from(raw);
+        state.time = state.time.to_offset(tz_offset);
+
+        if let (None, None, Some((latitude, longitude))) =
+            (state.sunrise, state.sunset, coordinates)
+        {
+            let sun = SunEvents::new(state.time.date(), latitude, longitude);
+            state.sunrise = sun.sunrise;
+            state.sunset = sun.sunset;
+        }
+
+        state.sunrise = state.sunrise.map(|t| t.to_offset(tz_offset));
+        state.sunset = state.sunset.map(|t| t.to_offset(tz_offset));
+        state
+    };
+
+    let mut skipped = Vec::new();
+    let mut decode_entries = |name: &str, entries: Vec<serde_json::Value>| {
+        let mut states = Vec::with_capacity(entries.len());
+
+        for (i, entry) in entries.into_iter().enumerate() {
+            match serde_json::from_value(entry) {
+                Ok(raw) => states.push(localize(raw)),
+                Err(e) => {
+                    let error = DecodeError {
+                        path: format!("{}[{}]", name, i),
+                        message: e.to_string(),
+                    };
+
+                    match mode {
+                        DecodeMode::Strict => return Err(error),
+                        DecodeMode::Lenient => skipped.push(error),
+                    }
+                }
+            }
+        }
+
+        Ok(states)
+    };
+
+    let minutely = decode_entries("minutely", response.minutely)?;
+    let hourly = decode_entries("hourly", response.hourly)?;
+    let daily = decode_entries("daily", response.daily)?;
+
+    Ok(Decoded {
+        value: WeatherReport {
+            current: localize(response.current),
+            minutely,
+            hourly,
+            daily,
+        },
+        skipped,
+    })
+}
+
+/// Fetch the current air pollution and its hourly forecast.
+pub async fn get_air_pollution(
+    client: &http::Client,
+    location: &Location,
+) -> Result<AirQualityReport, String> {
+    let api_key = api_key()?;
+    let url = |endpoint: &str| {
+        format!(
+            "https://api.openweathermap.org/data/2.5/{}?lat={}&lon={}&appid={}",
+            endpoint, location.latitude, location.longitude, api_key,
+        )
+    };
+
+    let current_url = url("air_pollution");
+    let forecast_url = url("air_pollution/forecast");
+
+    let (current, forecast) = tokio::join!(
+        client.get_text(&current_url),
+        client.get_text(&forecast_url),
+    );
+
+    Ok(AirQualityReport {
+        current: decode_air_pollution(&current?)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .next(),
+        forecast: forecast
+            .and_then(|forecast| decode_air_pollution(&forecast).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Unable to fetch air pollution forecast: {}", e);
+                Vec::new()
+            }),
+    })
+}
+
+pub fn decode_air_pollution(body: &str) -> Result<Vec<AirQuality>, DecodeError> {
+    let response: AirPollutionResponse = serde_json::from_str(body).map_err(|e| DecodeError {
+        path: String::new(),
+        message: e.to_string(),
+    })?;
+
+    Ok(response.list.into_iter().map(AirQuality::from).collect())
+}
+
+#[derive(Deserialize)]
+struct OneCallResponse {
+    lat: Option<f64>,
+    lon: Option<f64>,
+
+    #[serde(default)]
+    timezone_offset: i32,
+
+    current: RawWeatherState,
+
+    // Forecast entries are decoded individually so a single bad entry can be skipped.
+    #[serde(default)]
+    minutely: Vec<serde_json::Value>,
+
+    #[serde(default)]
+    hourly: Vec<serde_json::Value>,
+
+    #[serde(default)]
+    daily: Vec<serde_json::Value>,
+}
+
+/// ```json
+/// {
+///     "dt": 1595243443,
+///     "sunrise": 1608124431,
+///     "sunset": 1608160224,
+///     "temp": 274.75,
+///     "feels_like": 270.4,
+///     "pressure": 1017,
+///     "humidity": 96,
+///     "dew_point": 274.18,
+///     "uvi": 0,
+///     "clouds": 90,
+///     "visibility": 6437,
+///     "wind_speed": 3.6,
+///     "wind_deg": 320,
+///     "weather": [{
+///         "id": 701,
+///         "main": "Mist",
+///         "description": "mist",
+///         "icon": "50n"
+///     }]
+/// }
+/// ```
+#[derive(Deserialize)]
+struct RawWeatherState {
+    dt: i64,
+    sunrise: Option<i64>,
+    sunset: Option<i64>,
+    temp: Option<RawTemperature>,
+    humidity: Option<u8>,
+    pressure: Option<f32>,
+    wind_speed: Option<f32>,
+    wind_deg: Option<u16>,
+    wind_gust: Option<f32>,
+    clouds: Option<u8>,
+    visibility: Option<u32>,
+
+    /// Only present in minutely entries.
+    precipitation: Option<f32>,
+
+    rain: Option<RawPrecipitation>,
+    snow: Option<RawPrecipitation>,
+
+    #[serde(default)]
+    weather: Vec<RawCondition>,
+}
+
+/// Current and hourly entries have a single temperature, while daily entries have a temperature
+/// for each part of the day.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTemperature {
+    Instant(f32),
+    Daily { day: f32, min: f32, max: f32 },
+}
+
+/// Current and hourly entries report the past hour as `{"1h": 0.5}`, while daily entries report
+/// the whole day as a bare number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPrecipitation {
+    Total(f32),
+    Hourly {
+        #[serde(rename = "1h")]
+        one_hour: f32,
+    },
+}
+
+#[derive(Deserialize)]
+struct RawCondition {
+    id: u16,
+}
+
+/// ```json
+/// {
+///     "coord": { "lon": -73.6, "lat": 45.5 },
+///     "list": [{
+///         "main": { "aqi": 2 },
+///         "components": {
+///             "co": 240.33,
+///             "no": 0,
+///             "no2": 9.17,
+///             "o3": 68.66,
+///             "so2": 1.79,
+///             "pm2_5": 5.12,
+///             "pm10": 6.01,
+///             "nh3": 0.51
+///         },
+///         "dt": 1623754800
+///     }]
+/// }
+/// ```
+#[derive(Deserialize)]
+struct AirPollutionResponse {
+    list: Vec<RawAirPollution>,
+}
+
+#[derive(Deserialize)]
+struct RawAirPollution {
+    dt: i64,
+    main: RawAirQualityIndex,
+    components: RawPollutants,
+}
+
+#[derive(Deserialize)]
+struct RawAirQualityIndex {
+    aqi: u8,
+}
+
+/// Concentrations in μg/m³.
+#[derive(Deserialize)]
+struct RawPollutants {
+    co: Option<f32>,
+    no2: Option<f32>,
+    o3: Option<f32>,
+    so2: Option<f32>,
+    pm2_5: Option<f32>,
+    pm10: Option<f32>,
+}
+
+impl From<RawAirPollution> for AirQuality {
+    fn from(raw: RawAirPollution) -> Self {
+        let components = raw.components;
+
+        Self {
+            time: time::OffsetDateTime::from_unix_timestamp(raw.dt),
+            period: None,
+            index: AirQualityIndex::OpenWeather(raw.main.aqi),
+            dominant_pollutant: [
+                (Pollutant::CarbonMonoxide, components.co),
+                (Pollutant::NitrogenDioxide, components.no2),
+                (Pollutant::Ozone, components.o3),
+                (Pollutant::SulphurDioxide, components.so2),
+                (Pollutant::FineParticulateMatter, components.pm2_5),
+                (Pollutant::CoarseParticulateMatter, components.pm10),
+            ]
+            .iter()
+            .filter_map(|(pollutant, concentration)| {
+                concentration.map(|concentration| {
+                    (
+                        *pollutant,
+                        concentration / pollutant.very_poor_concentration(),
+                    )
+                })
+            })
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(pollutant, _)| pollutant),
+        }
+    }
+}
+
+impl From<RawWeatherState> for WeatherState {
+    fn from(raw: RawWeatherState) -> Self {
+        let precipitation = |amount: RawPrecipitation| match amount {
+            RawPrecipitation::Total(amount) => amount,
+            RawPrecipitation::Hourly { one_hour } => one_hour,
+        };
+        let (direction, gust) = (raw.wind_deg, raw.wind_gust);
+        let (temp_min, temp_max) = match raw.temp {
+            Some(RawTemperature::Daily { min, max, .. }) => {
+                (Some(Temperature::from(min)), Some(Temperature::from(max)))
+            }
+            _ => (None, None),
+        };
+
+        Self {
+            time: time::OffsetDateTime::from_unix_timestamp(raw.dt),
+            sunrise: raw.sunrise.map(time::OffsetDateTime::from_unix_timestamp),
+            sunset: raw.sunset.map(time::OffsetDateTime::from_unix_timestamp),
+            temp: raw.temp.map(|temp| match temp {
+                RawTemperature::Instant(temp) => temp.into(),
+                RawTemperature::Daily { day, .. } => day.into(),
+            }),
+            temp_min,
+            temp_max,
+            humidity: raw.humidity,
+            wind: raw.wind_speed.map(|speed| Wind {
+                speed,
+                direction,
+                gust,
+            }),
+            pressure: raw.pressure.map(|pressure| pressure.into()),
+            precipitation: match (raw.precipitation, raw.rain, raw.snow) {
+                (None, None, None) => None,
+                (minutely, rain, snow) => Some(Precipitation::from_millimetres(
+                    minutely.unwrap_or(0.)
+                        + rain.map_or(0., precipitation)
+                        + snow.map_or(0., precipitation),
+                )),
+            },
+            clouds: raw.clouds,
+            visibility: raw.visibility,
+            condition: raw
+                .weather
+                .first()
+                .map(|condition| WeatherCondition::from(condition.id)),
+            provider: Provider::OpenWeather,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ONE_CALL: &str = include_str!("../../tests/fixtures/open_weather/onecall.json");
+    const ONE_CALL_MALFORMED: &str =
+        include_str!("../../tests/fixtures/open_weather/onecall_malformed.json");
+
+    #[test]
+    fn decode_test() {
+        let report = decode(ONE_CALL, DecodeMode::Strict).unwrap();
+        assert!(report.skipped.is_empty());
+
+        let report = report.value;
+        assert_eq!(time::UtcOffset::hours(-5), report.current.time.offset());
+        assert_eq!(2, report.minutely.len());
+        assert_eq!(3, report.hourly.len());
+        assert_eq!(1, report.daily.len());
+
+        let current = report.current;
+        assert_eq!(2, current.temp.unwrap().celsius().round() as i32);
+        assert_eq!(Some(96), current.humidity);
+        assert_eq!(Some(320), current.wind.as_ref().unwrap().direction);
+        assert!(matches!(
+            current.condition,
+            Some(WeatherCondition::Atmosphere(_))
+        ));
+
+        // Hourly entries get their own sunrise and sunset.
+        let tomorrow = &report.hourly[2];
+        assert_eq!(
+            tomorrow.time.date(),
+            tomorrow.sunrise.unwrap().date(),
+            "Hourly sunrise should be on the same day as the entry."
+        );
+
+        assert!(report.hourly[1].precipitation.is_some());
+        assert!(report.daily[0].temp.is_some());
+        assert_eq!(
+            (-5, 3),
+            (
+                report.daily[0].temp_min.as_ref().unwrap().celsius().round() as i32,
+                report.daily[0].temp_max.as_ref().unwrap().celsius().round() as i32,
+            )
+        );
+    }
+
+    #[test]
+    fn decode_malformed_test() {
+        let error = decode(ONE_CALL_MALFORMED, DecodeMode::Strict)
+            .err()
+            .unwrap();
+        assert_eq!("hourly[1]", error.path);
+
+        let report = decode(ONE_CALL_MALFORMED, DecodeMode::Lenient).unwrap();
+        assert_eq!(1, report.skipped.len());
+        assert_eq!("hourly[1]", report.skipped[0].path);
+        assert_eq!(2, report.value.hourly.len());
+    }
+
+    #[test]
+    fn decode_air_pollution_test() {
+        let air_quality = decode_air_pollution(include_str!(
+            "../../tests/fixtures/open_weather/air_pollution.json"
+        ))
+        .unwrap();
+
+        assert_eq!(2, air_quality.len());
+        assert_eq!(AirQualityIndex::OpenWeather(4), air_quality[0].index);
+        assert_eq!(
+            Some(Pollutant::FineParticulateMatter),
+            air_quality[0].dominant_pollutant
+        );
+        assert_eq!(Some(Pollutant::Ozone), air_quality[1].dominant_pollutant);
+    }
+
+    #[test]
+    fn decode_invalid_test() {
+        assert!(decode("{}", DecodeMode::Lenient).is_err());
+        assert!(decode("<html>", DecodeMode::Lenient).is_err());
+    }
+}
@@ -0,0 +1,348 @@
+//! Weather providers, and a composite that falls back from one to the next and blends their
+//! reports together.
+
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+use super::{met_norway, metar, open_weather, DecodeMode, WeatherReport};
+use crate::cache::{Cache, Fetched};
+use crate::http;
+use crate::location::Location;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Provider {
+    OpenWeather,
+    MetNorway,
+    Metar,
+}
+
+impl Provider {
+    /// An identifier for use in configuration and file names, eg. `open_weather`.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::OpenWeather => "open_weather",
+            Self::MetNorway => "met_norway",
+            Self::Metar => "metar",
+        }
+    }
+
+    async fn call_api(&self, client: &http::Client, location: &Location) -> Result<String, String> {
+        match self {
+            Self::OpenWeather => open_weather::call_open_weather_api(client, location).await,
+            Self::MetNorway => met_norway::call_met_norway_api(client, location).await,
+            Self::Metar => metar::call_metar_api(client, location).await,
+        }
+    }
+
+    fn decode(
+        &self,
+        body: &str,
+        mode: DecodeMode,
+        location: &Location,
+    ) -> Result<WeatherReport, String> {
+        let tz_offset = time::UtcOffset::try_current_local_offset().unwrap_or(time::UtcOffset::UTC);
+
+        match self {
+            Self::OpenWeather => open_weather::decode(body, mode),
+            Self::MetNorway => met_norway::decode(body, mode, tz_offset),
+            Self::Metar => metar::decode(
+                body,
+                mode,
+                location,
+                time::OffsetDateTime::now_utc(),
+                tz_offset,
+            ),
+        }
+        .map(|decoded| decoded.report())
+        .map_err(|e| e.to_string())
+    }
+
+    fn cache_name(&self, location: &Location) -> String {
+        format!("{}_{}.json", self.id(), location.slug())
+    }
+
+    /// Fetch and decode a fresh report, caching the response if it's valid.
+    async fn fetch(
+        &self,
+        client: &http::Client,
+        cache: &Cache,
+        location: &Location,
+        mode: DecodeMode,
+    ) -> Option<Fetched<WeatherReport>> {
+        match self.call_api(client, location).await.and_then(|body| {
+            self.decode(&body, mode, location)
+                .map(|report| (body, Fetched::fresh(report)))
+        }) {
+            Ok((body, fetched)) => {
+                if let Err(e) = cache.store(
+                    &self.cache_name(location),
+                    body.as_bytes(),
+                    fetched.fetched_at,
+                ) {
+                    eprintln!(
+                        "Unable to cache {} response for {}: {}",
+                        self, location.name, e
+                    );
+                }
+                Some(fetched)
+            }
+            Err(e) => {
+                eprintln!("Unable to fetch {} for {}: {}", self, location.name, e);
+                None
+            }
+        }
+    }
+
+    /// Load the last good response from the cache.
+    fn load(
+        &self,
+        cache: &Cache,
+        location: &Location,
+        mode: DecodeMode,
+    ) -> Option<Fetched<WeatherReport>> {
+        let cached = cache.load(&self.cache_name(location)).ok()?;
+        let body = String::from_utf8(cached.value.clone()).ok()?;
+        let report = self.decode(&body, mode, location).ok()?;
+        Some(cached.map(|_| report))
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Self::OpenWeather => write!(f, "OpenWeather"),
+            Self::MetNorway => write!(f, "MET Norway"),
+            Self::Metar => write!(f, "METAR"),
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_lowercase().as_str() {
+            "open_weather" | "openweather" => Ok(Self::OpenWeather),
+            "met_norway" | "metno" | "met.no" => Ok(Self::MetNorway),
+            "metar" => Ok(Self::Metar),
+            _ => Err(format!("Unknown provider \"{}\".", raw)),
+        }
+    }
+}
+
+/// Tries providers in priority order, falling back to the cache if none of them respond.
+/// Observations and forecasts can be taken from different providers.
+pub struct Composite {
+    /// Providers for the current conditions, in priority order.
+    pub observation: Vec<Provider>,
+
+    /// Providers for the minutely, hourly and daily forecasts, in priority order.
+    pub forecast: Vec<Provider>,
+}
+
+impl Composite {
+    /// Read the providers from `WEATHER_PROVIDERS`, a comma-separated list which defaults to
+    /// `open_weather,met_norway`. `FORECAST_PROVIDERS` overrides the order for forecasts.
+    pub fn from_env() -> Self {
+        let providers = |key: &str| -> Option<Vec<Provider>> {
+            let providers: Vec<Provider> = env::var(key)
+                .ok()?
+                .split(',')
+                .filter_map(|provider| {
+                    provider
+                        .parse()
+                        .map_err(|e| eprintln!("Ignoring {}: {}", key, e))
+                        .ok()
+                })
+                .collect();
+
+            if providers.is_empty() {
+                None
+            } else {
+                Some(providers)
+            }
+        };
+
+        let observation = providers("WEATHER_PROVIDERS")
+            .unwrap_or_else(|| vec![Provider::OpenWeather, Provider::MetNorway]);
+
+        Self {
+            forecast: providers("FORECAST_PROVIDERS").unwrap_or_else(|| observation.clone()),
+            observation,
+        }
+    }
+
+    pub async fn fetch(
+        &self,
+        client: &http::Client,
+        cache: &Cache,
+        location: &Location,
+        mode: DecodeMode,
+    ) -> Option<Fetched<WeatherReport>> {
+        let mut attempts = Attempts {
+            client,
+            cache,
+            location,
+            mode,
+            reports: Vec::new(),
+        };
+
+        let observation = attempts.first_available(&self.observation).await;
+        let forecast = attempts.first_available(&self.forecast).await;
+
+        match (observation, forecast) {
+            (Some(observation), Some(forecast)) if observation != forecast => {
+                let mut report = attempts.take(observation)?;
+                let forecast = attempts.take(forecast)?;
+
+                report.value.minutely = forecast.value.minutely;
+                report.value.hourly = forecast.value.hourly;
+                report.value.daily = forecast.value.daily;
+                report.fetched_at = report.fetched_at.min(forecast.fetched_at);
+                report.stale |= forecast.stale;
+
+                Some(report)
+            }
+            (Some(source), _) | (None, Some(source)) => attempts.take(source),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Where a report came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Source {
+    provider: Provider,
+    cached: bool,
+}
+
+/// The reports requested so far while building a composite report, so that no provider is
+/// fetched twice.
+struct Attempts<'a> {
+    client: &'a http::Client,
+    cache: &'a Cache,
+    location: &'a Location,
+    mode: DecodeMode,
+    reports: Vec<(Source, Option<Fetched<WeatherReport>>)>,
+}
+
+impl Attempts<'_> {
+    /// The first provider with a fresh report or, failing that, the first with a cached one.
+    async fn first_available(&mut self, providers: &[Provider]) -> Option<Source> {
+        for &cached in &[false, true] {
+            for &provider in providers {
+                let source = Source { provider, cached };
+                if self.get(source).await {
+                    return Some(source);
+                }
+            }
+        }
+
+        None
+    }
+
+    async fn get(&mut self, source: Source) -> bool {
+        if let Some((_, report)) = self.reports.iter().find(|(s, _)| *s == source) {
+            return report.is_some();
+        }
+
+        let report = if source.cached {
+            source.provider.load(self.cache, self.location, self.mode)
+        } else {
+            source
+                .provider
+                .fetch(self.client, self.cache, self.location, self.mode)
+                .await
+        };
+
+        let available = report.is_some();
+        self.reports.push((source, report));
+        available
+    }
+
+    fn take(&mut self, source: Source) -> Option<Fetched<WeatherReport>> {
+        self.reports
+            .iter_mut()
+            .find(|(s, _)| *s == source)
+            .and_then(|(_, report)| report.take())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_str_test() {
+        assert_eq!(Ok(Provider::OpenWeather), "open_weather".parse());
+        assert_eq!(Ok(Provider::MetNorway), " MetNo".parse());
+        assert!("accuweather".parse::<Provider>().is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_fallback_test() {
+        let dir = env::temp_dir().join(format!("weathervane-provider-test-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let location = Location {
+            name: "Home".to_string(),
+            latitude: 45.5,
+            longitude: -73.6,
+            timezone: None,
+        };
+        let fetched_at = time::OffsetDateTime::from_unix_timestamp(1_608_134_400);
+
+        cache
+            .store(
+                "open_weather_home.json",
+                include_bytes!("../../tests/fixtures/open_weather/onecall.json"),
+                fetched_at,
+            )
+            .unwrap();
+        cache
+            .store(
+                "met_norway_home.json",
+                include_bytes!("../../tests/fixtures/met_norway/compact.json"),
+                fetched_at,
+            )
+            .unwrap();
+
+        let mut attempts = Attempts {
+            client: &http::Client::new(Default::default()),
+            cache: &cache,
+            location: &location,
+            mode: DecodeMode::Strict,
+            // Pretend that both providers have already failed to respond.
+            reports: vec![
+                (
+                    Source {
+                        provider: Provider::OpenWeather,
+                        cached: false,
+                    },
+                    None,
+                ),
+                (
+                    Source {
+                        provider: Provider::MetNorway,
+                        cached: false,
+                    },
+                    None,
+                ),
+            ],
+        };
+
+        let source = attempts
+            .first_available(&[Provider::MetNorway, Provider::OpenWeather])
+            .await
+            .unwrap();
+        assert_eq!(Provider::MetNorway, source.provider);
+        assert!(source.cached);
+
+        let report = attempts.take(source).unwrap();
+        assert!(report.stale);
+        assert_eq!(fetched_at, report.fetched_at);
+        assert_eq!(Provider::MetNorway, report.value.current.provider);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! Environment Canada's weather radar network.
+
+use std::env;
+
+use crate::http;
+
+pub struct RadarStation {
+    /// The station ID used in MSC Datamart paths, eg. `CASBV`.
+    pub id: &'static str,
+    pub name: &'static str,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// The stations of the national radar network, with approximate coordinates.
+pub const STATIONS: &[RadarStation] = &[
+    station("CASAG", "Aldergrove", 49.02, -122.49),
+    station("CASBE", "Bethune", 50.57, -105.18),
+    station("CASBI", "Britt", 45.79, -80.53),
+    station("CASBV", "Blainville", 45.71, -73.86),
+    station("CASCL", "Carvel", 53.56, -114.14),
+    station("CASCM", "Chipman", 46.22, -65.70),
+    station("CASDR", "Dryden", 49.86, -92.80),
+    station("CASET", "Exeter", 43.37, -81.38),
+    station("CASFM", "Foxwarren", 50.55, -101.09),
+    station("CASFT", "Franktown", 45.04, -76.12),
+    station("CASGO", "Gore", 45.10, -63.70),
+    station("CASHP", "Holyrood", 47.33, -53.17),
+    station("CASKR", "King City", 43.96, -79.57),
+    station("CASLA", "Landrienne", 48.55, -77.81),
+    station("CASMA", "Mont Apica", 47.90, -71.40),
+    station("CASMB", "Marion Bridge", 45.95, -60.21),
+    station("CASMM", "Marble Mountain", 48.93, -57.83),
+    station("CASMR", "Montreal River", 47.25, -84.60),
+    station("CASPG", "Prince George", 53.61, -122.95),
+    station("CASRA", "Radisson", 52.52, -107.44),
+    station("CASSF", "Strathmore", 51.21, -113.40),
+    station("CASSI", "Mount Sicker", 48.86, -123.76),
+    station("CASSM", "Smooth Rock Falls", 49.28, -81.79),
+    station("CASSR", "Silver Star", 50.37, -119.06),
+    station("CASSU", "Schuler", 50.31, -110.20),
+    station("CASVD", "Val d'Irène", 48.48, -67.60),
+    station("CASVL", "Villeroy", 46.45, -71.91),
+    station("CASWL", "Woodlands", 50.15, -97.78),
+];
+
+const fn station(
+    id: &'static str,
+    name: &'static str,
+    latitude: f64,
+    longitude: f64,
+) -> RadarStation {
+    RadarStation {
+        id,
+        name,
+        latitude,
+        longitude,
+    }
+}
+
+/// The station closest to a point.
+pub fn nearest_station(latitude: f64, longitude: f64) -> &'static RadarStation {
+    STATIONS
+        .iter()
+        .min_by(|a, b| {
+            a.distance_km(latitude, longitude)
+                .partial_cmp(&b.distance_km(latitude, longitude))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap()
+}
+
+impl RadarStation {
+    /// Great-circle distance to a point.
+    pub fn distance_km(&self, latitude: f64, longitude: f64) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.;
+
+        let (lat1, lat2) = (self.latitude.to_radians(), latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (longitude - self.longitude).to_radians();
+
+        let a = (d_lat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.).sin().powi(2);
+        2. * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+/// The station given by `ENVIRONMENT_CANADA_RADAR_ID`, or else the one nearest to a point.
+pub fn station_id(coordinates: Option<(f64, f64)>) -> String {
+    env::var("ENVIRONMENT_CANADA_RADAR_ID").unwrap_or_else(|_| {
+        coordinates
+            .map(|(latitude, longitude)| nearest_station(latitude, longitude).id)
+            .unwrap_or("CASBV")
+            .to_string()
+    })
+}
+
+/// Map layers drawn with a station's radar image: rivers underneath and town names on top.
+pub struct Overlays {
+    pub rivers: &'static [u8],
+    pub towns: &'static [u8],
+}
+
+/// The overlays for a station, if there are any. They're traced from the station's own maps, so
+/// they don't line up with any other station's.
+pub fn overlays(station_id: &str) -> Option<Overlays> {
+    match station_id {
+        "CASBV" => Some(Overlays {
+            rivers: include_bytes!("../../images/radar-rivers.gif"),
+            towns: include_bytes!("../../images/radar-towns.gif"),
+        }),
+        _ => None,
+    }
+}
+
+pub async fn get_weather_radar(client: &http::Client, station_id: &str) -> Result<Vec<u8>, String> {
+    let mut url = format!(
+        "https://dd.weather.gc.ca/radar/PRECIPET/GIF/{}/",
+        station_id
+    );
+
+    let page = client.get_text(&url).await?;
+
+    let search_str = "<img src=\"/icons/image2.gif\" alt=\"[IMG]\"> <a href=\"";
+
+    if let Some(filename) = page.lines().rev().find_map(|s| {
+        if s.starts_with(search_str) && s.contains("RAIN_A11Y.gif") {
+            if let Some(end) = s[search_str.len()..].find('"') {
+                return Some(&s[search_str.len()..search_str.len() + end]);
+            }
+        }
+        None
+    }) {
+        url.push_str(filename);
+        return client.get_bytes(&url).await;
+    }
+
+    Err(format!("Failed to parse radar URL: {}", url))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overlays_test() {
+        assert!(overlays("CASBV").is_some());
+        // Montreal's rivers and towns would be drawn in the wrong place over Toronto.
+        assert!(overlays("CASKR").is_none());
+    }
+
+    #[test]
+    fn nearest_station_test() {
+        // Montreal
+        assert_eq!("CASBV", nearest_station(45.5, -73.6).id);
+        // Ottawa
+        assert_eq!("CASFT", nearest_station(45.42, -75.70).id);
+        // Toronto
+        assert_eq!("CASKR", nearest_station(43.65, -79.38).id);
+        // Vancouver
+        assert_eq!("CASAG", nearest_station(49.28, -123.12).id);
+        // Halifax
+        assert_eq!("CASGO", nearest_station(44.65, -63.58).id);
+    }
+
+    #[test]
+    fn distance_test() {
+        let blainville = STATIONS.iter().find(|s| s.id == "CASBV").unwrap();
+
+        assert!(blainville.distance_km(45.71, -73.86) < 0.001);
+        // Montreal is about 30 km away.
+        assert!((blainville.distance_km(45.5, -73.6) - 30.).abs() < 5.);
+    }
+}